@@ -2,5 +2,17 @@
 
 pub mod prelude;
 
+pub mod canvas_artist_lists;
+pub mod canvas_finalisations;
+pub mod canvases;
 pub mod chain_id;
+pub mod contribution_timeouts;
+pub mod contributions;
+pub mod funds_recipient_changes;
 pub mod last_processed_version;
+pub mod ownership_changes;
+pub mod payments;
+pub mod pixel_history;
+pub mod pixel_writers;
+pub mod processor_status;
+pub mod quarantined_transactions;
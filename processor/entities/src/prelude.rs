@@ -1,5 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.1
 
 pub use super::{
-    chain_id::Entity as ChainId, last_processed_version::Entity as LastProcessedVersion,
+    canvas_artist_lists::Entity as CanvasArtistLists,
+    canvas_finalisations::Entity as CanvasFinalisations, canvases::Entity as Canvases,
+    chain_id::Entity as ChainId, contribution_timeouts::Entity as ContributionTimeouts,
+    contributions::Entity as Contributions,
+    funds_recipient_changes::Entity as FundsRecipientChanges,
+    last_processed_version::Entity as LastProcessedVersion,
+    ownership_changes::Entity as OwnershipChanges, payments::Entity as Payments,
+    pixel_history::Entity as PixelHistory, pixel_writers::Entity as PixelWriters,
+    processor_status::Entity as ProcessorStatus,
+    quarantined_transactions::Entity as QuarantinedTransactions,
 };
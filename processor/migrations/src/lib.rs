@@ -1,12 +1,42 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20230901_000001_create_canvases_table;
+mod m20230915_000001_create_quarantined_transactions_table;
+mod m20230920_000001_create_processor_status_table;
+mod m20230925_000001_add_cost_to_canvases;
+mod m20230925_000002_create_contributions_tables;
+mod m20231001_000001_create_pixel_history_table;
+mod m20231005_000001_create_canvas_artist_lists_table;
+mod m20231010_000001_add_config_to_canvases;
+mod m20231010_000002_create_contribution_timeouts_table;
+mod m20231015_000001_add_funds_recipient_to_canvases;
+mod m20231015_000002_create_payments_tables;
+mod m20231020_000001_add_owner_to_canvases;
+mod m20231020_000002_create_ownership_changes_table;
+mod m20231025_000001_create_canvas_finalisations_table;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20230901_000001_create_canvases_table::Migration),
+            Box::new(m20230915_000001_create_quarantined_transactions_table::Migration),
+            Box::new(m20230920_000001_create_processor_status_table::Migration),
+            Box::new(m20230925_000001_add_cost_to_canvases::Migration),
+            Box::new(m20230925_000002_create_contributions_tables::Migration),
+            Box::new(m20231001_000001_create_pixel_history_table::Migration),
+            Box::new(m20231005_000001_create_canvas_artist_lists_table::Migration),
+            Box::new(m20231010_000001_add_config_to_canvases::Migration),
+            Box::new(m20231010_000002_create_contribution_timeouts_table::Migration),
+            Box::new(m20231015_000001_add_funds_recipient_to_canvases::Migration),
+            Box::new(m20231015_000002_create_payments_tables::Migration),
+            Box::new(m20231020_000001_add_owner_to_canvases::Migration),
+            Box::new(m20231020_000002_create_ownership_changes_table::Migration),
+            Box::new(m20231025_000001_create_canvas_finalisations_table::Migration),
+        ]
     }
 }
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the canvases table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Canvases::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Canvases::Address)
+                            .string()
+                            .primary_key()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Canvases::Deployment).string().not_null())
+                    .col(ColumnDef::new(Canvases::ContractAddress).string().not_null())
+                    .col(ColumnDef::new(Canvases::Width).big_integer().not_null())
+                    .col(ColumnDef::new(Canvases::Height).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Canvases::CreatedVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // We often look up the canvases for a deployment.
+        manager
+            .create_index(
+                Index::create()
+                    .name("canvases_deployment_idx")
+                    .table(Canvases::Table)
+                    .col(Canvases::Deployment)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Canvases::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Canvases {
+    Table,
+    Address,
+    Deployment,
+    ContractAddress,
+    Width,
+    Height,
+    CreatedVersion,
+}
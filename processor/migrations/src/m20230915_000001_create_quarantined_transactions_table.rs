@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the quarantined_transactions table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(QuarantinedTransactions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(QuarantinedTransactions::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedTransactions::Deployment)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedTransactions::Changes)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedTransactions::Error)
+                            .text()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(QuarantinedTransactions::Version)
+                            .col(QuarantinedTransactions::Deployment),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(QuarantinedTransactions::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum QuarantinedTransactions {
+    Table,
+    Version,
+    Deployment,
+    Changes,
+    Error,
+}
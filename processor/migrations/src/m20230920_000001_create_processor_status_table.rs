@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the processor_status table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProcessorStatus::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ProcessorStatus::ProcessorName)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ProcessorStatus::ProcessedVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ProcessorStatus::LastTransactionTimestamp).big_integer())
+                    .col(
+                        ColumnDef::new(ProcessorStatus::LatestStreamVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ProcessorStatus::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ProcessorStatus {
+    Table,
+    ProcessorName,
+    ProcessedVersion,
+    LastTransactionTimestamp,
+    LatestStreamVersion,
+}
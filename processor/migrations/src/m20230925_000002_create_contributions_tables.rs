@@ -0,0 +1,131 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the contributions table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Contributions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Contributions::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Contributions::ArtistAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Contributions::PixelsDrawn)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Contributions::FirstContributionVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Contributions::FirstContributionTimestamp).big_integer())
+                    .col(
+                        ColumnDef::new(Contributions::LastContributionVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Contributions::LastContributionTimestamp).big_integer())
+                    .col(
+                        ColumnDef::new(Contributions::OctasSpent)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(Contributions::CanvasAddress)
+                            .col(Contributions::ArtistAddress),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create the pixel_writers table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(PixelWriters::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PixelWriters::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PixelWriters::PixelIndex)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PixelWriters::ArtistAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PixelWriters::Version).big_integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(PixelWriters::CanvasAddress)
+                            .col(PixelWriters::PixelIndex),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // We count the pixels each artist has on a canvas for the leaderboards.
+        manager
+            .create_index(
+                Index::create()
+                    .name("pixel_writers_canvas_address_artist_address_idx")
+                    .table(PixelWriters::Table)
+                    .col(PixelWriters::CanvasAddress)
+                    .col(PixelWriters::ArtistAddress)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PixelWriters::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Contributions::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Contributions {
+    Table,
+    CanvasAddress,
+    ArtistAddress,
+    PixelsDrawn,
+    FirstContributionVersion,
+    FirstContributionTimestamp,
+    LastContributionVersion,
+    LastContributionTimestamp,
+    OctasSpent,
+}
+
+#[derive(DeriveIden)]
+enum PixelWriters {
+    Table,
+    CanvasAddress,
+    PixelIndex,
+    ArtistAddress,
+    Version,
+}
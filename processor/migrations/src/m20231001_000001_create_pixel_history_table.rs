@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The primary key doubles as the index we use to look up the history of a
+        // single pixel, newest first.
+        manager
+            .create_table(
+                Table::create()
+                    .table(PixelHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PixelHistory::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PixelHistory::PixelIndex)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PixelHistory::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PixelHistory::ArtistAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PixelHistory::R).small_integer().not_null())
+                    .col(ColumnDef::new(PixelHistory::G).small_integer().not_null())
+                    .col(ColumnDef::new(PixelHistory::B).small_integer().not_null())
+                    .col(
+                        ColumnDef::new(PixelHistory::TransactionHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PixelHistory::Timestamp).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(PixelHistory::CanvasAddress)
+                            .col(PixelHistory::PixelIndex)
+                            .col(PixelHistory::Version),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PixelHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum PixelHistory {
+    Table,
+    CanvasAddress,
+    PixelIndex,
+    Version,
+    ArtistAddress,
+    R,
+    G,
+    B,
+    TransactionHash,
+    Timestamp,
+}
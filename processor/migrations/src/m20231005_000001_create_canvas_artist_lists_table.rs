@@ -0,0 +1,115 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the canvas_artist_lists table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(CanvasArtistLists::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CanvasArtistLists::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CanvasArtistLists::List).string().not_null())
+                    .col(
+                        ColumnDef::new(CanvasArtistLists::ArtistAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(CanvasArtistLists::CanvasAddress)
+                            .col(CanvasArtistLists::List)
+                            .col(CanvasArtistLists::ArtistAddress),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // We look up which lists an artist is on for their profile.
+        manager
+            .create_index(
+                Index::create()
+                    .name("canvas_artist_lists_artist_address_idx")
+                    .table(CanvasArtistLists::Table)
+                    .col(CanvasArtistLists::ArtistAddress)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        // We list the pixels an artist drew and count the ones still visible for
+        // their profile.
+        manager
+            .create_index(
+                Index::create()
+                    .name("pixel_history_artist_address_version_idx")
+                    .table(PixelHistory::Table)
+                    .col(PixelHistory::ArtistAddress)
+                    .col(PixelHistory::Version)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("pixel_writers_artist_address_idx")
+                    .table(PixelWriters::Table)
+                    .col(PixelWriters::ArtistAddress)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("pixel_writers_artist_address_idx")
+                    .table(PixelWriters::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("pixel_history_artist_address_version_idx")
+                    .table(PixelHistory::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(CanvasArtistLists::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CanvasArtistLists {
+    Table,
+    CanvasAddress,
+    List,
+    ArtistAddress,
+}
+
+#[derive(DeriveIden)]
+enum PixelHistory {
+    Table,
+    ArtistAddress,
+    Version,
+}
+
+#[derive(DeriveIden)]
+enum PixelWriters {
+    Table,
+    ArtistAddress,
+}
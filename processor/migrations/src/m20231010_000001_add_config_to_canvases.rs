@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Canvases created before this was added are recorded as having no timeout
+        // and never closing until the processor sees their Canvas resource again.
+        // SQLite only supports one change per ALTER TABLE.
+        for column in [
+            Canvases::PerAccountTimeoutS,
+            Canvases::CanDrawForS,
+            Canvases::CreatedAtS,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Canvases::Table)
+                        .add_column(ColumnDef::new(column).big_integer().not_null().default(0))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Canvases::PerAccountTimeoutS,
+            Canvases::CanDrawForS,
+            Canvases::CreatedAtS,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Canvases::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Canvases {
+    Table,
+    PerAccountTimeoutS,
+    CanDrawForS,
+    CreatedAtS,
+}
@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A mirror of the `last_contribution_s` table of each canvas.
+        manager
+            .create_table(
+                Table::create()
+                    .table(ContributionTimeouts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ContributionTimeouts::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ContributionTimeouts::ArtistAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ContributionTimeouts::LastContributionS)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ContributionTimeouts::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ContributionTimeouts::CanvasAddress)
+                            .col(ContributionTimeouts::ArtistAddress),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ContributionTimeouts::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ContributionTimeouts {
+    Table,
+    CanvasAddress,
+    ArtistAddress,
+    LastContributionS,
+    Version,
+}
@@ -0,0 +1,108 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create the payments table. A draw makes at most one payment.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Payments::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Payments::CanvasAddress).string().not_null())
+                    .col(ColumnDef::new(Payments::Version).big_integer().not_null())
+                    .col(ColumnDef::new(Payments::ArtistAddress).string().not_null())
+                    .col(
+                        ColumnDef::new(Payments::RecipientAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Payments::Amount).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Payments::TransactionHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Payments::Timestamp).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(Payments::CanvasAddress)
+                            .col(Payments::Version),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // We sum up the payments to each recipient.
+        manager
+            .create_index(
+                Index::create()
+                    .name("payments_recipient_address_idx")
+                    .table(Payments::Table)
+                    .col(Payments::RecipientAddress)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create the funds_recipient_changes table.
+        manager
+            .create_table(
+                Table::create()
+                    .table(FundsRecipientChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FundsRecipientChanges::CanvasAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(FundsRecipientChanges::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(FundsRecipientChanges::FundsRecipient).string())
+                    .col(ColumnDef::new(FundsRecipientChanges::Timestamp).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(FundsRecipientChanges::CanvasAddress)
+                            .col(FundsRecipientChanges::Version),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FundsRecipientChanges::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Payments::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Payments {
+    Table,
+    CanvasAddress,
+    Version,
+    ArtistAddress,
+    RecipientAddress,
+    Amount,
+    TransactionHash,
+    Timestamp,
+}
+
+#[derive(DeriveIden)]
+enum FundsRecipientChanges {
+    Table,
+    CanvasAddress,
+    Version,
+    FundsRecipient,
+    Timestamp,
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The owner of canvases created before this was added is unknown until they
+        // are next transferred. SQLite only supports one change per ALTER TABLE.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Canvases::Table)
+                    .add_column(ColumnDef::new(Canvases::OwnerAddress).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Canvases::Table)
+                    .add_column(
+                        ColumnDef::new(Canvases::OwnerIsSuperAdmin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // We list the canvases an account owns.
+        manager
+            .create_index(
+                Index::create()
+                    .name("canvases_owner_address_idx")
+                    .table(Canvases::Table)
+                    .col(Canvases::OwnerAddress)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("canvases_owner_address_idx")
+                    .table(Canvases::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [Canvases::OwnerIsSuperAdmin, Canvases::OwnerAddress] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Canvases::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Canvases {
+    Table,
+    OwnerAddress,
+    OwnerIsSuperAdmin,
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The owners of canvases and of the collection over time. The primary key
+        // doubles as the index we use to look up the history of a single object.
+        manager
+            .create_table(
+                Table::create()
+                    .table(OwnershipChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OwnershipChanges::ObjectAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OwnershipChanges::Version)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OwnershipChanges::Kind).string().not_null())
+                    .col(
+                        ColumnDef::new(OwnershipChanges::OwnerAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OwnershipChanges::TransactionHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OwnershipChanges::Timestamp).big_integer())
+                    .primary_key(
+                        Index::create()
+                            .col(OwnershipChanges::ObjectAddress)
+                            .col(OwnershipChanges::Version),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OwnershipChanges::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum OwnershipChanges {
+    Table,
+    ObjectAddress,
+    Version,
+    Kind,
+    OwnerAddress,
+    TransactionHash,
+    Timestamp,
+}
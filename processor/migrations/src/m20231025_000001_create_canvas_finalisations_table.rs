@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The final image of each canvas that has closed. These are written once and
+        // never changed.
+        manager
+            .create_table(
+                Table::create()
+                    .table(CanvasFinalisations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CanvasFinalisations::CanvasAddress)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CanvasFinalisations::ClosedAtS)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CanvasFinalisations::AppliedVersion)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CanvasFinalisations::Png).binary().not_null())
+                    .col(
+                        ColumnDef::new(CanvasFinalisations::ContentHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CanvasFinalisations::FinalisedAtS)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CanvasFinalisations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CanvasFinalisations {
+    Table,
+    CanvasAddress,
+    ClosedAtS,
+    AppliedVersion,
+    Png,
+    ContentHash,
+    FinalisedAtS,
+}
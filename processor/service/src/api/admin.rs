@@ -0,0 +1,87 @@
+//! Endpoints for operators of the processor rather than users of the canvas. These
+//! are only served if `admin_token` is set in the API config and the processor runs
+//! alongside the API, and every request must include it as a bearer token.
+
+use crate::db_storage::DatabaseStorage;
+use entities::quarantined_transactions;
+use poem::{
+    delete,
+    error::NotFoundError,
+    get, handler,
+    http::{header::AUTHORIZATION, StatusCode},
+    web::{Data, Json, Path, Query},
+    Endpoint, EndpointExt, IntoResponse, Response, Route,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Build the admin routes, which only accept requests with `admin_token`.
+pub fn build_admin_routes(admin_token: String) -> impl Endpoint {
+    // Compare digests of the header rather than the header itself, so how long the
+    // comparison takes doesn't give away how much of the token was right.
+    let expected = Sha256::digest(format!("Bearer {}", admin_token));
+    Route::new()
+        .at("/quarantine", get(get_quarantine))
+        .at(
+            "/quarantine/:deployment/:version",
+            delete(delete_quarantined_transaction),
+        )
+        .around(move |endpoint, request| {
+            let authorized = request
+                .headers()
+                .get(AUTHORIZATION)
+                .is_some_and(|value| Sha256::digest(value.as_bytes()) == expected);
+            async move {
+                if !authorized {
+                    return Ok(StatusCode::UNAUTHORIZED.into_response());
+                }
+                endpoint
+                    .call(request)
+                    .await
+                    .map(IntoResponse::into_response)
+            }
+        })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetQuarantineParams {
+    /// Only return txns quarantined for the deployment with this label.
+    deployment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Quarantine {
+    count: usize,
+    transactions: Vec<quarantined_transactions::Model>,
+}
+
+/// List the txns that failed to decode, in version order.
+#[handler]
+async fn get_quarantine(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Query(params): Query<GetQuarantineParams>,
+) -> poem::Result<Json<Quarantine>> {
+    let transactions = storage
+        .read_quarantined_transactions(params.deployment.as_deref())
+        .await?;
+    Ok(Json(Quarantine {
+        count: transactions.len(),
+        transactions,
+    }))
+}
+
+/// Remove a txn from the quarantine, e.g. once it has been replayed with a backfill.
+#[handler]
+async fn delete_quarantined_transaction(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path((deployment, version)): Path<(String, u64)>,
+) -> poem::Result<Response> {
+    if !storage
+        .delete_quarantined_transaction(version, &deployment)
+        .await?
+    {
+        return Err(NotFoundError.into());
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
@@ -0,0 +1,181 @@
+//! Everything an artist has done across every canvas.
+
+use crate::{
+    db_storage::{ArtistPermission, DatabaseStorage},
+    generated::Color,
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use poem::{
+    handler,
+    web::{Data, Json, Path, Query},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ArtistPixelsParams {
+    /// How many pixels to return, at most `MAX_LIMIT`.
+    limit: Option<u64>,
+    /// How many pixels to skip.
+    #[serde(default)]
+    offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtistProfile {
+    artist_address: String,
+    /// How many canvases the artist has drawn on.
+    canvases_contributed: u64,
+    pixels_drawn: i64,
+    /// How many of the pixels currently showing the artist drew, across every canvas.
+    pixels_visible: i64,
+    octas_spent: i64,
+    first_contribution_version: Option<i64>,
+    first_contribution_timestamp: Option<i64>,
+    last_contribution_version: Option<i64>,
+    last_contribution_timestamp: Option<i64>,
+    /// The canvases the artist has drawn on, in the order they first drew on them.
+    canvases: Vec<ArtistCanvas>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtistCanvas {
+    canvas_address: String,
+    pixels_drawn: i64,
+    pixels_visible: i64,
+    octas_spent: i64,
+    first_contribution_version: i64,
+    first_contribution_timestamp: Option<i64>,
+    last_contribution_version: i64,
+    last_contribution_timestamp: Option<i64>,
+    /// Whether the artist may currently draw on the canvas, based on its allowlist
+    /// and blocklist.
+    permission: ArtistPermission,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtistPixels {
+    /// How many pixels the artist has drawn in total, across all pages.
+    total: u64,
+    entries: Vec<ArtistPixel>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtistPixel {
+    canvas_address: String,
+    x: u64,
+    y: u64,
+    color: Color,
+    version: i64,
+    transaction_hash: String,
+    /// The timestamp of the txn in seconds.
+    timestamp: Option<i64>,
+}
+
+/// The canvases an artist has drawn on, how much they've drawn, and whether they
+/// may still draw on them.
+#[handler]
+pub async fn get_artist(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<ArtistProfile>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let contributions = storage.read_artist_contributions(&address).await?;
+    let visible_pixels = storage.count_artist_visible_pixels(&address).await?;
+    let canvas_addresses: Vec<String> = contributions
+        .iter()
+        .map(|contribution| contribution.canvas_address.clone())
+        .collect();
+    let permissions = storage
+        .read_artist_permissions(&address, &canvas_addresses)
+        .await?;
+
+    let canvases: Vec<ArtistCanvas> = contributions
+        .into_iter()
+        .map(|contribution| ArtistCanvas {
+            pixels_visible: visible_pixels
+                .get(&contribution.canvas_address)
+                .copied()
+                .unwrap_or(0),
+            permission: permissions[&contribution.canvas_address],
+            canvas_address: contribution.canvas_address,
+            pixels_drawn: contribution.pixels_drawn,
+            octas_spent: contribution.octas_spent,
+            first_contribution_version: contribution.first_contribution_version,
+            first_contribution_timestamp: contribution.first_contribution_timestamp,
+            last_contribution_version: contribution.last_contribution_version,
+            last_contribution_timestamp: contribution.last_contribution_timestamp,
+        })
+        .collect();
+    let first = canvases
+        .iter()
+        .min_by_key(|canvas| canvas.first_contribution_version);
+    let last = canvases
+        .iter()
+        .max_by_key(|canvas| canvas.last_contribution_version);
+    Ok(Json(ArtistProfile {
+        artist_address: address,
+        canvases_contributed: canvases.len() as u64,
+        pixels_drawn: canvases.iter().map(|canvas| canvas.pixels_drawn).sum(),
+        pixels_visible: canvases.iter().map(|canvas| canvas.pixels_visible).sum(),
+        octas_spent: canvases.iter().map(|canvas| canvas.octas_spent).sum(),
+        first_contribution_version: first.map(|canvas| canvas.first_contribution_version),
+        first_contribution_timestamp: first.and_then(|canvas| canvas.first_contribution_timestamp),
+        last_contribution_version: last.map(|canvas| canvas.last_contribution_version),
+        last_contribution_timestamp: last.and_then(|canvas| canvas.last_contribution_timestamp),
+        canvases,
+    }))
+}
+
+/// The pixels an artist has drawn across every canvas, newest first.
+#[handler]
+pub async fn get_artist_pixels(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+    Query(params): Query<ArtistPixelsParams>,
+) -> poem::Result<Json<ArtistPixels>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let (total, pixel_history) = storage
+        .read_artist_pixel_history(&address, limit, params.offset)
+        .await?;
+
+    // We need the width of each canvas to turn the pixel indices into coordinates.
+    let mut canvas_widths = HashMap::new();
+    for pixel_write in &pixel_history {
+        if canvas_widths.contains_key(&pixel_write.canvas_address) {
+            continue;
+        }
+        let canvas = storage
+            .read_canvas(&pixel_write.canvas_address)
+            .await?
+            .with_context(|| format!("Unknown canvas {}", pixel_write.canvas_address))?;
+        canvas_widths.insert(canvas.address, canvas.width as u64);
+    }
+
+    let entries = pixel_history
+        .into_iter()
+        .map(|pixel_write| {
+            let width = canvas_widths[&pixel_write.canvas_address];
+            ArtistPixel {
+                x: pixel_write.pixel_index as u64 % width,
+                y: pixel_write.pixel_index as u64 / width,
+                canvas_address: pixel_write.canvas_address,
+                color: Color {
+                    r: pixel_write.r as u8,
+                    g: pixel_write.g as u8,
+                    b: pixel_write.b as u8,
+                },
+                version: pixel_write.version,
+                transaction_hash: pixel_write.transaction_hash,
+                timestamp: pixel_write.timestamp,
+            }
+        })
+        .collect();
+    Ok(Json(ArtistPixels { total, entries }))
+}
@@ -0,0 +1,49 @@
+use crate::{db_storage::DatabaseStorage, processor::address_to_string};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use entities::canvases;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path, Query},
+};
+use serde::Deserialize;
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Deserialize)]
+pub struct GetCanvasesParams {
+    /// Only return canvases from the deployment with this label.
+    deployment: Option<String>,
+    /// Only return canvases currently owned by this account.
+    owner: Option<String>,
+}
+
+/// List all the canvases, in the order they were created.
+#[handler]
+pub async fn get_canvases(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Query(params): Query<GetCanvasesParams>,
+) -> poem::Result<Json<Vec<canvases::Model>>> {
+    let owner = params
+        .owner
+        .map(|owner| Address::from_str(&owner).context("Invalid owner"))
+        .transpose()?
+        .map(|owner| address_to_string(&owner));
+    let canvases = storage
+        .read_canvases(params.deployment.as_deref(), owner.as_deref())
+        .await?;
+    Ok(Json(canvases))
+}
+
+#[handler]
+pub async fn get_canvas(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<canvases::Model>> {
+    let address = Address::from_str(&address).context("Invalid address")?;
+    let canvas = storage
+        .read_canvas(&address_to_string(&address))
+        .await?
+        .ok_or(NotFoundError)?;
+    Ok(Json(canvas))
+}
@@ -0,0 +1,127 @@
+//! Whether an artist can draw on a canvas right now, and if not, when they can.
+
+use crate::{
+    db_storage::{ArtistPermission, DatabaseStorage},
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path},
+};
+use serde::Serialize;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Why `draw` would fail for the artist, mirroring the checks in the contract.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IneligibilityReason {
+    InBlocklist,
+    NotInAllowlist,
+    /// The canvas is past its `can_draw_for_s` deadline.
+    CanvasClosed,
+    /// The artist drew too recently given the canvas' `per_account_timeout_s`.
+    MustWait,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Eligibility {
+    canvas_address: String,
+    artist_address: String,
+    can_draw: bool,
+    /// Empty if the artist can draw.
+    reasons: Vec<IneligibilityReason>,
+    /// The earliest time the artist can draw, which is `now_s` if they can draw
+    /// now. This is null if they can't draw again unless the lists change, or if
+    /// the canvas closes before their timeout ends.
+    next_draw_at_s: Option<i64>,
+    /// The time this was worked out for.
+    now_s: i64,
+    /// When the artist last drew as far as the timeout is concerned. This is null
+    /// if they haven't drawn since the timeouts were last cleared.
+    last_contribution_s: Option<i64>,
+    per_account_timeout_s: i64,
+    /// The last time anyone can draw, null if the canvas never closes.
+    closes_at_s: Option<i64>,
+    /// How much drawing a pixel costs in OCTA. The processor can't tell whether the
+    /// artist can afford it.
+    cost: i64,
+}
+
+/// Work out whether an artist can draw on a canvas. This uses the wall clock of
+/// the API in place of the on chain time, and is only as up to date as the
+/// processor.
+#[handler]
+pub async fn get_eligibility(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path((address, artist)): Path<(String, String)>,
+) -> poem::Result<Json<Eligibility>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let artist = address_to_string(&Address::from_str(&artist).context("Invalid artist")?);
+    let canvas = storage.read_canvas(&address).await?.ok_or(NotFoundError)?;
+    let permission = storage
+        .read_artist_permissions(&artist, std::slice::from_ref(&address))
+        .await?[&address];
+    let last_contribution_s = if canvas.per_account_timeout_s > 0 {
+        storage
+            .read_contribution_timeout(&address, &artist)
+            .await?
+            .map(|contribution_timeout| contribution_timeout.last_contribution_s)
+    } else {
+        None
+    };
+    let now_s = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System time is before the epoch")?
+        .as_secs() as i64;
+
+    let mut reasons = Vec::new();
+    match permission {
+        ArtistPermission::Allowed => {},
+        ArtistPermission::InBlocklist => reasons.push(IneligibilityReason::InBlocklist),
+        ArtistPermission::NotInAllowlist => reasons.push(IneligibilityReason::NotInAllowlist),
+    }
+    let closes_at_s =
+        (canvas.can_draw_for_s > 0).then_some(canvas.created_at_s + canvas.can_draw_for_s);
+    let closed = closes_at_s.is_some_and(|closes_at_s| now_s > closes_at_s);
+    if closed {
+        reasons.push(IneligibilityReason::CanvasClosed);
+    }
+    // The contract requires now > last + timeout.
+    let timeout_ends_at_s = last_contribution_s
+        .map(|last_contribution_s| last_contribution_s + canvas.per_account_timeout_s + 1);
+    let must_wait = timeout_ends_at_s.is_some_and(|timeout_ends_at_s| now_s < timeout_ends_at_s);
+    if must_wait {
+        reasons.push(IneligibilityReason::MustWait);
+    }
+
+    let next_draw_at_s = if permission != ArtistPermission::Allowed || closed {
+        None
+    } else {
+        let next_draw_at_s =
+            timeout_ends_at_s.map_or(now_s, |timeout_ends_at_s| timeout_ends_at_s.max(now_s));
+        match closes_at_s {
+            Some(closes_at_s) if next_draw_at_s > closes_at_s => None,
+            _ => Some(next_draw_at_s),
+        }
+    };
+
+    Ok(Json(Eligibility {
+        canvas_address: address,
+        artist_address: artist,
+        can_draw: reasons.is_empty(),
+        reasons,
+        next_draw_at_s,
+        now_s,
+        last_contribution_s,
+        per_account_timeout_s: canvas.per_account_timeout_s,
+        closes_at_s,
+        cost: canvas.cost,
+    }))
+}
@@ -0,0 +1,177 @@
+//! Endpoints for load balancers and process supervisors. `/healthz` just says the
+//! process is up, `/readyz` says whether this node is fit to serve, i.e. the
+//! processor is keeping up with the chain and the storage it needs is usable.
+
+use crate::{db_storage::DatabaseStorage, processor::CANVAS_PROCESSOR_NAME};
+use anyhow::{Context, Result};
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json},
+    IntoResponse, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReadinessConfig {
+    /// We're not ready if the last txn the processor processed is older than this.
+    /// Since every block has a block metadata txn this keeps moving even if nobody
+    /// is drawing.
+    #[serde(default = "ReadinessConfig::default_max_lag_secs")]
+    pub max_lag_secs: u64,
+
+    /// We're not ready if the processor is more than this many versions behind the
+    /// last version it received from the txn stream.
+    #[serde(default = "ReadinessConfig::default_max_version_lag")]
+    pub max_version_lag: u64,
+}
+
+impl ReadinessConfig {
+    pub fn default_max_lag_secs() -> u64 {
+        120
+    }
+
+    pub fn default_max_version_lag() -> u64 {
+        10_000
+    }
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            max_lag_secs: Self::default_max_lag_secs(),
+            max_version_lag: Self::default_max_version_lag(),
+        }
+    }
+}
+
+/// What `/readyz` needs beyond the storage.
+#[derive(Debug)]
+pub struct ReadinessCheck {
+    pub config: ReadinessConfig,
+    pub canvas_storage_directory: PathBuf,
+    /// Whether this instance only reads the canvas directory, i.e. it is only
+    /// running the API. Followers may have it mounted read only, so for them we
+    /// only check we can read it.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Readiness {
+    ready: bool,
+    /// Why we're not ready, if we're not.
+    problems: Vec<String>,
+    processed_version: Option<u64>,
+    /// The timestamp of the last txn processed, in seconds since the epoch.
+    last_transaction_timestamp: Option<i64>,
+    lag_secs: Option<i64>,
+    latest_stream_version: Option<u64>,
+    version_lag: Option<u64>,
+    storage_reachable: bool,
+    canvas_directory_readable: bool,
+    /// Null if this instance only reads the canvas directory.
+    canvas_directory_writable: Option<bool>,
+}
+
+#[handler]
+pub async fn get_healthz() -> &'static str {
+    "ok"
+}
+
+/// Returns 200 if we're ready and 503 if not, with the details either way.
+#[handler]
+pub async fn get_readyz(
+    storage: Data<&Arc<DatabaseStorage>>,
+    check: Data<&Arc<ReadinessCheck>>,
+) -> poem::Result<Response> {
+    let mut readiness = Readiness::default();
+
+    match storage.read_processor_status(CANVAS_PROCESSOR_NAME).await {
+        Ok(Some(status)) => {
+            readiness.storage_reachable = true;
+            let processed_version = status.processed_version as u64;
+            let latest_stream_version = status.latest_stream_version as u64;
+            let version_lag = latest_stream_version.saturating_sub(processed_version);
+            readiness.processed_version = Some(processed_version);
+            readiness.latest_stream_version = Some(latest_stream_version);
+            readiness.version_lag = Some(version_lag);
+            if version_lag > check.config.max_version_lag {
+                readiness.problems.push(format!(
+                    "Processor is {} versions behind the txn stream, the max is {}",
+                    version_lag, check.config.max_version_lag
+                ));
+            }
+
+            readiness.last_transaction_timestamp = status.last_transaction_timestamp;
+            if let Some(last_transaction_timestamp) = status.last_transaction_timestamp {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .context("System time is before the epoch")?;
+                let lag_secs = now.as_secs() as i64 - last_transaction_timestamp;
+                readiness.lag_secs = Some(lag_secs);
+                if lag_secs > check.config.max_lag_secs as i64 {
+                    readiness.problems.push(format!(
+                        "Last txn processed was {}s ago, the max is {}s",
+                        lag_secs, check.config.max_lag_secs
+                    ));
+                }
+            }
+        },
+        Ok(None) => {
+            readiness.storage_reachable = true;
+            readiness
+                .problems
+                .push("Processor hasn't processed any txns yet".to_string());
+        },
+        Err(e) => {
+            readiness
+                .problems
+                .push(format!("Storage is unreachable: {:#}", e));
+        },
+    }
+
+    match check_directory_readable(&check.canvas_storage_directory) {
+        Ok(()) => readiness.canvas_directory_readable = true,
+        Err(e) => readiness
+            .problems
+            .push(format!("Canvas directory is not readable: {:#}", e)),
+    }
+    if !check.read_only {
+        let writable = check_directory_writable(&check.canvas_storage_directory);
+        readiness.canvas_directory_writable = Some(writable.is_ok());
+        if let Err(e) = writable {
+            readiness
+                .problems
+                .push(format!("Canvas directory is not writable: {:#}", e));
+        }
+    }
+
+    readiness.ready = readiness.problems.is_empty();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(Json(readiness).with_status(status).into_response())
+}
+
+/// Check we can list the files in the directory.
+fn check_directory_readable(directory: &Path) -> Result<()> {
+    fs::read_dir(directory)
+        .and_then(|mut entries| entries.next().transpose())
+        .with_context(|| format!("Failed to read {}", directory.display()))?;
+    Ok(())
+}
+
+/// Check we can create and remove a file in the directory.
+fn check_directory_writable(directory: &Path) -> Result<()> {
+    let path = directory.join(format!(".readyz-{}", std::process::id()));
+    fs::write(&path, b"").with_context(|| format!("Failed to write {}", path.display()))?;
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))
+}
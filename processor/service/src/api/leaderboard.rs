@@ -0,0 +1,98 @@
+//! Leaderboards of the artists who contributed the most, either to a single canvas
+//! or across the whole collection.
+
+use crate::{
+    db_storage::{ContributionTotals, DatabaseStorage},
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path, Query},
+};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardParams {
+    /// How many artists to return, at most `MAX_LIMIT`.
+    limit: Option<u64>,
+    /// How many artists to skip.
+    #[serde(default)]
+    offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Leaderboard {
+    /// How many artists there are in total, across all pages.
+    total: u64,
+    entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardEntry {
+    #[serde(flatten)]
+    totals: ContributionTotals,
+    /// How many of the pixels currently showing this artist drew.
+    pixels_visible: i64,
+}
+
+/// The artists who drew the most pixels on a canvas.
+#[handler]
+pub async fn get_canvas_leaderboard(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+    Query(params): Query<LeaderboardParams>,
+) -> poem::Result<Json<Leaderboard>> {
+    let address = Address::from_str(&address).context("Invalid address")?;
+    let canvas = storage
+        .read_canvas(&address_to_string(&address))
+        .await?
+        .ok_or(NotFoundError)?;
+    Ok(Json(
+        build_leaderboard(&storage, Some(&canvas.address), &params).await?,
+    ))
+}
+
+/// The artists who drew the most pixels across every canvas.
+#[handler]
+pub async fn get_leaderboard(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Query(params): Query<LeaderboardParams>,
+) -> poem::Result<Json<Leaderboard>> {
+    Ok(Json(build_leaderboard(&storage, None, &params).await?))
+}
+
+async fn build_leaderboard(
+    storage: &DatabaseStorage,
+    canvas_address: Option<&str>,
+    params: &LeaderboardParams,
+) -> anyhow::Result<Leaderboard> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let (total, totals) = storage
+        .read_leaderboard(canvas_address, limit, params.offset)
+        .await?;
+    let artist_addresses: Vec<String> = totals
+        .iter()
+        .map(|totals| totals.artist_address.clone())
+        .collect();
+    let visible_pixels = storage
+        .count_visible_pixels(canvas_address, &artist_addresses)
+        .await?;
+    let entries = totals
+        .into_iter()
+        .map(|totals| LeaderboardEntry {
+            pixels_visible: visible_pixels
+                .get(&totals.artist_address)
+                .copied()
+                .unwrap_or(0),
+            totals,
+        })
+        .collect();
+    Ok(Leaderboard { total, entries })
+}
@@ -0,0 +1,143 @@
+//! Where a canvas is in its life, from open for drawing to finalised, and the final
+//! image of finalised canvases. See `finaliser` for how canvases get finalised.
+
+use super::APPLIED_VERSION_HEADER;
+use crate::{db_storage::DatabaseStorage, processor::address_to_string};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path},
+    Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The header we use to tell clients the sha256 of the final image of a canvas.
+const CONTENT_HASH_HEADER: &str = "X-Canvas-Content-Hash";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LifecycleConfig {
+    /// A canvas is closing soon once it closes in less than this.
+    #[serde(default = "LifecycleConfig::default_closing_soon_secs")]
+    pub closing_soon_secs: i64,
+}
+
+impl LifecycleConfig {
+    pub fn default_closing_soon_secs() -> i64 {
+        3600
+    }
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            closing_soon_secs: Self::default_closing_soon_secs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleStatus {
+    Open,
+    /// Still open, but closing within `closing_soon_secs`.
+    ClosingSoon,
+    /// Past its `can_draw_for_s` deadline, but the final image hasn't been made yet.
+    Closed,
+    /// Closed, and the final image has been made.
+    Finalised,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CanvasLifecycle {
+    canvas_address: String,
+    status: LifecycleStatus,
+    /// The last time anyone can draw, null if the canvas never closes.
+    closes_at_s: Option<i64>,
+    /// The time this was worked out for.
+    now_s: i64,
+    /// Null unless the canvas is finalised.
+    finalisation: Option<Finalisation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finalisation {
+    /// The version of the last txn whose changes are reflected in the final image.
+    applied_version: i64,
+    /// The sha256 of the final image, hex encoded.
+    content_hash: String,
+    finalised_at_s: i64,
+}
+
+/// Work out where a canvas is in its life. Whether it is open uses the wall clock
+/// of the API in place of the on chain time, whereas it is only finalised once the
+/// processor has seen a txn from after it closed.
+#[handler]
+pub async fn get_canvas_lifecycle(
+    storage: Data<&Arc<DatabaseStorage>>,
+    config: Data<&Arc<LifecycleConfig>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<CanvasLifecycle>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let canvas = storage.read_canvas(&address).await?.ok_or(NotFoundError)?;
+    let finalisation = storage
+        .read_canvas_finalisation(&address)
+        .await?
+        .map(|finalisation| Finalisation {
+            applied_version: finalisation.applied_version,
+            content_hash: finalisation.content_hash,
+            finalised_at_s: finalisation.finalised_at_s,
+        });
+    let now_s = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System time is before the epoch")?
+        .as_secs() as i64;
+
+    let closes_at_s =
+        (canvas.can_draw_for_s > 0).then_some(canvas.created_at_s + canvas.can_draw_for_s);
+    let status = match closes_at_s {
+        _ if finalisation.is_some() => LifecycleStatus::Finalised,
+        None => LifecycleStatus::Open,
+        // The contract lets artists draw up to and including the deadline.
+        Some(closes_at_s) if now_s > closes_at_s => LifecycleStatus::Closed,
+        Some(closes_at_s) if closes_at_s - now_s < config.closing_soon_secs => {
+            LifecycleStatus::ClosingSoon
+        },
+        Some(_) => LifecycleStatus::Open,
+    };
+
+    Ok(Json(CanvasLifecycle {
+        canvas_address: address,
+        status,
+        closes_at_s,
+        now_s,
+        finalisation,
+    }))
+}
+
+/// Serve the final image of a canvas. Unlike the live image this never changes, so
+/// it can be cached forever. 404 if the canvas isn't finalised.
+#[handler]
+pub async fn get_final_image(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Response> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let finalisation = storage
+        .read_canvas_finalisation(&address)
+        .await?
+        .ok_or(NotFoundError)?;
+    Ok(Response::builder()
+        .header(APPLIED_VERSION_HEADER, finalisation.applied_version)
+        .header(CONTENT_HASH_HEADER, &finalisation.content_hash)
+        .header("ETag", format!("\"{}\"", finalisation.content_hash))
+        .header("Cache-Control", "public, max-age=31536000, immutable")
+        .body(finalisation.png)
+        .set_content_type("image/png"))
+}
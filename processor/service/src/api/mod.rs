@@ -1,18 +1,56 @@
-use crate::canvas_storage::CanvasStorageTrait;
+mod admin;
+mod artists;
+mod canvases;
+mod eligibility;
+mod health;
+mod leaderboard;
+mod lifecycle;
+mod owners;
+mod ownership;
+mod pixels;
+mod revenue;
+
+use crate::{
+    canvas_storage::CanvasStorageTrait,
+    db_storage::DatabaseStorage,
+    metrics::{encode_metrics, HTTP_REQUEST_SECONDS},
+};
 use anyhow::{Context, Result};
 use aptos_move_graphql_scalars::Address;
+use health::ReadinessCheck;
+pub use health::ReadinessConfig;
+pub use lifecycle::LifecycleConfig;
 use poem::{
     get, handler,
     http::Method,
     listener::TcpListener,
     middleware::{Cors, Tracing},
     web::{Data, Path},
-    EndpointExt, Response, Route, Server,
+    Endpoint, EndpointExt, IntoResponse, Response, Route, Server,
 };
 use serde::{Deserialize, Serialize};
-use std::{str::FromStr, sync::Arc};
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Instant};
 use tracing::info;
 
+/// The header we use to tell clients the version of the last txn reflected in a
+/// canvas image.
+const APPLIED_VERSION_HEADER: &str = "X-Canvas-Applied-Version";
+
+/// The top level routes. HTTP metrics are labelled with these rather than the full
+/// path, since the full path includes e.g. canvas addresses.
+const ROUTES: &[&str] = &[
+    "media",
+    "canvases",
+    "leaderboard",
+    "artists",
+    "collections",
+    "recipients",
+    "admin",
+    "metrics",
+    "healthz",
+    "readyz",
+];
+
 #[handler]
 async fn get_image(
     // This gives me a higher order lifetime error.
@@ -25,7 +63,7 @@ async fn get_image(
         address
     };
     let address = Address::from_str(&address).context("Invalid address")?;
-    let png_data = canvas_storage
+    let png = canvas_storage
         .get_canvas_as_png(&address)
         .await
         .with_context(|| {
@@ -33,10 +71,10 @@ async fn get_image(
                 "Failed to get image for address {}",
                 address.to_canonical_string()
             )
-        })
-        .unwrap();
+        })?;
     Ok(Response::builder()
-        .body(png_data)
+        .header(APPLIED_VERSION_HEADER, png.applied_version)
+        .body(png.data)
         .set_content_type("image/png"))
 }
 
@@ -45,28 +83,139 @@ async fn root() -> String {
     "No problems baby!!".to_string()
 }
 
+#[handler]
+async fn get_metrics() -> poem::Result<Response> {
+    let metrics = encode_metrics()?;
+    Ok(Response::builder()
+        .body(metrics)
+        .set_content_type("text/plain; version=0.0.4"))
+}
+
+/// Record how long the endpoint took to respond in the HTTP metrics.
+fn with_http_metrics(endpoint: impl Endpoint + 'static) -> impl Endpoint {
+    endpoint.around(|endpoint, request| async move {
+        let start = Instant::now();
+        let method = request.method().to_string();
+        let route = get_route_label(request.uri().path());
+        let response = match endpoint.call(request).await {
+            Ok(response) => response.into_response(),
+            Err(e) => e.into_response(),
+        };
+        HTTP_REQUEST_SECONDS
+            .with_label_values(&[&method, route, response.status().as_str()])
+            .observe(start.elapsed().as_secs_f64());
+        Ok(response)
+    })
+}
+
+fn get_route_label(path: &str) -> &'static str {
+    let first_segment = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    if first_segment.is_empty() {
+        return "root";
+    }
+    ROUTES
+        .iter()
+        .find(|route| **route == first_segment)
+        .copied()
+        .unwrap_or("other")
+}
+
 pub struct Api {
     config: ApiConfig,
     canvas_storage: Arc<dyn CanvasStorageTrait>,
+    storage: Arc<DatabaseStorage>,
+    canvas_storage_directory: PathBuf,
+    /// Whether we only read from canvas storage, i.e. we're not running the
+    /// processor.
+    read_only: bool,
 }
 
 impl Api {
-    pub fn new(config: ApiConfig, canvas_storage: Arc<dyn CanvasStorageTrait>) -> Self {
+    pub fn new(
+        config: ApiConfig,
+        canvas_storage: Arc<dyn CanvasStorageTrait>,
+        storage: Arc<DatabaseStorage>,
+        canvas_storage_directory: PathBuf,
+        read_only: bool,
+    ) -> Self {
         Self {
             config,
             canvas_storage,
+            storage,
+            canvas_storage_directory,
+            read_only,
         }
     }
 
     pub async fn start_api(&self) -> Result<()> {
         info!("API server starting");
         let cors = Cors::new().allow_methods(vec![Method::GET]);
-        let app = Route::new()
+        let mut app = Route::new()
             .at("/", get(root))
+            .at("/metrics", get(get_metrics))
+            .at("/healthz", get(health::get_healthz))
+            .at("/readyz", get(health::get_readyz))
             .at("/media/:address", get(get_image))
-            .data(self.canvas_storage.clone())
-            .with(cors)
-            .with(Tracing);
+            .at("/media/:address/final", get(lifecycle::get_final_image))
+            .at("/media/:address/final.png", get(lifecycle::get_final_image))
+            .at(
+                "/media/:address/overlays/:artist",
+                get(ownership::get_artist_overlay),
+            )
+            .at("/canvases", get(canvases::get_canvases))
+            .at("/canvases/:address", get(canvases::get_canvas))
+            .at(
+                "/canvases/:address/leaderboard",
+                get(leaderboard::get_canvas_leaderboard),
+            )
+            .at(
+                "/canvases/:address/artists/:artist/eligibility",
+                get(eligibility::get_eligibility),
+            )
+            .at(
+                "/canvases/:address/lifecycle",
+                get(lifecycle::get_canvas_lifecycle),
+            )
+            .at("/canvases/:address/owners", get(owners::get_canvas_owners))
+            .at("/canvases/:address/pixels/:x/:y", get(pixels::get_pixel))
+            .at(
+                "/canvases/:address/revenue",
+                get(revenue::get_canvas_revenue),
+            )
+            .at(
+                "/canvases/:address/visible-pixels",
+                get(ownership::get_visible_pixels),
+            )
+            .at("/leaderboard", get(leaderboard::get_leaderboard))
+            .at("/artists/:address", get(artists::get_artist))
+            .at("/artists/:address/pixels", get(artists::get_artist_pixels))
+            .at(
+                "/recipients/:address/revenue",
+                get(revenue::get_recipient_revenue),
+            )
+            .at(
+                "/collections/:address/owners",
+                get(owners::get_collection_owners),
+            );
+        // The admin endpoints change the DB, which API replicas can't do.
+        if !self.read_only {
+            if let Some(admin_token) = &self.config.admin_token {
+                app = app.nest("/admin", admin::build_admin_routes(admin_token.clone()));
+            }
+        }
+        let readiness_check = Arc::new(ReadinessCheck {
+            config: self.config.readiness_config.clone(),
+            canvas_storage_directory: self.canvas_storage_directory.clone(),
+            read_only: self.read_only,
+        });
+        let app = with_http_metrics(
+            app.data(self.canvas_storage.clone())
+                .data(self.storage.clone())
+                .data(readiness_check)
+                .data(Arc::new(self.config.lifecycle_config.clone())),
+        )
+        .with(cors)
+        .with(Tracing);
         Server::new(TcpListener::bind((
             self.config.listen_address.as_str(),
             self.config.api_port,
@@ -76,6 +225,20 @@ impl Api {
         .await
         .context("API server ended unexpectedly")
     }
+
+    /// Serve just the metrics, for when we're only running the processor.
+    pub async fn start_metrics_server(&self, port: u16) -> Result<()> {
+        info!("Metrics server starting");
+        let app = Route::new().at("/metrics", get(get_metrics)).with(Tracing);
+        Server::new(TcpListener::bind((
+            self.config.listen_address.as_str(),
+            port,
+        )))
+        .name("metrics")
+        .run(app)
+        .await
+        .context("Metrics server ended unexpectedly")
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -85,6 +248,26 @@ pub struct ApiConfig {
 
     #[serde(default = "ApiConfig::default_api_port")]
     pub api_port: u16,
+
+    /// If set, the admin endpoints under `/admin` are served when the processor runs
+    /// alongside the API, and requests to them must include this as a bearer token,
+    /// e.g. `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// If set, when only running the processor, the metrics are served at `/metrics`
+    /// on this port. Otherwise they're served by the API. This is separate from
+    /// `api_port` so the processor can run alongside an API following it.
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+
+    /// Thresholds for `/readyz`, see `health`.
+    #[serde(default)]
+    pub readiness_config: ReadinessConfig,
+
+    /// Thresholds for the lifecycle status of canvases, see `lifecycle`.
+    #[serde(default)]
+    pub lifecycle_config: LifecycleConfig,
 }
 
 impl ApiConfig {
@@ -0,0 +1,78 @@
+//! Who owns the canvases and the collection, and who owned them before.
+
+use crate::{
+    db_storage::{DatabaseStorage, ObjectKind},
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use entities::ownership_changes;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path},
+};
+use serde::Serialize;
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Serialize)]
+pub struct CanvasOwners {
+    canvas_address: String,
+    /// Null if the processor hasn't seen the owner yet, e.g. the canvas was created
+    /// before owners were indexed and hasn't been transferred since.
+    owner_address: Option<String>,
+    /// The owner if `owner_is_super_admin` is set, otherwise there is no super admin.
+    super_admin: Option<String>,
+    /// The owners over time, oldest first.
+    history: Vec<ownership_changes::Model>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionOwners {
+    collection_address: String,
+    owner_address: String,
+    /// The owners over time, oldest first.
+    history: Vec<ownership_changes::Model>,
+}
+
+/// The current and past owners of a canvas.
+#[handler]
+pub async fn get_canvas_owners(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<CanvasOwners>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let canvas = storage.read_canvas(&address).await?.ok_or(NotFoundError)?;
+    let history = storage.read_ownership_changes(&address).await?;
+    Ok(Json(CanvasOwners {
+        canvas_address: address,
+        super_admin: canvas
+            .owner_address
+            .clone()
+            .filter(|_| canvas.owner_is_super_admin),
+        owner_address: canvas.owner_address,
+        history,
+    }))
+}
+
+/// The current and past owners of the collection. We only know about the
+/// collection if we've seen it created or transferred.
+#[handler]
+pub async fn get_collection_owners(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<CollectionOwners>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let history = storage.read_ownership_changes(&address).await?;
+    let owner_address = match history.last() {
+        Some(change) if change.kind == ObjectKind::Collection.as_str() => {
+            change.owner_address.clone()
+        },
+        _ => return Err(NotFoundError.into()),
+    };
+    Ok(Json(CollectionOwners {
+        collection_address: address,
+        owner_address,
+        history,
+    }))
+}
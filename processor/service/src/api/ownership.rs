@@ -0,0 +1,75 @@
+//! Who drew the pixels currently showing on a canvas, based on the last writer of
+//! each pixel recorded in the DB.
+
+use super::APPLIED_VERSION_HEADER;
+use crate::{
+    canvas_storage::CanvasStorageTrait, db_storage::DatabaseStorage, processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path},
+    Response,
+};
+use serde::Serialize;
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Serialize)]
+pub struct VisiblePixels {
+    artist_address: String,
+    /// How many of the pixels currently showing this artist drew.
+    pixels_visible: u64,
+}
+
+/// How many of the pixels currently showing on a canvas each artist drew, most
+/// first. Pixels nobody has drawn since the canvas was created or cleared aren't
+/// counted.
+#[handler]
+pub async fn get_visible_pixels(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<Vec<VisiblePixels>>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    storage.read_canvas(&address).await?.ok_or(NotFoundError)?;
+    Ok(Json(
+        storage
+            .count_canvas_visible_pixels(&address)
+            .await?
+            .into_iter()
+            .map(|(artist_address, pixels_visible)| VisiblePixels {
+                artist_address,
+                pixels_visible: pixels_visible as u64,
+            })
+            .collect(),
+    ))
+}
+
+/// The canvas as a png with the pixels the artist drew that are still showing
+/// highlighted.
+#[handler]
+pub async fn get_artist_overlay(
+    canvas_storage: Data<&Arc<dyn CanvasStorageTrait>>,
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path((address, artist)): Path<(String, String)>,
+) -> poem::Result<Response> {
+    let address = Address::from_str(&address).context("Invalid address")?;
+    let artist = Address::from_str(artist.strip_suffix(".png").unwrap_or(&artist))
+        .context("Invalid artist address")?;
+    storage
+        .read_canvas(&address_to_string(&address))
+        .await?
+        .ok_or(NotFoundError)?;
+    let highlighted_pixels = storage
+        .read_artist_pixel_indices(&address_to_string(&address), &address_to_string(&artist))
+        .await?;
+    let png = canvas_storage
+        .get_overlay_as_png(&address, &highlighted_pixels)
+        .await
+        .with_context(|| format!("Failed to get overlay of {} for {}", address, artist))?;
+    Ok(Response::builder()
+        .header(APPLIED_VERSION_HEADER, png.applied_version)
+        .body(png.data)
+        .set_content_type("image/png"))
+}
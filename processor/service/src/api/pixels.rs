@@ -0,0 +1,115 @@
+//! What's known about a single pixel of a canvas: its current color, who drew it,
+//! and every time it has been drawn.
+
+use super::APPLIED_VERSION_HEADER;
+use crate::{
+    canvas_storage::CanvasStorageTrait, db_storage::DatabaseStorage, generated::Color,
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use entities::pixel_history;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path, Query},
+    IntoResponse, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct PixelParams {
+    /// How many draws of the pixel to return, at most `MAX_LIMIT`.
+    limit: Option<u64>,
+    /// How many draws to skip.
+    #[serde(default)]
+    offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PixelInfo {
+    x: u64,
+    y: u64,
+    color: Color,
+    /// Who drew the pixel currently showing, if anyone has since the canvas was
+    /// created or cleared.
+    artist_address: Option<String>,
+    /// How many times the pixel has been drawn, including before any clears.
+    writes: u64,
+    /// How many times the pixel has been drawn over.
+    overwrite_count: u64,
+    /// A page of the draws of the pixel, newest first.
+    history: Vec<PixelWrite>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PixelWrite {
+    artist_address: String,
+    color: Color,
+    version: i64,
+    transaction_hash: String,
+    /// The timestamp of the txn in seconds.
+    timestamp: Option<i64>,
+}
+
+impl From<pixel_history::Model> for PixelWrite {
+    fn from(model: pixel_history::Model) -> Self {
+        Self {
+            artist_address: model.artist_address,
+            color: Color {
+                r: model.r as u8,
+                g: model.g as u8,
+                b: model.b as u8,
+            },
+            version: model.version,
+            transaction_hash: model.transaction_hash,
+            timestamp: model.timestamp,
+        }
+    }
+}
+
+/// The current color of a pixel and the history of who drew it.
+#[handler]
+pub async fn get_pixel(
+    canvas_storage: Data<&Arc<dyn CanvasStorageTrait>>,
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path((address, x, y)): Path<(String, u64, u64)>,
+    Query(params): Query<PixelParams>,
+) -> poem::Result<Response> {
+    let address = Address::from_str(&address).context("Invalid address")?;
+    let canvas = storage
+        .read_canvas(&address_to_string(&address))
+        .await?
+        .ok_or(NotFoundError)?;
+    if x >= canvas.width as u64 || y >= canvas.height as u64 {
+        return Err(NotFoundError.into());
+    }
+    let index = y * canvas.width as u64 + x;
+
+    let pixel = canvas_storage
+        .get_pixel(&address, index)
+        .await
+        .with_context(|| format!("Failed to get pixel {} of {}", index, address))?;
+    let writer = storage.read_pixel_writer(&canvas.address, index).await?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let (writes, history) = storage
+        .read_pixel_history(&canvas.address, index, limit, params.offset)
+        .await?;
+
+    let pixel_info = PixelInfo {
+        x,
+        y,
+        color: pixel.color,
+        artist_address: writer.map(|writer| writer.artist_address),
+        writes,
+        overwrite_count: writes.saturating_sub(1),
+        history: history.into_iter().map(PixelWrite::from).collect(),
+    };
+    Ok(Json(pixel_info)
+        .with_header(APPLIED_VERSION_HEADER, pixel.applied_version)
+        .into_response())
+}
@@ -0,0 +1,84 @@
+//! How much draws on paid canvases have paid, and to whom.
+
+use crate::{
+    db_storage::{DatabaseStorage, RevenueGrouping, RevenueTotals},
+    processor::address_to_string,
+};
+use anyhow::Context;
+use aptos_move_graphql_scalars::Address;
+use entities::funds_recipient_changes;
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Json, Path},
+};
+use serde::Serialize;
+use std::{str::FromStr, sync::Arc};
+
+#[derive(Debug, Serialize)]
+pub struct CanvasRevenue {
+    canvas_address: String,
+    /// How much drawing a pixel costs in OCTA.
+    cost: i64,
+    octas_received: i64,
+    payments: i64,
+    /// Who currently receives the funds, null if it's the owner of the canvas.
+    funds_recipient: Option<String>,
+    /// Who the funds actually went to, biggest earners first.
+    recipients: Vec<RevenueTotals>,
+    /// Who was set to receive the funds over time, oldest first.
+    funds_recipient_history: Vec<funds_recipient_changes::Model>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecipientRevenue {
+    recipient_address: String,
+    octas_received: i64,
+    payments: i64,
+    /// The canvases the recipient was paid for, biggest earners first.
+    canvases: Vec<RevenueTotals>,
+}
+
+/// The payments made by draws on a canvas.
+#[handler]
+pub async fn get_canvas_revenue(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<CanvasRevenue>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let canvas = storage.read_canvas(&address).await?.ok_or(NotFoundError)?;
+    let recipients = storage
+        .read_revenue_totals(Some(&address), None, RevenueGrouping::Recipient)
+        .await?;
+    let funds_recipient_history = storage.read_funds_recipient_changes(&address).await?;
+    Ok(Json(CanvasRevenue {
+        canvas_address: address,
+        cost: canvas.cost,
+        octas_received: recipients
+            .iter()
+            .map(|recipient| recipient.octas_received)
+            .sum(),
+        payments: recipients.iter().map(|recipient| recipient.payments).sum(),
+        funds_recipient: canvas.funds_recipient,
+        recipients,
+        funds_recipient_history,
+    }))
+}
+
+/// The payments an account has received for draws, across every canvas.
+#[handler]
+pub async fn get_recipient_revenue(
+    storage: Data<&Arc<DatabaseStorage>>,
+    Path(address): Path<String>,
+) -> poem::Result<Json<RecipientRevenue>> {
+    let address = address_to_string(&Address::from_str(&address).context("Invalid address")?);
+    let canvases = storage
+        .read_revenue_totals(None, Some(&address), RevenueGrouping::Canvas)
+        .await?;
+    Ok(Json(RecipientRevenue {
+        recipient_address: address,
+        octas_received: canvases.iter().map(|canvas| canvas.octas_received).sum(),
+        payments: canvases.iter().map(|canvas| canvas.payments).sum(),
+        canvases,
+    }))
+}
@@ -0,0 +1,338 @@
+//! Backfilling rebuilds canvases and tables from txns that have already been
+//! processed, e.g. after fixing a bug in the processor, without stopping the live
+//! processor.
+//!
+//! A backfill processes the range `[start, end]` into a separate staging canvas
+//! directory and DB, under its own processor name so its progress is tracked
+//! separately. Once it has processed the range it carries on until it has caught up
+//! with the live processor, at which point it copies the staging tables into the live
+//! DB while the live processor keeps going. It then pauses the live processor, swaps
+//! the staging canvases and tables in, and replays the txns the live processor
+//! processed during the copy on top. The live processor then carries on from where it
+//! was, on top of the backfilled data.
+//!
+//! Canvases created before `start` aren't in the staging directory or DB, so they
+//! and their rows are left as they are. This means `start` should be at or before the version where any
+//! canvas you want rebuilt was created, and no canvas created before `start` may be
+//! drawn on in the range.
+
+use crate::{
+    canvas_storage::{
+        get_canvas_filename, parse_canvas_filename, write_atomically, MmapCanvasStorage,
+        MmapCanvasStorageConfig,
+    },
+    db_storage::{DatabaseStorage, DatabaseStorageConfig},
+    processor::{CanvasProcessor, CanvasProcessorConfig},
+};
+use anyhow::{Context, Result};
+use aptos_processor_framework::{
+    indexer_protos::transaction::v1::Transaction, GrpcStreamSubscriber, GrpcStreamSubscriberConfig,
+    ProcessorTrait, StorageTrait, StreamSubscriberTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// The name the backfill records its progress under in the staging DB.
+pub const BACKFILL_PROCESSOR_NAME: &str = "CanvasProcessorBackfill";
+
+/// How long to wait for the live processor to make progress before checking again.
+const LIVE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackfillConfig {
+    /// Where to build the canvases. This should be on the same filesystem as the
+    /// live canvas directory.
+    pub staging_canvas_storage_directory: PathBuf,
+    /// Where to build the tables. This must be a different DB to the live one.
+    pub staging_database_storage_config: DatabaseStorageConfig,
+}
+
+#[derive(Debug)]
+pub struct Backfill {
+    start_version: u64,
+    end_version: Option<u64>,
+    stream_subscriber_config: GrpcStreamSubscriberConfig,
+
+    staging_canvas_directory: PathBuf,
+    staging_storage: Arc<DatabaseStorage>,
+    staging_processor: CanvasProcessor,
+    /// Used for replaying txns on top of the backfill once it has been swapped in.
+    canvas_processor_config: CanvasProcessorConfig,
+
+    live_canvas_directory: PathBuf,
+    live_canvas_storage: Arc<MmapCanvasStorage>,
+    live_storage: Arc<DatabaseStorage>,
+    /// See `CanvasProcessor::processed_version`.
+    live_processed_version: Arc<Mutex<Option<u64>>>,
+}
+
+impl Backfill {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        config: BackfillConfig,
+        start_version: u64,
+        end_version: Option<u64>,
+        stream_subscriber_config: GrpcStreamSubscriberConfig,
+        canvas_processor_config: CanvasProcessorConfig,
+        live_canvas_directory: PathBuf,
+        live_canvas_storage: Arc<MmapCanvasStorage>,
+        live_storage: Arc<DatabaseStorage>,
+        live_processed_version: Arc<Mutex<Option<u64>>>,
+    ) -> Result<Self> {
+        if config.staging_canvas_storage_directory == live_canvas_directory {
+            anyhow::bail!("The staging canvas directory must not be the live one");
+        }
+        fs::create_dir_all(&config.staging_canvas_storage_directory).with_context(|| {
+            format!(
+                "Failed to create {}",
+                config.staging_canvas_storage_directory.display()
+            )
+        })?;
+        let staging_storage = Arc::new(
+            DatabaseStorage::new(config.staging_database_storage_config)
+                .await
+                .context("Failed to initialize staging database storage")?,
+        );
+        let staging_canvas_storage = Arc::new(MmapCanvasStorage::new(MmapCanvasStorageConfig {
+            canvas_storage_directory: config.staging_canvas_storage_directory.clone(),
+        }));
+        let staging_processor = CanvasProcessor::new(
+            canvas_processor_config.clone(),
+            staging_canvas_storage,
+            staging_storage.clone(),
+        )
+        .with_name(BACKFILL_PROCESSOR_NAME);
+
+        Ok(Self {
+            start_version,
+            end_version,
+            stream_subscriber_config,
+            staging_canvas_directory: config.staging_canvas_storage_directory,
+            staging_storage,
+            staging_processor,
+            canvas_processor_config,
+            live_canvas_directory,
+            live_canvas_storage,
+            live_storage,
+            live_processed_version,
+        })
+    }
+
+    /// Run the backfill until it has been swapped in.
+    pub async fn run(self) -> Result<()> {
+        let live_version = self.live_processed_version.lock().await.context(
+            "The live processor hasn't processed anything yet, there is nothing to backfill",
+        )?;
+        let end_version = self.end_version.unwrap_or(live_version);
+        if end_version < self.start_version || end_version > live_version {
+            anyhow::bail!(
+                "The backfill range [{}, {}] must end at or before the last version the live processor processed ({})",
+                self.start_version,
+                end_version,
+                live_version
+            );
+        }
+
+        // If we already made progress on a previous attempt, carry on from there.
+        let mut staging_version = self
+            .staging_storage
+            .read_last_processed_version(BACKFILL_PROCESSOR_NAME)
+            .await?;
+        let starting_version = match staging_version {
+            Some(version) => {
+                info!("Resuming backfill from version {}", version + 1);
+                version + 1
+            },
+            None => {
+                // Make sure we don't build on files from an attempt that never
+                // recorded any progress.
+                remove_canvas_files(&self.staging_canvas_directory)?;
+                self.start_version
+            },
+        };
+        info!(
+            start_version = starting_version,
+            end_version = end_version,
+            "Starting backfill"
+        );
+
+        let stream_subscriber = GrpcStreamSubscriber {
+            config: self.stream_subscriber_config.clone(),
+            processor_name: BACKFILL_PROCESSOR_NAME.to_string(),
+            starting_version,
+        };
+        let mut receiver = stream_subscriber.start().await?.receiver;
+
+        let mut pending: Vec<Transaction> = Vec::new();
+        loop {
+            // Once we've caught up with the live processor, copy the staging tables
+            // into the live DB. This can take a while, so the live processor carries
+            // on meanwhile.
+            if staging_version.is_some_and(|version| version >= end_version)
+                && staging_version == *self.live_processed_version.lock().await
+            {
+                let version = staging_version.unwrap();
+                info!(
+                    version = version,
+                    "Copying backfilled tables into the live DB"
+                );
+                self.live_storage
+                    .stage_indexed_tables_from(&self.staging_storage)
+                    .await
+                    .context("Failed to copy backfilled tables into the live DB")?;
+
+                // Then swap. We hold the lock while swapping so the live processor
+                // can't process anything meanwhile.
+                let live_version_lock = self.live_processed_version.lock().await;
+                let live_version = live_version_lock.unwrap_or(version);
+                // Gather the txns the live processor processed while we were copying,
+                // to replay on top of the backfill.
+                let mut transactions: Vec<Transaction> = Vec::new();
+                loop {
+                    let split = pending
+                        .iter()
+                        .position(|transaction| transaction.version > live_version)
+                        .unwrap_or(pending.len());
+                    let reached_live_version = split < pending.len();
+                    transactions.extend(pending.drain(..split));
+                    if reached_live_version
+                        || transactions
+                            .last()
+                            .map_or(version, |transaction| transaction.version)
+                            >= live_version
+                    {
+                        break;
+                    }
+                    pending = receiver
+                        .recv()
+                        .await
+                        .context("Txn stream ended before the backfill caught up")?;
+                }
+                self.swap(version, transactions).await?;
+                return Ok(());
+            }
+
+            if pending.is_empty() {
+                pending = receiver
+                    .recv()
+                    .await
+                    .context("Txn stream ended before the backfill caught up")?;
+                pending.retain(|transaction| transaction.version >= starting_version);
+                continue;
+            }
+
+            // Never get ahead of the live processor, otherwise we'd never be at the
+            // same version as it to swap. During the bounded part of the backfill we
+            // don't need to worry about this since the end is behind the live processor.
+            let limit = (*self.live_processed_version.lock().await).unwrap_or(0);
+            let split = pending
+                .iter()
+                .position(|transaction| transaction.version > limit)
+                .unwrap_or(pending.len());
+            if split == 0 {
+                tokio::time::sleep(LIVE_POLL_INTERVAL).await;
+                continue;
+            }
+            let remaining = pending.split_off(split);
+            let batch = std::mem::replace(&mut pending, remaining);
+
+            let batch_start = batch.first().unwrap().version;
+            let batch_end = batch.last().unwrap().version;
+            self.staging_processor
+                .process_transactions(batch, batch_start, batch_end)
+                .await
+                .context("Failed to process backfill batch")?;
+            self.staging_storage
+                .write_last_processed_version(BACKFILL_PROCESSOR_NAME, batch_end)
+                .await?;
+            if staging_version.map_or(true, |version| version < end_version)
+                && batch_end >= end_version
+            {
+                info!(
+                    "Backfilled up to version {}, catching up with the live processor",
+                    end_version
+                );
+            }
+            staging_version = Some(batch_end);
+        }
+    }
+
+    /// Replace the live canvases and tables with the staging ones, which are at
+    /// `version`, then replay `transactions`, the txns the live processor processed
+    /// since, on top. The live processor must be paused when this is called, and the
+    /// staging tables already copied into the live DB.
+    async fn swap(&self, version: u64, transactions: Vec<Transaction>) -> Result<()> {
+        info!(version = version, "Swapping in backfill");
+
+        let mut num_canvases = 0;
+        for entry in fs::read_dir(&self.staging_canvas_directory)? {
+            let path = entry?.path();
+            let canvas_address = match parse_canvas_filename(&path) {
+                Some(canvas_address) => canvas_address,
+                None => continue,
+            };
+            let data =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            write_atomically(
+                &get_canvas_filename(&self.live_canvas_directory, &canvas_address),
+                &data,
+            )?;
+            num_canvases += 1;
+        }
+        self.live_canvas_storage.clear_cache();
+
+        self.live_storage
+            .swap_in_staged_tables()
+            .await
+            .context("Failed to swap in backfilled tables")?;
+
+        if let (Some(first), Some(last)) = (transactions.first(), transactions.last()) {
+            let (start_version, end_version) = (first.version, last.version);
+            info!(
+                start_version = start_version,
+                end_version = end_version,
+                "Replaying txns processed while copying the backfill"
+            );
+            CanvasProcessor::new(
+                self.canvas_processor_config.clone(),
+                self.live_canvas_storage.clone(),
+                self.live_storage.clone(),
+            )
+            .process_transactions(transactions, start_version, end_version)
+            .await
+            .context("Failed to replay txns on top of the backfill")?;
+        }
+
+        // Clean up, so if the backfill is run again it starts from scratch.
+        remove_canvas_files(&self.staging_canvas_directory)?;
+        self.staging_storage
+            .delete_last_processed_version(BACKFILL_PROCESSOR_NAME)
+            .await?;
+
+        info!(
+            version = version,
+            num_canvases = num_canvases,
+            "Swapped in backfill"
+        );
+
+        Ok(())
+    }
+}
+
+fn remove_canvas_files(directory: &Path) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if parse_canvas_filename(&path).is_some() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use crate::generated::Color;
+use anyhow::{Context, Result};
+use std::sync::atomic::{fence, Ordering};
+
+// The on disk format of a canvas file is a fixed size header followed by the pixels,
+// where each pixel is stored as 3 bytes (r, g, b), row by row starting from the top
+// left corner. All integers in the header are little endian. The header looks like
+// this:
+//
+// | offset | size | field            |
+// |--------|------|------------------|
+// | 0      | 8    | magic            |
+// | 8      | 4    | format version   |
+// | 12     | 4    | write generation |
+// | 16     | 8    | width            |
+// | 24     | 8    | height           |
+// | 32     | 8    | applied version  |
+//
+// The applied version is the version of the last txn whose changes are reflected in
+// the canvas. It is updated by the writer after it finishes applying a batch of txns.
+//
+// The write generation lets readers in other processes tell whether they read the
+// file while it was being written to, like a seqlock. The writer makes it odd before
+// it writes a batch's pixels to the canvas and makes it even again straight after, and
+// does the same when it sets the applied version. A read is consistent if the
+// generation was even before it and unchanged after it, in which case it reflects at
+// least every txn up to the applied version. Files written before this was introduced
+// have zeros here, i.e. no write in progress.
+//
+// Before the header was introduced (format version 0) the file was just the pixels
+// followed by the width and height as 8 bytes each. We can still read files in that
+// format, but the writer upgrades them when it opens them.
+
+/// Every canvas file (from format version 1 onwards) starts with these bytes.
+pub const CANVAS_FILE_MAGIC: &[u8; 8] = b"APTCNVS\0";
+
+/// The format version that we write.
+pub const CURRENT_FORMAT_VERSION: u32 = 1;
+
+/// The length of the header at the start of the file.
+pub const HEADER_LENGTH: usize = 40;
+
+/// How many bytes we use to store each pixel.
+pub const BYTES_PER_PIXEL: usize = 3;
+
+/// The length of the width and height at the end of a format version 0 file.
+const LEGACY_TRAILER_LENGTH: usize = 16;
+
+const WRITE_GENERATION_OFFSET: usize = 12;
+
+const APPLIED_VERSION_OFFSET: usize = 32;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CanvasHeader {
+    pub format_version: u32,
+    pub width: u64,
+    pub height: u64,
+    pub applied_version: u64,
+}
+
+impl CanvasHeader {
+    pub fn new(width: u64, height: u64) -> Self {
+        Self {
+            format_version: CURRENT_FORMAT_VERSION,
+            width,
+            height,
+            applied_version: 0,
+        }
+    }
+
+    /// Read the header from the full contents of a canvas file. This handles both
+    /// the current format and the legacy format without a header.
+    pub fn read(data: &[u8]) -> Result<Self> {
+        if data.len() >= CANVAS_FILE_MAGIC.len()
+            && &data[..CANVAS_FILE_MAGIC.len()] == CANVAS_FILE_MAGIC
+        {
+            if data.len() < HEADER_LENGTH {
+                anyhow::bail!("File is too short to contain the header");
+            }
+            let format_version = u32::from_le_bytes(data[8..12].try_into().unwrap());
+            if format_version != CURRENT_FORMAT_VERSION {
+                anyhow::bail!("Unsupported canvas format version {}", format_version);
+            }
+            Ok(Self {
+                format_version,
+                width: read_u64(data, 16),
+                height: read_u64(data, 24),
+                applied_version: read_u64(data, APPLIED_VERSION_OFFSET),
+            })
+        } else {
+            let len = data.len();
+            if len < LEGACY_TRAILER_LENGTH {
+                anyhow::bail!("File is too short to contain width and height");
+            }
+            Ok(Self {
+                format_version: 0,
+                width: read_u64(data, len - 16),
+                height: read_u64(data, len - 8),
+                applied_version: 0,
+            })
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LENGTH] {
+        let mut bytes = [0; HEADER_LENGTH];
+        bytes[..8].copy_from_slice(CANVAS_FILE_MAGIC);
+        bytes[8..12].copy_from_slice(&self.format_version.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.width.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.height.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.applied_version.to_le_bytes());
+        bytes
+    }
+
+    pub fn num_pixels(&self) -> Result<u64> {
+        self.width
+            .checked_mul(self.height)
+            .context("Width * height overflows")
+    }
+
+    /// Where the pixel data starts in the file.
+    pub fn pixels_offset(&self) -> usize {
+        if self.format_version == 0 {
+            0
+        } else {
+            HEADER_LENGTH
+        }
+    }
+
+    /// Where the pixel at `index` starts in the file.
+    pub fn pixel_offset(&self, index: u64) -> Result<usize> {
+        if index >= self.num_pixels()? {
+            anyhow::bail!(
+                "Pixel index {} is out of bounds for a {}x{} canvas",
+                index,
+                self.width,
+                self.height
+            );
+        }
+        Ok(self.pixels_offset() + index as usize * BYTES_PER_PIXEL)
+    }
+
+    /// How long a file with this header should be.
+    pub fn expected_file_length(&self) -> Result<usize> {
+        let pixel_bytes = (self.num_pixels()? as usize)
+            .checked_mul(BYTES_PER_PIXEL)
+            .context("Pixel data length overflows")?;
+        let extra = if self.format_version == 0 {
+            LEGACY_TRAILER_LENGTH
+        } else {
+            HEADER_LENGTH
+        };
+        Ok(pixel_bytes + extra)
+    }
+
+    /// Confirm that the file the header was read from is the right length.
+    pub fn check_file_length(&self, file_length: usize) -> Result<()> {
+        let expected = self.expected_file_length()?;
+        if file_length != expected {
+            anyhow::bail!(
+                "File is {} bytes but a {}x{} canvas should be {} bytes",
+                file_length,
+                self.width,
+                self.height,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Build the full contents of a new canvas file where every pixel is `color`.
+pub fn build_canvas_file(width: u64, height: u64, color: &Color) -> Result<Vec<u8>> {
+    let header = CanvasHeader::new(width, height);
+    let mut data = Vec::with_capacity(header.expected_file_length()?);
+    data.extend(header.to_bytes());
+    for _ in 0..header.num_pixels()? {
+        data.push(color.r);
+        data.push(color.g);
+        data.push(color.b);
+    }
+    Ok(data)
+}
+
+/// Convert the contents of a format version 0 file to the current format.
+pub fn upgrade_legacy_canvas_file(data: &[u8]) -> Result<Vec<u8>> {
+    let legacy_header = CanvasHeader::read(data)?;
+    if legacy_header.format_version != 0 {
+        anyhow::bail!("Canvas file is not in the legacy format");
+    }
+    legacy_header.check_file_length(data.len())?;
+    let header = CanvasHeader::new(legacy_header.width, legacy_header.height);
+    let mut upgraded = Vec::with_capacity(header.expected_file_length()?);
+    upgraded.extend(header.to_bytes());
+    upgraded.extend(&data[..data.len() - LEGACY_TRAILER_LENGTH]);
+    Ok(upgraded)
+}
+
+/// Read the write generation from the header, see the top of this module. Legacy
+/// files don't have one, so they always read as 0.
+pub fn read_write_generation(data: &[u8]) -> u32 {
+    if data.len() < HEADER_LENGTH || &data[..CANVAS_FILE_MAGIC.len()] != CANVAS_FILE_MAGIC {
+        return 0;
+    }
+    // The writer changes this under us, so make sure we actually read it each time.
+    let bytes = unsafe {
+        std::ptr::read_volatile(data[WRITE_GENERATION_OFFSET..].as_ptr() as *const [u8; 4])
+    };
+    u32::from_le_bytes(bytes)
+}
+
+fn write_write_generation(data: &mut [u8], generation: u32) {
+    unsafe {
+        std::ptr::write_volatile(
+            data[WRITE_GENERATION_OFFSET..].as_mut_ptr() as *mut [u8; 4],
+            generation.to_le_bytes(),
+        )
+    };
+}
+
+/// Mark that a batch is being written, if it isn't already. This must be called
+/// before writing any pixels, `end_write` or `write_applied_version` clears it once
+/// the writes are done. This only works for the current format.
+pub fn begin_write(data: &mut [u8]) -> Result<()> {
+    let header = CanvasHeader::read(data)?;
+    if header.format_version == 0 {
+        anyhow::bail!("Cannot write to a legacy canvas file");
+    }
+    let generation = read_write_generation(data);
+    if generation % 2 == 0 {
+        write_write_generation(data, generation.wrapping_add(1));
+        // Make sure readers see the mark before any of the pixels.
+        fence(Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Clear the mark made by `begin_write`, if there is one. This only works for the
+/// current format.
+pub fn end_write(data: &mut [u8]) {
+    // Make sure readers see everything written before the mark is cleared.
+    fence(Ordering::SeqCst);
+    let generation = read_write_generation(data);
+    if generation % 2 == 1 {
+        write_write_generation(data, generation.wrapping_add(1));
+    }
+}
+
+/// Write the applied version into the header. This only works for the current
+/// format.
+pub fn write_applied_version(data: &mut [u8], version: u64) -> Result<()> {
+    begin_write(data)?;
+    data[APPLIED_VERSION_OFFSET..APPLIED_VERSION_OFFSET + 8]
+        .copy_from_slice(&version.to_le_bytes());
+    end_write(data);
+    Ok(())
+}
+
+/// Read from the contents of a canvas file with `read` such that what is read is
+/// consistent with the header. This returns `None` if the writer was in the middle
+/// of a batch, in which case the caller should try again later.
+pub fn read_consistently<T>(
+    data: &[u8],
+    read: impl FnOnce(&[u8], &CanvasHeader) -> Result<T>,
+) -> Result<Option<(CanvasHeader, T)>> {
+    let generation = read_write_generation(data);
+    if generation % 2 == 1 {
+        return Ok(None);
+    }
+    fence(Ordering::Acquire);
+    let header = CanvasHeader::read(data)?;
+    let value = read(data, &header)?;
+    fence(Ordering::Acquire);
+    if read_write_generation(data) != generation {
+        return Ok(None);
+    }
+    Ok(Some((header, value)))
+}
+
+/// Read all the pixels of the canvas, row by row.
+pub fn read_pixels(data: &[u8], header: &CanvasHeader) -> Result<Vec<Color>> {
+    header.check_file_length(data.len())?;
+    let offset = header.pixels_offset();
+    let num_pixels = header.num_pixels()? as usize;
+    let mut pixels = Vec::with_capacity(num_pixels);
+    for i in 0..num_pixels {
+        let index = offset + i * BYTES_PER_PIXEL;
+        pixels.push(Color {
+            r: data[index],
+            g: data[index + 1],
+            b: data[index + 2],
+        });
+    }
+    Ok(pixels)
+}
@@ -0,0 +1,129 @@
+use super::{
+    format::CanvasHeader,
+    utils::{get_image, highlight_pixels},
+    CanvasPixel, CanvasPng, CanvasStorageTrait, CreateCanvasIntent, WritePixelIntent,
+};
+use crate::generated::Color;
+use anyhow::{Context, Result};
+use aptos_move_graphql_scalars::Address;
+use dashmap::DashMap;
+use std::collections::HashSet;
+
+/// An in-memory, transient canvas storage implementation.
+#[derive(Debug, Default)]
+pub struct MemoryCanvasStorage {
+    canvases: DashMap<Address, MemoryCanvas>,
+}
+
+/// A canvas held in memory.
+#[derive(Clone, Debug)]
+pub struct MemoryCanvas {
+    pub header: CanvasHeader,
+    /// The pixels row by row, starting from the top left corner.
+    pub pixels: Vec<Color>,
+}
+
+impl MemoryCanvasStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a copy of a canvas, if it exists.
+    pub fn get_canvas(&self, canvas_address: &Address) -> Option<MemoryCanvas> {
+        self.canvases
+            .get(canvas_address)
+            .map(|canvas| canvas.clone())
+    }
+
+    /// Get the addresses of all the canvases.
+    pub fn get_canvas_addresses(&self) -> Vec<Address> {
+        self.canvases.iter().map(|canvas| *canvas.key()).collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl CanvasStorageTrait for MemoryCanvasStorage {
+    async fn create_canvas(&self, intent: CreateCanvasIntent) -> Result<()> {
+        let header = CanvasHeader::new(intent.width, intent.height);
+        let pixels = vec![intent.default_color; header.num_pixels()? as usize];
+        self.canvases
+            .insert(intent.canvas_address, MemoryCanvas { header, pixels });
+        Ok(())
+    }
+
+    async fn write_pixels(
+        &self,
+        canvas_address: &Address,
+        intents: Vec<WritePixelIntent>,
+    ) -> Result<()> {
+        let mut canvas = self
+            .canvases
+            .get_mut(canvas_address)
+            .context("Canvas does not exist")?;
+        for intent in intents {
+            // This checks the index is in bounds.
+            canvas.header.pixel_offset(intent.index)?;
+            canvas.pixels[intent.index as usize] = intent.color;
+        }
+        Ok(())
+    }
+
+    async fn set_applied_version(&self, canvas_address: &Address, version: u64) -> Result<()> {
+        let mut canvas = self
+            .canvases
+            .get_mut(canvas_address)
+            .context("Canvas does not exist")?;
+        canvas.header.applied_version = version;
+        Ok(())
+    }
+
+    async fn get_canvas_as_png(&self, canvas_address: &Address) -> Result<CanvasPng> {
+        let canvas = self
+            .get_canvas(canvas_address)
+            .context("Canvas does not exist")?;
+        let png = get_image(
+            canvas.pixels,
+            canvas.header.width as u32,
+            canvas.header.height as u32,
+        )
+        .context("Failed to convert data to a png")?;
+        Ok(CanvasPng {
+            data: png,
+            applied_version: canvas.header.applied_version,
+        })
+    }
+
+    async fn get_pixel(&self, canvas_address: &Address, index: u64) -> Result<CanvasPixel> {
+        let canvas = self
+            .canvases
+            .get(canvas_address)
+            .context("Canvas does not exist")?;
+        // This checks the index is in bounds.
+        canvas.header.pixel_offset(index)?;
+        Ok(CanvasPixel {
+            color: canvas.pixels[index as usize].clone(),
+            applied_version: canvas.header.applied_version,
+        })
+    }
+
+    async fn get_overlay_as_png(
+        &self,
+        canvas_address: &Address,
+        highlighted_pixels: &HashSet<u64>,
+    ) -> Result<CanvasPng> {
+        let canvas = self
+            .get_canvas(canvas_address)
+            .context("Canvas does not exist")?;
+        let pixels = highlight_pixels(canvas.pixels, highlighted_pixels);
+        let png = get_image(
+            pixels,
+            canvas.header.width as u32,
+            canvas.header.height as u32,
+        )
+        .context("Failed to convert data to a png")?;
+        Ok(CanvasPng {
+            data: png,
+            applied_version: canvas.header.applied_version,
+        })
+    }
+}
@@ -1,152 +1,508 @@
-use super::{utils::get_image, CanvasStorageTrait, CreateCanvasIntent, WritePixelIntent};
-use crate::generated::Color;
+use super::{
+    format::{
+        begin_write, build_canvas_file, end_write, read_consistently, read_pixels,
+        upgrade_legacy_canvas_file, write_applied_version, CanvasHeader,
+    },
+    utils::{get_image, highlight_pixels},
+    CanvasPixel, CanvasPng, CanvasStorageTrait, CreateCanvasIntent, WritePixelIntent,
+};
+use crate::{generated::Color, metrics::RENDER_CACHE_LOOKUPS};
 use anyhow::{Context, Result};
 use aptos_move_graphql_scalars::Address;
-use dashmap::DashMap;
-use memmap2::MmapMut;
+use dashmap::{
+    mapref::{
+        entry::Entry,
+        one::{Ref, RefMut},
+    },
+    DashMap,
+};
+use memmap2::{Mmap, MmapMut};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{File, OpenOptions},
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
     io::Write,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
+use tracing::{info, warn};
 
 // There could be an alternate implementation where instead of using the mmap, for
 // every pixel we read the png, update the pixel, and write the png back to disk.
 // This would be slower and result in more disk IO but use less storage and memory.
 
+/// How many times we try to read a consistent snapshot of a canvas, see
+/// `read_consistently`. The writer can be in the middle of a batch for as long as it
+/// takes to process it, so we keep trying for a few seconds.
+const MAX_READ_ATTEMPTS: usize = 200;
+
+/// How long to wait between attempts to read a consistent snapshot of a canvas.
+const READ_RETRY_INTERVAL: Duration = Duration::from_millis(25);
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MmapCanvasStorageConfig {
     pub canvas_storage_directory: PathBuf,
 }
 
+/// A memory map of a canvas file. When following a writer the files are mapped read
+/// only, otherwise they're mapped read / write.
+#[derive(Debug)]
+enum CanvasMmap {
+    ReadWrite(MmapMut),
+    ReadOnly(Mmap),
+}
+
+impl CanvasMmap {
+    fn data(&self) -> &[u8] {
+        match self {
+            CanvasMmap::ReadWrite(mmap) => mmap,
+            CanvasMmap::ReadOnly(mmap) => mmap,
+        }
+    }
+
+    fn data_mut(&mut self) -> Result<&mut [u8]> {
+        match self {
+            CanvasMmap::ReadWrite(mmap) => Ok(mmap),
+            CanvasMmap::ReadOnly(_) => anyhow::bail!("Canvas storage is read only"),
+        }
+    }
+
+    fn flush_async(&self) -> Result<()> {
+        match self {
+            CanvasMmap::ReadWrite(mmap) => mmap.flush_async().context("Failed to flush mmap"),
+            CanvasMmap::ReadOnly(_) => Ok(()),
+        }
+    }
+}
+
 /// Handles creating, updating, and reading canvases.
 #[derive(Debug)]
 pub struct MmapCanvasStorage {
     config: MmapCanvasStorageConfig,
-    mmaps: DashMap<Address, MmapMut>,
+    mmaps: Arc<DashMap<Address, CanvasMmap>>,
+
+    /// If set, this storage never writes to the canvas files, it just follows along
+    /// with the files written by a processor running elsewhere.
+    read_only: bool,
+
+    /// When following, this watches the canvas directory for new / replaced files.
+    /// We just need to keep it alive, it stops watching once dropped.
+    _watcher: Option<RecommendedWatcher>,
 }
 
 impl MmapCanvasStorage {
     pub fn new(config: MmapCanvasStorageConfig) -> Self {
         Self {
             config,
-            mmaps: DashMap::new(),
+            mmaps: Arc::new(DashMap::new()),
+            read_only: false,
+            _watcher: None,
         }
     }
 
-    fn get_filename(&self, canvas_address: &Address) -> PathBuf {
-        Path::new(&self.config.canvas_storage_directory)
-            .join(format!("0x{}.canvas", canvas_address.to_canonical_string()))
+    /// Build a read only storage that follows the canvas files written by a processor
+    /// running elsewhere, e.g. in another process using the same (shared) directory.
+    /// Files are mapped read only, so updates to pixels by the writer are visible
+    /// as they happen, and we watch the directory so we notice when canvases are
+    /// created or replaced.
+    ///
+    /// Note: Seeing the writes through the mmap relies on the reader and writer
+    /// sharing a page cache, so this won't work across hosts on a network filesystem.
+    pub fn new_follower(config: MmapCanvasStorageConfig) -> Result<Self> {
+        let mmaps = Arc::new(DashMap::new());
+        let watcher = watch_canvas_directory(&config.canvas_storage_directory, mmaps.clone())
+            .context("Failed to watch canvas directory")?;
+        info!(
+            directory = %config.canvas_storage_directory.display(),
+            "Following canvas directory"
+        );
+        Ok(Self {
+            config,
+            mmaps,
+            read_only: true,
+            _watcher: Some(watcher),
+        })
     }
-}
 
-#[async_trait::async_trait]
-impl CanvasStorageTrait for MmapCanvasStorage {
-    /// Create a canvas as a file on disk. We use a custom format where each pixel is
-    /// stored as 3 bytes (r, g, b) and the width and height are stored at the end of
-    /// the file as 8 bytes each.
-    async fn create_canvas(&self, intent: CreateCanvasIntent) -> Result<()> {
-        let mut file = File::create(self.get_filename(&intent.canvas_address))?;
+    /// Drop all the mmaps, so the files get mapped again the next time they're used.
+    /// This is necessary if the files are replaced by something other than this
+    /// storage, e.g. when swapping in a backfill.
+    pub fn clear_cache(&self) {
+        self.mmaps.clear();
+    }
 
-        // Calculate the total number of pixels
-        let num_pixels = intent.width * intent.height;
+    fn get_filename(&self, canvas_address: &Address) -> PathBuf {
+        get_canvas_filename(&self.config.canvas_storage_directory, canvas_address)
+    }
 
-        // Build all the data into a single vector.
-        let mut data = Vec::with_capacity(num_pixels as usize * 3);
-        for _ in 0..num_pixels {
-            data.push(intent.default_color.r);
-            data.push(intent.default_color.g);
-            data.push(intent.default_color.b);
+    /// Get an existing mmap for the canvas file or initialize a new one.
+    fn get_mmap(&self, canvas_address: &Address) -> Result<RefMut<'_, Address, CanvasMmap>> {
+        match self.mmaps.entry(*canvas_address) {
+            Entry::Occupied(entry) => Ok(entry.into_ref()),
+            Entry::Vacant(entry) => {
+                let mmap = self.open_mmap(canvas_address)?;
+                Ok(entry.insert(mmap))
+            },
         }
+    }
 
-        // Put the width and height at the end of the file.
-        data.extend(intent.width.to_le_bytes());
-        data.extend(intent.height.to_le_bytes());
+    /// Like `get_mmap`, but only holds a read lock on the mmap, so readers don't block
+    /// each other. Only reads count towards the render cache metrics.
+    fn get_mmap_for_read(&self, canvas_address: &Address) -> Result<Ref<'_, Address, CanvasMmap>> {
+        if let Some(mmap) = self.mmaps.get(canvas_address) {
+            RENDER_CACHE_LOOKUPS.with_label_values(&["hit"]).inc();
+            return Ok(mmap);
+        }
+        RENDER_CACHE_LOOKUPS.with_label_values(&["miss"]).inc();
+        Ok(self.get_mmap(canvas_address)?.downgrade())
+    }
 
-        // Write the data to the file.
-        file.write_all(&data)?;
+    fn open_mmap(&self, canvas_address: &Address) -> Result<CanvasMmap> {
+        let path = self.get_filename(canvas_address);
+        if self.read_only {
+            return map_read_only(&path);
+        }
 
-        Ok(())
-    }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut mmap = unsafe { MmapMut::map_mut(&file) }.context("Failed to mmap file")?;
 
-    async fn write_pixel(&self, intent: WritePixelIntent) -> Result<()> {
-        // Get an existing mmap for the canvas file or initialize a new one.
-        let mut mmap = self.mmaps.entry(intent.canvas_address).or_insert_with(|| {
+        // If the file is still in the old format, upgrade it before using it.
+        if CanvasHeader::read(&mmap)?.format_version == 0 {
+            info!("Upgrading canvas file {} to current format", path.display());
+            let upgraded = upgrade_legacy_canvas_file(&mmap)?;
+            drop(mmap);
+            write_atomically(&path, &upgraded)?;
             let file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(false)
-                .open(self.get_filename(&intent.canvas_address))
-                .expect("Failed to open file");
-            unsafe { MmapMut::map_mut(&file).expect("Failed to mmap file") }
-        });
-
-        // Write the pixel to the file through the mmap.
-        let index = intent.index as usize;
-        mmap[index * 3] = intent.color.r;
-        mmap[index * 3 + 1] = intent.color.g;
-        mmap[index * 3 + 2] = intent.color.b;
-
-        // TEMPORARY FOR TESTING. Write the data as a png.
-        drop(mmap);
-        let png_bytes = self.get_canvas_as_png(&intent.canvas_address).await?;
-        let mut file = File::create("/tmp/test.png")?;
-        file.write_all(&png_bytes)?;
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let mmap = unsafe { MmapMut::map_mut(&file) }.context("Failed to mmap file")?;
+            return Ok(CanvasMmap::ReadWrite(mmap));
+        }
+
+        // If a previous writer stopped part way through writing to the file, clear its
+        // mark so readers don't wait on it forever. The batch it was writing gets
+        // processed again.
+        end_write(&mut mmap);
+
+        Ok(CanvasMmap::ReadWrite(mmap))
+    }
+
+    /// Read from the canvas file with `read`, making sure what we read matches the
+    /// applied version in the header. If the writer is in the middle of a batch we
+    /// wait for it to finish, see the `format` module.
+    async fn read_consistently<T>(
+        &self,
+        canvas_address: &Address,
+        read: impl Fn(&[u8], &CanvasHeader) -> Result<T>,
+    ) -> Result<(CanvasHeader, T)> {
+        for _ in 0..MAX_READ_ATTEMPTS {
+            // Don't hold on to the mmap while waiting, the writer needs it.
+            {
+                let mmap = self.get_mmap_for_read(canvas_address)?;
+                if let Some(result) = read_consistently(mmap.data(), &read)? {
+                    return Ok(result);
+                }
+            }
+            tokio::time::sleep(READ_RETRY_INTERVAL).await;
+        }
+        anyhow::bail!(
+            "Canvas was still being written to after {} attempts to read it",
+            MAX_READ_ATTEMPTS
+        )
+    }
+
+    /// Read the pixels of the canvas along with the header they were read with.
+    async fn read_canvas(&self, canvas_address: &Address) -> Result<(CanvasHeader, Vec<Color>)> {
+        self.read_consistently(canvas_address, read_pixels).await
+    }
+}
+
+#[async_trait::async_trait]
+impl CanvasStorageTrait for MmapCanvasStorage {
+    /// Create a canvas as a file on disk. See the `format` module for the format.
+    async fn create_canvas(&self, intent: CreateCanvasIntent) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!("Canvas storage is read only");
+        }
+
+        let data = build_canvas_file(intent.width, intent.height, &intent.default_color)?;
+
+        // Write the data to a temporary file first and then move it into place, so
+        // followers never see a partially written canvas.
+        write_atomically(&self.get_filename(&intent.canvas_address), &data)?;
+        self.mmaps.remove(&intent.canvas_address);
 
         Ok(())
     }
 
-    async fn get_canvas_as_png(&self, canvas_address: &Address) -> Result<Vec<u8>> {
-        let mmap = self.mmaps.entry(*canvas_address).or_insert_with(|| {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(false)
-                .open(self.get_filename(canvas_address))
-                .expect("Failed to open file");
-            unsafe { MmapMut::map_mut(&file).expect("Failed to mmap file") }
-        });
-
-        // Get the width and height from the end of the file.
-        let (width, height) =
-            read_width_and_height(&mmap).context("Failed to read width and height")?;
-
-        // Read the data from the file as a vector of Colors.
-        let mut data = Vec::with_capacity((width * height) as usize);
-        for i in 0..width * height {
-            let index = i as usize;
-            data.push(Color {
-                r: mmap[index * 3],
-                g: mmap[index * 3 + 1],
-                b: mmap[index * 3 + 2],
-            });
+    async fn write_pixels(
+        &self,
+        canvas_address: &Address,
+        intents: Vec<WritePixelIntent>,
+    ) -> Result<()> {
+        let mut mmap = self.get_mmap(canvas_address)?;
+        let data = mmap.data_mut()?;
+        let header = CanvasHeader::read(data)?;
+
+        // Check every pixel is in bounds before we start, so we never leave the file
+        // marked as being written to.
+        let offsets = intents
+            .iter()
+            .map(|intent| header.pixel_offset(intent.index))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Write the pixels to the file through the mmap, letting readers know while
+        // we do.
+        begin_write(data)?;
+        for (offset, intent) in offsets.into_iter().zip(intents) {
+            data[offset] = intent.color.r;
+            data[offset + 1] = intent.color.g;
+            data[offset + 2] = intent.color.b;
         }
+        end_write(data);
+
+        Ok(())
+    }
+
+    async fn set_applied_version(&self, canvas_address: &Address, version: u64) -> Result<()> {
+        let mut mmap = self.get_mmap(canvas_address)?;
+        write_applied_version(mmap.data_mut()?, version)?;
+        mmap.flush_async()
+    }
+
+    async fn get_canvas_as_png(&self, canvas_address: &Address) -> Result<CanvasPng> {
+        let (header, pixels) = self.read_canvas(canvas_address).await?;
 
         // Convert the data to a png.
-        let png = get_image(data, width as u32, height as u32)
+        let png = get_image(pixels, header.width as u32, header.height as u32)
+            .context("Failed to convert data to a png")?;
+
+        Ok(CanvasPng {
+            data: png,
+            applied_version: header.applied_version,
+        })
+    }
+
+    async fn get_pixel(&self, canvas_address: &Address, index: u64) -> Result<CanvasPixel> {
+        let (header, color) = self
+            .read_consistently(canvas_address, |data, header| {
+                let offset = header.pixel_offset(index)?;
+                Ok(Color {
+                    r: data[offset],
+                    g: data[offset + 1],
+                    b: data[offset + 2],
+                })
+            })
+            .await?;
+        Ok(CanvasPixel {
+            color,
+            applied_version: header.applied_version,
+        })
+    }
+
+    async fn get_overlay_as_png(
+        &self,
+        canvas_address: &Address,
+        highlighted_pixels: &HashSet<u64>,
+    ) -> Result<CanvasPng> {
+        let (header, pixels) = self.read_canvas(canvas_address).await?;
+        let pixels = highlight_pixels(pixels, highlighted_pixels);
+        let png = get_image(pixels, header.width as u32, header.height as u32)
             .context("Failed to convert data to a png")?;
+        Ok(CanvasPng {
+            data: png,
+            applied_version: header.applied_version,
+        })
+    }
+}
 
-        Ok(png)
+/// Get the path of the file for a canvas.
+pub fn get_canvas_filename(directory: &Path, canvas_address: &Address) -> PathBuf {
+    directory.join(format!("0x{}.canvas", canvas_address.to_canonical_string()))
+}
+
+/// Get the address of a canvas from the path of its file, if it is a canvas file.
+pub fn parse_canvas_filename(path: &Path) -> Option<Address> {
+    if path.extension()? != "canvas" {
+        return None;
     }
+    Address::from_str(path.file_stem()?.to_str()?).ok()
+}
+
+fn map_read_only(path: &Path) -> Result<CanvasMmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mmap = unsafe { Mmap::map(&file) }.context("Failed to mmap file")?;
+    Ok(CanvasMmap::ReadOnly(mmap))
+}
+
+/// Write a file by writing to a temporary file next to it and then moving that into
+/// place, which is atomic.
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("canvas.tmp");
+    let mut file = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to move canvas file into {}", path.display()))?;
+    Ok(())
+}
+
+/// Watch the canvas directory for canvas files being created, replaced, or removed,
+/// and update the mmaps accordingly.
+fn watch_canvas_directory(
+    directory: &Path,
+    mmaps: Arc<DashMap<Address, CanvasMmap>>,
+) -> Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        let event = match result {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Error watching canvas directory: {:#}", e);
+                return;
+            },
+        };
+        let (added, removed): (Vec<&PathBuf>, Vec<&PathBuf>) = match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                (event.paths.iter().collect(), vec![])
+            },
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => (
+                event.paths.get(1).into_iter().collect(),
+                event.paths.first().into_iter().collect(),
+            ),
+            // We don't map newly created files straight away since they might not
+            // have been fully written yet, we just make sure we don't use a stale
+            // mmap and let them be mapped on demand later.
+            EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_)) => (vec![], event.paths.iter().collect()),
+            _ => return,
+        };
+        for path in removed {
+            if let Some(canvas_address) = parse_canvas_filename(path) {
+                mmaps.remove(&canvas_address);
+            }
+        }
+        for path in added {
+            if let Some(canvas_address) = parse_canvas_filename(path) {
+                match map_read_only(path) {
+                    Ok(mmap) => {
+                        info!("Following canvas {}", canvas_address);
+                        mmaps.insert(canvas_address, mmap);
+                    },
+                    Err(e) => warn!("Failed to map canvas file {}: {:#}", path.display(), e),
+                }
+            }
+        }
+    })?;
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
 }
 
-/// Read the width and height from the end of the file.
-fn read_width_and_height(mmap: &MmapMut) -> Result<(u64, u64)> {
-    let len = mmap.len();
-    if len < 16 {
-        anyhow::bail!("File is too short to contain width and height");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_write_pixel_intent(canvas_address: Address, index: u64) -> WritePixelIntent {
+        WritePixelIntent {
+            canvas_address,
+            index,
+            color: Color { r: 255, g: 0, b: 0 },
+        }
     }
 
-    let width_bytes: [u8; 8] = mmap[len - 16..len - 8]
-        .try_into()
-        .context("Failed to read width")?;
-    let height_bytes: [u8; 8] = mmap[len - 8..len]
-        .try_into()
-        .context("Failed to read height")?;
+    #[tokio::test]
+    async fn test_read_while_writing() {
+        let directory =
+            std::env::temp_dir().join(format!("canvas-mid-batch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let config = MmapCanvasStorageConfig {
+            canvas_storage_directory: directory.clone(),
+        };
+        let storage = MmapCanvasStorage::new(config.clone());
+        let canvas_address = Address::from_str("0xc1").unwrap();
+        storage
+            .create_canvas(CreateCanvasIntent {
+                canvas_address,
+                width: 2,
+                height: 2,
+                default_color: Color { r: 0, g: 0, b: 0 },
+            })
+            .await
+            .unwrap();
+        let path = get_canvas_filename(&directory, &canvas_address);
+        let read = || read_consistently(&fs::read(&path).unwrap(), read_pixels).unwrap();
+        let read_colors = || {
+            let (header, pixels) = read().unwrap();
+            let colors: Vec<_> = pixels
+                .iter()
+                .map(|pixel| (pixel.r, pixel.g, pixel.b))
+                .collect();
+            (header.applied_version, colors)
+        };
+        assert_eq!(read_colors(), (0, vec![(0, 0, 0); 4]));
+
+        // A failed write leaves the canvas as it was and readable.
+        assert!(storage
+            .write_pixels(
+                &canvas_address,
+                vec![
+                    build_write_pixel_intent(canvas_address, 0),
+                    build_write_pixel_intent(canvas_address, 4),
+                ]
+            )
+            .await
+            .is_err());
+        assert_eq!(read_colors(), (0, vec![(0, 0, 0); 4]));
 
-    let width = u64::from_le_bytes(width_bytes);
-    let height = u64::from_le_bytes(height_bytes);
+        // While a writer is writing to the canvas, readers in other processes can't
+        // read it.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file) }.unwrap();
+        begin_write(&mut mmap).unwrap();
+        assert!(read().is_none());
 
-    Ok((width, height))
+        // If that writer stops part way through, the next one clears its mark.
+        drop(mmap);
+        let storage = MmapCanvasStorage::new(config);
+        storage
+            .write_pixels(
+                &canvas_address,
+                vec![
+                    build_write_pixel_intent(canvas_address, 0),
+                    build_write_pixel_intent(canvas_address, 1),
+                ],
+            )
+            .await
+            .unwrap();
+        let red = (255, 0, 0);
+        let black = (0, 0, 0);
+        assert_eq!(read_colors(), (0, vec![red, red, black, black]));
+        storage
+            .set_applied_version(&canvas_address, 5)
+            .await
+            .unwrap();
+        assert_eq!(read_colors(), (5, vec![red, red, black, black]));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
@@ -1,18 +1,44 @@
+pub mod format;
+#[cfg(test)]
+mod memory;
 mod mmap;
 mod utils;
 
 use crate::generated::Color;
 use anyhow::Result;
 use aptos_move_graphql_scalars::Address;
-pub use mmap::{MmapCanvasStorage, MmapCanvasStorageConfig};
-use std::fmt::Debug;
+#[cfg(test)]
+pub use memory::MemoryCanvasStorage;
+pub use mmap::{
+    get_canvas_filename, parse_canvas_filename, write_atomically, MmapCanvasStorage,
+    MmapCanvasStorageConfig,
+};
+use std::{collections::HashSet, fmt::Debug};
+pub use utils::get_image;
 
 /// Handles creating, updating, and reading canvases.
 #[async_trait::async_trait]
 pub trait CanvasStorageTrait: Debug + Send + Sync + 'static {
     async fn create_canvas(&self, intent: CreateCanvasIntent) -> Result<()>;
-    async fn write_pixel(&self, intent: WritePixelIntent) -> Result<()>;
-    async fn get_canvas_as_png(&self, canvas_address: &Address) -> Result<Vec<u8>>;
+    /// Write pixels to a canvas, in order. Every intent must be for `canvas_address`.
+    async fn write_pixels(
+        &self,
+        canvas_address: &Address,
+        intents: Vec<WritePixelIntent>,
+    ) -> Result<()>;
+    /// Record that all txns up to and including `version` have been applied to the
+    /// canvas. This should be called after writing all the pixels for a batch.
+    async fn set_applied_version(&self, canvas_address: &Address, version: u64) -> Result<()>;
+    async fn get_canvas_as_png(&self, canvas_address: &Address) -> Result<CanvasPng>;
+    /// Get the current color of a pixel.
+    async fn get_pixel(&self, canvas_address: &Address, index: u64) -> Result<CanvasPixel>;
+    /// Render the canvas as a png with the pixels at `highlighted_pixels` as they are
+    /// and every other pixel faded out.
+    async fn get_overlay_as_png(
+        &self,
+        canvas_address: &Address,
+        highlighted_pixels: &HashSet<u64>,
+    ) -> Result<CanvasPng>;
 }
 
 /// All the information necessary to write a Pixel to storage.
@@ -33,3 +59,19 @@ pub struct CreateCanvasIntent {
     pub height: u64,
     pub default_color: Color,
 }
+
+/// A canvas rendered as a png.
+#[derive(Clone, Debug)]
+pub struct CanvasPng {
+    pub data: Vec<u8>,
+    /// The version of the last txn whose changes are reflected in the image.
+    pub applied_version: u64,
+}
+
+/// A single pixel of a canvas.
+#[derive(Clone, Debug)]
+pub struct CanvasPixel {
+    pub color: Color,
+    /// The version of the last txn whose changes are reflected in the pixel.
+    pub applied_version: u64,
+}
@@ -1,9 +1,11 @@
-use crate::generated::Color;
+use crate::{generated::Color, metrics::PNG_ENCODE_SECONDS};
 use anyhow::{Context, Result};
 use image::{codecs::png::PngEncoder, ColorType, ImageBuffer, ImageEncoder, Rgb};
+use std::collections::HashSet;
 
 /// Convert a vector of Colors to a png.
 pub fn get_image(pixels: Vec<Color>, width: u32, height: u32) -> Result<Vec<u8>> {
+    let _timer = PNG_ENCODE_SECONDS.start_timer();
     let mut image_buffer = ImageBuffer::new(width, height);
 
     for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
@@ -24,3 +26,24 @@ pub fn get_image(pixels: Vec<Color>, width: u32, height: u32) -> Result<Vec<u8>>
 
     Ok(buffer)
 }
+
+/// Keep the pixels at `highlighted_pixels` as they are and fade every other pixel
+/// out to a light grey, so the highlighted pixels stand out.
+pub fn highlight_pixels(pixels: Vec<Color>, highlighted_pixels: &HashSet<u64>) -> Vec<Color> {
+    pixels
+        .into_iter()
+        .enumerate()
+        .map(|(index, color)| {
+            if highlighted_pixels.contains(&(index as u64)) {
+                return color;
+            }
+            let luma = (299 * color.r as u32 + 587 * color.g as u32 + 114 * color.b as u32) / 1000;
+            let faded = ((luma + 3 * 255) / 4) as u8;
+            Color {
+                r: faded,
+                g: faded,
+                b: faded,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,141 @@
+//! Guard against mixing data from different networks, e.g. pointing a mainnet config
+//! at a DB or canvas directory that was built from testnet.
+//!
+//! The first time the processor runs we record the chain ID the txn stream reports,
+//! both in the DB and in a marker file in the canvas directory, since the two can be
+//! pointed at different places independently. On every later run we refuse to start
+//! if either of them disagrees with the stream, unless explicitly told to allow it.
+
+use crate::db_storage::DatabaseStorage;
+use anyhow::{Context, Result};
+use aptos_processor_framework::{
+    indexer_protos::indexer::v1::{raw_data_client::RawDataClient, GetTransactionsRequest},
+    GrpcStreamSubscriberConfig, StorageTrait,
+};
+use std::{fs, path::Path};
+use tracing::{info, warn};
+
+/// The name of the marker file in the canvas directory.
+pub const CHAIN_ID_FILENAME: &str = "chain_id";
+
+/// Ask the txn stream service which chain it is serving txns from.
+pub async fn fetch_stream_chain_id(
+    config: &GrpcStreamSubscriberConfig,
+    processor_name: &str,
+    starting_version: u64,
+) -> Result<u8> {
+    let mut client = RawDataClient::connect(format!(
+        "http://{}",
+        config.indexer_grpc_data_service_address
+    ))
+    .await
+    .context("Failed to connect to txn stream service")?;
+    let mut request = tonic::Request::new(GetTransactionsRequest {
+        starting_version: Some(starting_version),
+        transactions_count: Some(1),
+        batch_size: None,
+    });
+    request.metadata_mut().insert(
+        "x-aptos-data-authorization",
+        config
+            .auth_token
+            .parse()
+            .context("Auth token is not a valid header value")?,
+    );
+    request.metadata_mut().insert(
+        "x-aptos-request-name",
+        processor_name
+            .parse()
+            .context("Processor name is not a valid header value")?,
+    );
+    let response = client
+        .get_transactions(request)
+        .await
+        .context("Failed to request txns from txn stream service")?
+        .into_inner()
+        .message()
+        .await
+        .context("Failed to read from txn stream")?
+        .context("Txn stream ended without sending anything")?;
+    let chain_id = response
+        .chain_id
+        .context("Txn stream service didn't report a chain ID")?;
+    u8::try_from(chain_id).with_context(|| format!("Chain ID {} doesn't fit in a u8", chain_id))
+}
+
+/// Check the chain ID recorded in the DB and the canvas directory against the one the
+/// txn stream reports, recording it wherever it isn't recorded yet. If
+/// `allow_chain_id_change` is set, a mismatch is overwritten rather than an error.
+pub async fn check_chain_id(
+    stream_chain_id: u8,
+    storage: &DatabaseStorage,
+    canvas_directory: &Path,
+    allow_chain_id_change: bool,
+) -> Result<()> {
+    let db_chain_id = storage.read_chain_id().await?;
+    let marker_chain_id = read_chain_id_marker(canvas_directory)?;
+
+    let mut mismatches = Vec::new();
+    if let Some(db_chain_id) = db_chain_id.filter(|id| *id != stream_chain_id) {
+        mismatches.push(format!("the DB is for chain {}", db_chain_id));
+    }
+    if let Some(marker_chain_id) = marker_chain_id.filter(|id| *id != stream_chain_id) {
+        mismatches.push(format!(
+            "the canvas directory is for chain {}",
+            marker_chain_id
+        ));
+    }
+    if !mismatches.is_empty() {
+        let message = format!(
+            "The txn stream is for chain {} but {}",
+            stream_chain_id,
+            mismatches.join(" and ")
+        );
+        if !allow_chain_id_change {
+            anyhow::bail!(
+                "{}. If this is intentional, e.g. you're migrating to a new network, run with --allow-chain-id-change",
+                message
+            );
+        }
+        warn!(
+            "{}, overwriting it since changing the chain ID is allowed",
+            message
+        );
+    }
+
+    if db_chain_id != Some(stream_chain_id) {
+        storage
+            .write_chain_id(stream_chain_id)
+            .await
+            .context("Failed to write chain ID to DB")?;
+    }
+    if marker_chain_id != Some(stream_chain_id) {
+        write_chain_id_marker(canvas_directory, stream_chain_id)?;
+    }
+
+    info!(chain_id = stream_chain_id, "Chain ID matches txn stream");
+
+    Ok(())
+}
+
+/// Read the chain ID from the marker file in the canvas directory, if there is one.
+pub fn read_chain_id_marker(canvas_directory: &Path) -> Result<Option<u8>> {
+    let path = canvas_directory.join(CHAIN_ID_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let chain_id = contents
+        .trim()
+        .parse()
+        .with_context(|| format!("{} doesn't contain a valid chain ID", path.display()))?;
+    Ok(Some(chain_id))
+}
+
+/// Write the chain ID to the marker file in the canvas directory.
+pub fn write_chain_id_marker(canvas_directory: &Path, chain_id: u8) -> Result<()> {
+    let path = canvas_directory.join(CHAIN_ID_FILENAME);
+    fs::write(&path, format!("{}\n", chain_id))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,231 @@
+//! Commands that operate directly on canvas files, without needing the DB or the txn
+//! stream. These are useful for debugging production data.
+
+use crate::{
+    canvas_storage::{
+        format::{read_pixels, CanvasHeader, CURRENT_FORMAT_VERSION},
+        get_image, parse_canvas_filename,
+    },
+    generated::Color,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, clap::Args)]
+pub struct RenderArgs {
+    /// The canvas file to render.
+    pub canvas_file: PathBuf,
+
+    /// Where to write the png.
+    #[clap(long)]
+    pub out: PathBuf,
+
+    /// How many pixels wide and high each pixel of the canvas should be in the png.
+    #[clap(long, default_value_t = 1)]
+    pub scale: u32,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct InspectArgs {
+    /// The canvas file to inspect.
+    pub canvas_file: PathBuf,
+
+    /// How many of the most common colors to show.
+    #[clap(long, default_value_t = 10)]
+    pub top_colors: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct VerifyArgs {
+    /// Canvas files to verify. If a directory is given, all the canvas files in it
+    /// are verified.
+    #[clap(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+/// Render a canvas file as a png, optionally scaling it up.
+pub fn render(args: RenderArgs) -> Result<()> {
+    if args.scale == 0 {
+        anyhow::bail!("--scale must be at least 1");
+    }
+
+    let (header, pixels) = read_canvas_file(&args.canvas_file)?;
+    let width = u32::try_from(header.width).context("Canvas is too wide to render")?;
+    let height = u32::try_from(header.height).context("Canvas is too high to render")?;
+    let scaled_width = width
+        .checked_mul(args.scale)
+        .context("Scaled width is too large")?;
+    let scaled_height = height
+        .checked_mul(args.scale)
+        .context("Scaled height is too large")?;
+
+    // Scale the image up using nearest neighbour, so each pixel of the canvas becomes
+    // a scale x scale block in the png.
+    let mut scaled = Vec::with_capacity(scaled_width as usize * scaled_height as usize);
+    for y in 0..scaled_height {
+        for x in 0..scaled_width {
+            let index = (y / args.scale) * width + (x / args.scale);
+            scaled.push(pixels[index as usize].clone());
+        }
+    }
+
+    let png = get_image(scaled, scaled_width, scaled_height)?;
+    fs::write(&args.out, png)
+        .with_context(|| format!("Failed to write png to {}", args.out.display()))?;
+
+    println!(
+        "Rendered {}x{} canvas as a {}x{} png at {}",
+        width,
+        height,
+        scaled_width,
+        scaled_height,
+        args.out.display()
+    );
+
+    Ok(())
+}
+
+/// Print information about a canvas file.
+pub fn inspect(args: InspectArgs) -> Result<()> {
+    let file_length = fs::metadata(&args.canvas_file)
+        .with_context(|| format!("Failed to read {}", args.canvas_file.display()))?
+        .len();
+    let (header, pixels) = read_canvas_file(&args.canvas_file)?;
+
+    let mut histogram: HashMap<(u8, u8, u8), u64> = HashMap::new();
+    for pixel in &pixels {
+        *histogram.entry((pixel.r, pixel.g, pixel.b)).or_default() += 1;
+    }
+    let mut histogram: Vec<_> = histogram.into_iter().collect();
+    // Most common first, ties broken by color so the output is stable.
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    match parse_canvas_filename(&args.canvas_file) {
+        Some(canvas_address) => println!("Canvas address:   {}", canvas_address),
+        None => println!("Canvas address:   unknown (unexpected filename)"),
+    }
+    println!("File length:      {} bytes", file_length);
+    println!("Format version:   {}", header.format_version);
+    println!("Dimensions:       {}x{}", header.width, header.height);
+    println!("Pixels:           {}", pixels.len());
+    if header.format_version == 0 {
+        println!("Applied version:  unknown (legacy format)");
+    } else {
+        println!("Applied version:  {}", header.applied_version);
+    }
+    println!("Distinct colors:  {}", histogram.len());
+    println!("Most common colors:");
+    for ((r, g, b), count) in histogram.iter().take(args.top_colors) {
+        println!(
+            "  #{:02x}{:02x}{:02x}  {:>10}  {:>6.2}%",
+            r,
+            g,
+            b,
+            count,
+            *count as f64 * 100.0 / pixels.len().max(1) as f64
+        );
+    }
+
+    Ok(())
+}
+
+/// Check the structural integrity of canvas files.
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let mut paths = Vec::new();
+    for path in args.paths {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(&path)
+                .with_context(|| format!("Failed to read directory {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.retain(|path| path.extension().is_some_and(|e| e == "canvas"));
+            entries.sort();
+            paths.extend(entries);
+        } else {
+            paths.push(path);
+        }
+    }
+
+    let mut num_failed = 0;
+    for path in &paths {
+        match verify_canvas_file(path) {
+            Ok(header) if header.format_version != CURRENT_FORMAT_VERSION => println!(
+                "OK    {} (format version {}, the processor will upgrade it when it next opens it)",
+                path.display(),
+                header.format_version
+            ),
+            Ok(_) => println!("OK    {}", path.display()),
+            Err(problems) => {
+                num_failed += 1;
+                println!("FAIL  {}", path.display());
+                for problem in problems {
+                    println!("        {}", problem);
+                }
+            },
+        }
+    }
+
+    if num_failed > 0 {
+        anyhow::bail!(
+            "{} of {} canvas files failed verification",
+            num_failed,
+            paths.len()
+        );
+    }
+
+    println!("All {} canvas files look good", paths.len());
+    Ok(())
+}
+
+/// Returns the header if the file is valid, otherwise a description of every problem
+/// found with the file.
+fn verify_canvas_file(path: &Path) -> Result<CanvasHeader, Vec<String>> {
+    let mut problems = Vec::new();
+
+    if parse_canvas_filename(path).is_none() {
+        problems.push("Filename is not of the form 0x<canvas address>.canvas".to_string());
+    }
+
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            problems.push(format!("Failed to read file: {}", e));
+            return Err(problems);
+        },
+    };
+
+    let header = match CanvasHeader::read(&data) {
+        Ok(header) => header,
+        Err(e) => {
+            problems.push(format!("Failed to read header: {:#}", e));
+            return Err(problems);
+        },
+    };
+
+    if header.width == 0 || header.height == 0 {
+        problems.push(format!(
+            "Dimensions {}x{} are empty",
+            header.width, header.height
+        ));
+    }
+    if let Err(e) = header.check_file_length(data.len()) {
+        problems.push(format!("{:#}", e));
+    }
+
+    if problems.is_empty() {
+        Ok(header)
+    } else {
+        Err(problems)
+    }
+}
+
+fn read_canvas_file(path: &Path) -> Result<(CanvasHeader, Vec<Color>)> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let header = CanvasHeader::read(&data).context("Failed to read header")?;
+    let pixels = read_pixels(&data, &header)?;
+    Ok((header, pixels))
+}
@@ -0,0 +1,10 @@
+//! Subcommands other than `run`. These are generally for operating on the data the
+//! processor produces, e.g. for debugging.
+
+mod canvas_file;
+mod reconcile;
+mod snapshot;
+
+pub use canvas_file::{inspect, render, verify, InspectArgs, RenderArgs, VerifyArgs};
+pub use reconcile::{reconcile, ReconcileArgs};
+pub use snapshot::{export_snapshot, import_snapshot, ExportSnapshotArgs, ImportSnapshotArgs};
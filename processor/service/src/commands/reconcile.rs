@@ -0,0 +1,549 @@
+//! Compare a canvas file against the state of the canvas on chain, as read from the
+//! REST API of a fullnode, and optionally repair any pixels that differ.
+
+use crate::{
+    canvas_storage::{
+        format::{begin_write, end_write, read_pixels, CanvasHeader},
+        get_canvas_filename,
+    },
+    config::Config,
+    db_storage::DatabaseStorage,
+    generated::{Canvas, Color, SmartTableBucket},
+    processor::address_to_string,
+};
+use anyhow::{Context, Result};
+use aptos_move_graphql_scalars::{Address, U64};
+use memmap2::MmapMut;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct ReconcileArgs {
+    /// The config used to run the processor. We use this to find the canvas file
+    /// and the address of the contract of the deployment the canvas belongs to.
+    #[clap(long)]
+    pub config_path: PathBuf,
+
+    /// The URL of the fullnode REST API, e.g. https://fullnode.testnet.aptoslabs.com
+    #[clap(long)]
+    pub node_url: String,
+
+    /// The address of the canvas to reconcile.
+    #[clap(long)]
+    pub canvas_address: String,
+
+    /// By default we compare against the on chain state at the version recorded in
+    /// the canvas file, so any txns the processor hasn't applied yet don't show up as
+    /// mismatches. If set, we compare against the latest on chain state instead.
+    /// This is necessary if the node has pruned the state at the applied version.
+    #[clap(long)]
+    pub latest: bool,
+
+    /// If set, pixels that differ are overwritten with the on chain color. The
+    /// processor should not be running against the canvas directory while doing this.
+    /// This can't be used with --latest, since then we can't tell whether the canvas
+    /// file moved on while we were comparing it.
+    #[clap(long, conflicts_with = "latest")]
+    pub repair: bool,
+
+    /// How many mismatched pixels to print.
+    #[clap(long, default_value_t = 20)]
+    pub max_reported: usize,
+}
+
+/// A pixel where the color in the canvas file differs from the color on chain.
+#[derive(Clone, Debug)]
+pub struct PixelMismatch {
+    pub index: u64,
+    pub local: Color,
+    pub on_chain: Color,
+}
+
+#[derive(Debug)]
+pub struct ReconcileReport {
+    pub canvas_file: PathBuf,
+    /// The version of the on chain state we compared against. None means latest.
+    pub ledger_version: Option<u64>,
+    pub width: u64,
+    pub mismatches: Vec<PixelMismatch>,
+}
+
+pub async fn reconcile(args: ReconcileArgs) -> Result<()> {
+    let config = Config::load(&args.config_path)?;
+    let canvas_address =
+        Address::from_str(&args.canvas_address).context("Invalid canvas address")?;
+    let canvas_file = get_canvas_filename(
+        &config.canvas_storage_config.canvas_storage_directory,
+        &canvas_address,
+    );
+    let client = NodeClient::new(&args.node_url);
+
+    // Find the contract of the deployment the canvas belongs to. If the canvas isn't
+    // in the DB, e.g. because it was created before we recorded canvases there, we
+    // can still manage as long as there is only one deployment.
+    let storage = DatabaseStorage::new_read_only(config.database_storage_config.clone())
+        .await
+        .context("Failed to initialize database storage")?;
+    let canvas = storage
+        .read_canvas(&address_to_string(&canvas_address))
+        .await?;
+    let contract_address = match (
+        canvas,
+        config.canvas_processor_config.deployments.as_slice(),
+    ) {
+        (Some(canvas), _) => canvas.contract_address,
+        (None, [deployment]) => address_to_string(&deployment.contract_address),
+        (None, _) => anyhow::bail!(
+            "Canvas {} is not in the DB, so we don't know which deployment it belongs to",
+            args.canvas_address
+        ),
+    };
+
+    let report = reconcile_canvas(
+        &client,
+        &contract_address,
+        &canvas_address,
+        &canvas_file,
+        args.latest,
+    )
+    .await?;
+
+    match report.ledger_version {
+        Some(version) => println!("Compared against on chain state at version {}", version),
+        None => println!("Compared against latest on chain state"),
+    }
+    println!("Mismatched pixels: {}", report.mismatches.len());
+    for mismatch in report.mismatches.iter().take(args.max_reported) {
+        println!(
+            "  ({}, {})  local #{:02x}{:02x}{:02x}  on chain #{:02x}{:02x}{:02x}",
+            mismatch.index % report.width,
+            mismatch.index / report.width,
+            mismatch.local.r,
+            mismatch.local.g,
+            mismatch.local.b,
+            mismatch.on_chain.r,
+            mismatch.on_chain.g,
+            mismatch.on_chain.b,
+        );
+    }
+    if report.mismatches.len() > args.max_reported {
+        println!("  ...");
+    }
+
+    if report.mismatches.is_empty() {
+        return Ok(());
+    }
+
+    if !args.repair {
+        anyhow::bail!(
+            "Found {} mismatched pixels, run with --repair to fix them",
+            report.mismatches.len()
+        );
+    }
+
+    repair_canvas(&report)?;
+    println!("Repaired {} pixels", report.mismatches.len());
+
+    Ok(())
+}
+
+/// Compare the pixels in the canvas file with the pixels on chain.
+pub async fn reconcile_canvas(
+    client: &NodeClient,
+    contract_address: &str,
+    canvas_address: &Address,
+    canvas_file: &Path,
+    latest: bool,
+) -> Result<ReconcileReport> {
+    let data = std::fs::read(canvas_file)
+        .with_context(|| format!("Failed to read {}", canvas_file.display()))?;
+    let header = CanvasHeader::read(&data).context("Failed to read header")?;
+    let local_pixels = read_pixels(&data, &header)?;
+
+    // Legacy canvas files don't know what version they're at.
+    let ledger_version = if latest || header.applied_version == 0 {
+        None
+    } else {
+        Some(header.applied_version)
+    };
+
+    let canvas = client
+        .get_canvas(contract_address, canvas_address, ledger_version)
+        .await?;
+    if canvas.config.width.0 != header.width || canvas.config.height.0 != header.height {
+        anyhow::bail!(
+            "Canvas is {}x{} on chain but {}x{} locally",
+            canvas.config.width.0,
+            canvas.config.height.0,
+            header.width,
+            header.height
+        );
+    }
+
+    // The pixels SmartTable is a table of buckets, where each bucket is a vector of
+    // entries. We go through every bucket to get all the pixels set on chain.
+    let value_type = format!(
+        "vector<0x1::smart_table::Entry<u64, {}::canvas_token::Color>>",
+        contract_address
+    );
+    let mut on_chain_pixels = HashMap::new();
+    for bucket in 0..canvas.pixels.num_buckets.0 {
+        let entries = client
+            .get_table_bucket(
+                &canvas.pixels.buckets.inner.handle,
+                &value_type,
+                bucket,
+                ledger_version,
+            )
+            .await?;
+        for entry in entries {
+            on_chain_pixels.insert(entry.key.0, entry.value);
+        }
+    }
+
+    let mismatches = diff_pixels(
+        &local_pixels,
+        &on_chain_pixels,
+        &canvas.config.default_color,
+    );
+
+    Ok(ReconcileReport {
+        canvas_file: canvas_file.to_path_buf(),
+        ledger_version,
+        width: header.width,
+        mismatches,
+    })
+}
+
+/// Any pixel not set on chain should be the default color.
+fn diff_pixels(
+    local_pixels: &[Color],
+    on_chain_pixels: &HashMap<u64, Color>,
+    default_color: &Color,
+) -> Vec<PixelMismatch> {
+    let mut mismatches = Vec::new();
+    for (index, local) in local_pixels.iter().enumerate() {
+        let index = index as u64;
+        let on_chain = on_chain_pixels.get(&index).unwrap_or(default_color);
+        if (local.r, local.g, local.b) != (on_chain.r, on_chain.g, on_chain.b) {
+            mismatches.push(PixelMismatch {
+                index,
+                local: local.clone(),
+                on_chain: on_chain.clone(),
+            });
+        }
+    }
+    mismatches
+}
+
+/// Overwrite the mismatched pixels in the canvas file with the on chain colors.
+pub fn repair_canvas(report: &ReconcileReport) -> Result<()> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&report.canvas_file)
+        .with_context(|| format!("Failed to open {}", report.canvas_file.display()))?;
+    let mut mmap = unsafe { MmapMut::map_mut(&file) }.context("Failed to mmap file")?;
+    let header = CanvasHeader::read(&mmap)?;
+
+    // Make sure the processor didn't apply more txns since we compared, otherwise we
+    // might overwrite newer pixels with older ones.
+    if let Some(ledger_version) = report.ledger_version {
+        if header.applied_version != ledger_version {
+            anyhow::bail!(
+                "Canvas file changed from version {} to {} while reconciling, is the processor running?",
+                ledger_version,
+                header.applied_version
+            );
+        }
+    }
+
+    // Check every pixel is in bounds before we start, so we never leave the file
+    // marked as being written to.
+    let offsets = report
+        .mismatches
+        .iter()
+        .map(|mismatch| header.pixel_offset(mismatch.index))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Like the processor, let readers know the pixels are changing, see the format
+    // module. Legacy files have nowhere to record this.
+    let legacy = header.format_version == 0;
+    if !legacy {
+        begin_write(&mut mmap)?;
+    }
+    for (offset, mismatch) in offsets.into_iter().zip(&report.mismatches) {
+        mmap[offset] = mismatch.on_chain.r;
+        mmap[offset + 1] = mismatch.on_chain.g;
+        mmap[offset + 2] = mismatch.on_chain.b;
+    }
+    if !legacy {
+        end_write(&mut mmap);
+    }
+    mmap.flush().context("Failed to flush mmap")?;
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveResource {
+    data: serde_json::Value,
+}
+
+/// A minimal client for the parts of the fullnode REST API we need.
+#[derive(Debug)]
+pub struct NodeClient {
+    client: reqwest::Client,
+    node_url: String,
+}
+
+impl NodeClient {
+    pub fn new(node_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            node_url: node_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn get_canvas(
+        &self,
+        contract_address: &str,
+        canvas_address: &Address,
+        ledger_version: Option<u64>,
+    ) -> Result<Canvas> {
+        let url = format!(
+            "{}/v1/accounts/0x{}/resource/{}::canvas_token::Canvas",
+            self.node_url,
+            canvas_address.to_canonical_string(),
+            contract_address
+        );
+        let mut request = self.client.get(&url);
+        if let Some(ledger_version) = ledger_version {
+            request = request.query(&[("ledger_version", ledger_version)]);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {}", url))?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Failed to get Canvas resource ({}): {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let resource: MoveResource = response
+            .json()
+            .await
+            .context("Failed to parse resource response")?;
+        serde_json::from_value(resource.data).context("Failed to parse Canvas")
+    }
+
+    /// Get the entries in a SmartTable bucket. Returns no entries if the bucket
+    /// doesn't exist.
+    pub async fn get_table_bucket(
+        &self,
+        handle: &Address,
+        value_type: &str,
+        bucket: u64,
+        ledger_version: Option<u64>,
+    ) -> Result<SmartTableBucket<U64, Color>> {
+        let url = format!(
+            "{}/v1/tables/0x{}/item",
+            self.node_url,
+            handle.to_canonical_string()
+        );
+        let mut request = self.client.post(&url).json(&json!({
+            "key_type": "u64",
+            "value_type": value_type,
+            "key": bucket.to_string(),
+        }));
+        if let Some(ledger_version) = ledger_version {
+            request = request.query(&[("ledger_version", ledger_version)]);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {}", url))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(vec![]);
+        }
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Failed to get bucket {} of table 0x{} ({}): {}",
+                bucket,
+                handle.to_canonical_string(),
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+        response
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse bucket {}", bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_storage::format::{build_canvas_file, write_applied_version};
+    use poem::{
+        get, handler,
+        listener::{Acceptor, Listener, TcpListener},
+        post,
+        web::{Json, Path as PoemPath, Query},
+        IntoResponse, Route, Server,
+    };
+    use serde_json::Value;
+
+    const CONTRACT_ADDRESS: &str = "0x123";
+    const TABLE_HANDLE: &str = "0x00000000000000000000000000000000000000000000000000000000000000aa";
+
+    #[derive(Deserialize)]
+    struct LedgerVersion {
+        ledger_version: Option<u64>,
+    }
+
+    #[handler]
+    fn mock_get_resource(
+        PoemPath((_address, _resource_type)): PoemPath<(String, String)>,
+        Query(query): Query<LedgerVersion>,
+    ) -> Json<Value> {
+        assert_eq!(query.ledger_version, Some(100));
+        let empty_set = json!({ "data": [] });
+        Json(json!({
+            "type": format!("{}::canvas_token::Canvas", CONTRACT_ADDRESS),
+            "data": {
+                "config": {
+                    "width": "3",
+                    "height": "2",
+                    "per_account_timeout_s": "0",
+                    "can_draw_for_s": "0",
+                    "palette": [],
+                    "cost": "0",
+                    "funds_recipient": { "vec": [] },
+                    "default_color": { "r": 255, "g": 255, "b": 255 },
+                    "owner_is_super_admin": false,
+                },
+                "pixels": {
+                    "buckets": { "inner": { "handle": TABLE_HANDLE }, "length": "2" },
+                    "num_buckets": "2",
+                    "level": 1,
+                    "size": "3",
+                    "split_load_threshold": 75,
+                    "target_bucket_size": "100",
+                },
+                "last_contribution_s": {
+                    "buckets": { "inner": { "handle": "0xbb" }, "length": "1" },
+                    "num_buckets": "1",
+                    "level": 0,
+                    "size": "0",
+                    "split_load_threshold": 75,
+                    "target_bucket_size": "100",
+                },
+                "allowlisted_artists": empty_set,
+                "blocklisted_artists": empty_set,
+                "admins": empty_set,
+                "created_at_s": "1690000000",
+                "extend_ref": { "self": "0x456" },
+                "mutator_ref": { "self": "0x456" },
+            },
+        }))
+    }
+
+    #[handler]
+    fn mock_get_table_item(Json(body): Json<Value>) -> poem::Response {
+        let entries = match body["key"].as_str().unwrap() {
+            "0" => json!([
+                { "hash": "1", "key": "0", "value": { "r": 255, "g": 0, "b": 0 } },
+                { "hash": "2", "key": "4", "value": { "r": 0, "g": 0, "b": 255 } },
+            ]),
+            "1" => json!([
+                { "hash": "3", "key": "1", "value": { "r": 255, "g": 255, "b": 255 } },
+            ]),
+            _ => return poem::http::StatusCode::NOT_FOUND.into_response(),
+        };
+        Json(entries).into_response()
+    }
+
+    async fn start_mock_node() -> String {
+        let acceptor = TcpListener::bind("127.0.0.1:0")
+            .into_acceptor()
+            .await
+            .unwrap();
+        let port = acceptor.local_addr()[0].as_socket_addr().unwrap().port();
+        let app = Route::new()
+            .at(
+                "/v1/accounts/:address/resource/:resource_type",
+                get(mock_get_resource),
+            )
+            .at("/v1/tables/:handle/item", post(mock_get_table_item));
+        tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_and_repair() {
+        let node_url = start_mock_node().await;
+        let client = NodeClient::new(&node_url);
+        let canvas_address = Address::from_str("0x456").unwrap();
+
+        // Build a local canvas where pixel 0 is right, pixel 4 was missed, and
+        // pixel 5 has a color that isn't on chain.
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        let mut data = build_canvas_file(3, 2, &white).unwrap();
+        write_applied_version(&mut data, 100).unwrap();
+        let header = CanvasHeader::read(&data).unwrap();
+        let offset = header.pixel_offset(0).unwrap();
+        data[offset..offset + 3].copy_from_slice(&[255, 0, 0]);
+        let offset = header.pixel_offset(5).unwrap();
+        data[offset..offset + 3].copy_from_slice(&[0, 255, 0]);
+
+        let directory = std::env::temp_dir().join(format!("reconcile-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let canvas_file = get_canvas_filename(&directory, &canvas_address);
+        std::fs::write(&canvas_file, &data).unwrap();
+
+        let report = reconcile_canvas(
+            &client,
+            CONTRACT_ADDRESS,
+            &canvas_address,
+            &canvas_file,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.ledger_version, Some(100));
+        let mismatched: Vec<_> = report
+            .mismatches
+            .iter()
+            .map(|m| (m.index, m.on_chain.r, m.on_chain.g, m.on_chain.b))
+            .collect();
+        assert_eq!(mismatched, vec![(4, 0, 0, 255), (5, 255, 255, 255)]);
+
+        repair_canvas(&report).unwrap();
+        let report = reconcile_canvas(
+            &client,
+            CONTRACT_ADDRESS,
+            &canvas_address,
+            &canvas_file,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(report.mismatches.is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
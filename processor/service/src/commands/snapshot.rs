@@ -0,0 +1,569 @@
+//! Export everything the processor has built up (the canvas files and the DB) into a
+//! single archive, and import it somewhere else. This lets a new node start from a
+//! recent point rather than replaying all the txns since the contract was deployed.
+//!
+//! The archive is a gzipped tarball that looks like this:
+//!
+//! ```text
+//! tables/<table name>.jsonl
+//! canvases/0x<address>.canvas
+//! manifest.json
+//! ```
+//!
+//! Each table is stored as JSON Lines, one row per line, so tables can be written
+//! and read a page at a time rather than all at once. The manifest says which
+//! version of the snapshot format the archive uses, what chain it came from and what
+//! version it was taken at, and the length and SHA-256 checksum of every other file
+//! in the archive. It comes last since the checksums are worked out as the other
+//! files are written.
+
+use crate::{
+    canvas_storage::{format::CanvasHeader, parse_canvas_filename, write_atomically},
+    config::Config,
+    db_storage::{DatabaseStorage, TableSink},
+    processor::CANVAS_PROCESSOR_NAME,
+};
+use anyhow::{Context, Result};
+use aptos_processor_framework::StorageTrait;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The version of the snapshot format that we write. Bump this whenever the layout
+/// of the archive or the manifest changes in a way older versions can't read.
+const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+
+/// How many rows we insert at a time when importing a table.
+const IMPORT_PAGE_SIZE: usize = 1000;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const CANVASES_DIRECTORY: &str = "canvases";
+const TABLES_DIRECTORY: &str = "tables";
+
+#[derive(Debug, clap::Args)]
+pub struct ExportSnapshotArgs {
+    #[clap(long)]
+    pub config_path: PathBuf,
+
+    /// Where to write the archive, e.g. /tmp/canvas-snapshot.tar.gz
+    #[clap(long)]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ImportSnapshotArgs {
+    #[clap(long)]
+    pub config_path: PathBuf,
+
+    /// The archive to import.
+    pub archive: PathBuf,
+
+    /// By default we refuse to import into a DB or canvas directory that already has
+    /// data in it. If set, the existing data is replaced instead.
+    #[clap(long)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SnapshotManifest {
+    pub format_version: u32,
+    pub created_at_unix_s: u64,
+    pub chain_id: Option<u8>,
+    /// The last version the processor processed when the snapshot was taken. Once
+    /// imported the processor carries on from the version after this.
+    pub last_processed_version: Option<u64>,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SnapshotFile {
+    /// The path of the file within the archive.
+    pub path: String,
+    pub length: u64,
+    /// Hex encoded SHA-256 of the file.
+    pub sha256: String,
+}
+
+/// Writes the files of a snapshot into the archive as they are exported, keeping
+/// track of the length and checksum of each for the manifest.
+struct SnapshotWriter {
+    builder: tar::Builder<GzEncoder<File>>,
+    mtime: u64,
+    /// The length of a file has to be known before it can be added to the archive, so
+    /// the rows of each table are written here first.
+    spool_path: PathBuf,
+    spool: Option<HashingStream<BufWriter<File>>>,
+    files: Vec<SnapshotFile>,
+}
+
+impl SnapshotWriter {
+    fn append(&mut self, path: String, length: u64, sha256: String, data: impl Read) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(length);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        header.set_cksum();
+        self.builder
+            .append_data(&mut header, &path, data)
+            .with_context(|| format!("Failed to add {} to archive", path))?;
+        self.files.push(SnapshotFile {
+            path,
+            length,
+            sha256,
+        });
+        Ok(())
+    }
+
+    fn spool(&mut self) -> Result<&mut HashingStream<BufWriter<File>>> {
+        if self.spool.is_none() {
+            let file = File::create(&self.spool_path)
+                .with_context(|| format!("Failed to create {}", self.spool_path.display()))?;
+            self.spool = Some(HashingStream::new(BufWriter::new(file)));
+        }
+        Ok(self.spool.as_mut().unwrap())
+    }
+}
+
+impl TableSink for SnapshotWriter {
+    fn write_rows(&mut self, table_name: &str, rows: Vec<Value>) -> Result<()> {
+        let spool = self.spool()?;
+        for row in rows {
+            serde_json::to_writer(&mut *spool, &row)
+                .map_err(io::Error::from)
+                .and_then(|()| spool.write_all(b"\n"))
+                .with_context(|| format!("Failed to write {}", table_name))?;
+        }
+        Ok(())
+    }
+
+    fn finish_table(&mut self, table_name: &str) -> Result<()> {
+        self.spool()?;
+        let (spool, length, sha256) = self.spool.take().unwrap().finish();
+        spool
+            .into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed to write {}", table_name))?;
+        let file = File::open(&self.spool_path)
+            .with_context(|| format!("Failed to open {}", self.spool_path.display()))?;
+        self.append(
+            format!("{}/{}.jsonl", TABLES_DIRECTORY, table_name),
+            length,
+            sha256,
+            BufReader::new(file),
+        )?;
+        fs::remove_file(&self.spool_path)
+            .with_context(|| format!("Failed to remove {}", self.spool_path.display()))
+    }
+}
+
+/// Passes reads or writes through to the inner reader or writer, keeping track of
+/// the length and SHA-256 checksum of everything that went through.
+struct HashingStream<T> {
+    inner: T,
+    hasher: Sha256,
+    length: u64,
+}
+
+impl<T> HashingStream<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            length: 0,
+        }
+    }
+
+    /// Returns the inner reader or writer, the length and the hex encoded checksum.
+    fn finish(self) -> (T, u64, String) {
+        (self.inner, self.length, hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl<T: Read> Read for HashingStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.length += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for HashingStream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.length += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Export the canvas files and the DB to a snapshot archive. The processor should
+/// not be running while doing this, we check that it didn't process anything while
+/// we were exporting to make sure the snapshot is consistent.
+pub async fn export_snapshot(args: ExportSnapshotArgs) -> Result<()> {
+    let config = Config::load(&args.config_path)?;
+    let storage = DatabaseStorage::new_read_only(config.database_storage_config.clone())
+        .await
+        .context("Failed to initialize database storage")?;
+
+    let last_processed_version = storage
+        .read_last_processed_version(CANVAS_PROCESSOR_NAME)
+        .await?;
+    let chain_id = storage.read_chain_id().await?;
+
+    let created_at_unix_s = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    // Write the archive to a temporary file and move it into place once complete, so
+    // we never leave a truncated archive behind.
+    let tmp_path = args.out.with_extension("tmp");
+    let file = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    let mut writer = SnapshotWriter {
+        builder: tar::Builder::new(GzEncoder::new(file, Compression::default())),
+        mtime: created_at_unix_s,
+        spool_path: args.out.with_extension("table.tmp"),
+        spool: None,
+        files: Vec::new(),
+    };
+    storage.export_tables(&mut writer).await?;
+    let mut canvas_count = 0;
+    for path in list_canvas_files(&config.canvas_storage_config.canvas_storage_directory)? {
+        let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        CanvasHeader::read(&data)
+            .and_then(|header| header.check_file_length(data.len()))
+            .with_context(|| format!("Canvas file {} is invalid", path.display()))?;
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        writer.append(
+            format!("{}/{}", CANVASES_DIRECTORY, file_name),
+            data.len() as u64,
+            sha256(&data),
+            data.as_slice(),
+        )?;
+        canvas_count += 1;
+    }
+
+    if storage
+        .read_last_processed_version(CANVAS_PROCESSOR_NAME)
+        .await?
+        != last_processed_version
+    {
+        anyhow::bail!(
+            "The processor processed more txns while exporting, stop it before exporting a snapshot"
+        );
+    }
+
+    let manifest = SnapshotManifest {
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        created_at_unix_s,
+        chain_id,
+        last_processed_version,
+        files: std::mem::take(&mut writer.files),
+    };
+    let manifest_data = serde_json::to_vec_pretty(&manifest)?;
+    writer.append(
+        MANIFEST_PATH.to_string(),
+        manifest_data.len() as u64,
+        sha256(&manifest_data),
+        manifest_data.as_slice(),
+    )?;
+    let file = writer
+        .builder
+        .into_inner()
+        .context("Failed to finish archive")?
+        .finish()
+        .context("Failed to finish compressing archive")?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &args.out)
+        .with_context(|| format!("Failed to move archive to {}", args.out.display()))?;
+
+    println!(
+        "Exported snapshot at version {} with {} canvases to {}",
+        display_version(manifest.last_processed_version),
+        canvas_count,
+        args.out.display()
+    );
+
+    Ok(())
+}
+
+/// Import a snapshot archive into the DB and canvas directory from the config. The
+/// whole archive is checked against the manifest before anything is written, then
+/// read again to import it, so it never has to be held in memory.
+pub async fn import_snapshot(args: ImportSnapshotArgs) -> Result<()> {
+    let config = Config::load(&args.config_path)?;
+    let canvas_directory = &config.canvas_storage_config.canvas_storage_directory;
+
+    let manifest = check_archive(&args.archive)?;
+
+    let storage = DatabaseStorage::new(config.database_storage_config.clone())
+        .await
+        .context("Failed to initialize database storage")?;
+
+    if let (Some(existing), Some(snapshot)) = (storage.read_chain_id().await?, manifest.chain_id) {
+        if existing != snapshot {
+            anyhow::bail!(
+                "The snapshot is from chain {} but the DB is for chain {}",
+                snapshot,
+                existing
+            );
+        }
+    }
+
+    fs::create_dir_all(canvas_directory)
+        .with_context(|| format!("Failed to create {}", canvas_directory.display()))?;
+    let existing_canvas_files = list_canvas_files(canvas_directory)?;
+    if !args.overwrite {
+        if let Some(version) = storage
+            .read_last_processed_version(CANVAS_PROCESSOR_NAME)
+            .await?
+        {
+            anyhow::bail!(
+                "The DB has already processed up to version {}, use --overwrite to replace it",
+                version
+            );
+        }
+        if !existing_canvas_files.is_empty() {
+            anyhow::bail!(
+                "{} already contains canvas files, use --overwrite to replace them",
+                canvas_directory.display()
+            );
+        }
+    }
+
+    // Nothing is committed to the DB until the canvas files have all been written,
+    // so if we fail part way through the DB doesn't claim we've processed txns that
+    // aren't in the canvases.
+    let import = storage
+        .import_tables()
+        .await
+        .context("Failed to start importing tables")?;
+    let mut imported_files = Vec::new();
+    let mut imported_canvas_files = Vec::new();
+    let mut archive = open_archive(&args.archive)?;
+    for entry in archive.entries().context("Failed to read archive")? {
+        let mut entry = entry.context("Failed to read archive entry")?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        if !entry.header().entry_type().is_file() || entry_path == MANIFEST_PATH {
+            continue;
+        }
+        let mut reader = HashingStream::new(&mut entry);
+        if let Some(file_name) = entry_path.strip_prefix(&format!("{}/", CANVASES_DIRECTORY)) {
+            let mut data = Vec::new();
+            reader
+                .read_to_end(&mut data)
+                .with_context(|| format!("Failed to read {} from archive", entry_path))?;
+            let path = canvas_directory.join(file_name);
+            write_atomically(&path, &data)?;
+            imported_canvas_files.push(path);
+        } else if let Some(table_name) = entry_path
+            .strip_prefix(&format!("{}/", TABLES_DIRECTORY))
+            .and_then(|file_name| file_name.strip_suffix(".jsonl"))
+        {
+            let mut rows = Vec::new();
+            for line in BufReader::new(&mut reader).lines() {
+                let line =
+                    line.with_context(|| format!("Failed to read {} from archive", entry_path))?;
+                rows.push(
+                    serde_json::from_str(&line)
+                        .with_context(|| format!("Failed to parse {}", entry_path))?,
+                );
+                if rows.len() == IMPORT_PAGE_SIZE {
+                    import.write_rows(table_name, &rows).await?;
+                    rows.clear();
+                }
+            }
+            if !rows.is_empty() {
+                import.write_rows(table_name, &rows).await?;
+            }
+        }
+        let (_, length, sha256) = reader.finish();
+        imported_files.push(SnapshotFile {
+            path: entry_path,
+            length,
+            sha256,
+        });
+    }
+    // We checked the archive before starting, make sure it didn't change since.
+    let problems = check_files(&manifest, &imported_files);
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Snapshot changed while importing it:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+
+    for path in existing_canvas_files {
+        if !imported_canvas_files.contains(&path) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    import.commit().await.context("Failed to import tables")?;
+
+    println!(
+        "Imported snapshot at version {} with {} canvases",
+        display_version(manifest.last_processed_version),
+        imported_canvas_files.len()
+    );
+
+    Ok(())
+}
+
+fn open_archive(path: &Path) -> Result<tar::Archive<GzDecoder<File>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(tar::Archive::new(GzDecoder::new(file)))
+}
+
+/// Read through the whole archive and check every file against the manifest,
+/// without keeping anything other than the manifest in memory.
+fn check_archive(path: &Path) -> Result<SnapshotManifest> {
+    let mut archive = open_archive(path)?;
+    let mut manifest_data = None;
+    let mut files = Vec::new();
+    for entry in archive.entries().context("Failed to read archive")? {
+        let mut entry = entry.context("Failed to read archive entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        if entry_path == MANIFEST_PATH {
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .with_context(|| format!("Failed to read {} from archive", entry_path))?;
+            manifest_data = Some(data);
+            continue;
+        }
+        let mut reader = HashingStream::new(&mut entry);
+        io::copy(&mut reader, &mut io::sink())
+            .with_context(|| format!("Failed to read {} from archive", entry_path))?;
+        let (_, length, sha256) = reader.finish();
+        files.push(SnapshotFile {
+            path: entry_path,
+            length,
+            sha256,
+        });
+    }
+
+    let manifest: SnapshotManifest =
+        serde_json::from_slice(&manifest_data.context("Archive has no manifest")?)
+            .context("Failed to parse manifest")?;
+    if manifest.format_version != SNAPSHOT_FORMAT_VERSION {
+        anyhow::bail!(
+            "Unsupported snapshot format version {}, expected {}",
+            manifest.format_version,
+            SNAPSHOT_FORMAT_VERSION
+        );
+    }
+
+    let problems = check_files(&manifest, &files);
+    if !problems.is_empty() {
+        anyhow::bail!("Snapshot is invalid:\n  {}", problems.join("\n  "));
+    }
+
+    Ok(manifest)
+}
+
+/// Compare the files found in an archive with those in its manifest, returning a
+/// description of every difference.
+fn check_files(manifest: &SnapshotManifest, files: &[SnapshotFile]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for snapshot_file in &manifest.files {
+        let file = match files.iter().find(|file| file.path == snapshot_file.path) {
+            Some(file) => file,
+            None => {
+                problems.push(format!("{} is missing", snapshot_file.path));
+                continue;
+            },
+        };
+        if file.length != snapshot_file.length {
+            problems.push(format!(
+                "{} is {} bytes but should be {} bytes",
+                snapshot_file.path, file.length, snapshot_file.length
+            ));
+        } else if file.sha256 != snapshot_file.sha256 {
+            problems.push(format!("{} has the wrong checksum", snapshot_file.path));
+        }
+        if !is_valid_snapshot_path(&snapshot_file.path) {
+            problems.push(format!("{} is not a valid path", snapshot_file.path));
+        }
+    }
+    for file in files {
+        if !manifest
+            .files
+            .iter()
+            .any(|snapshot_file| snapshot_file.path == file.path)
+        {
+            problems.push(format!("{} is not in the manifest", file.path));
+        }
+    }
+    problems
+}
+
+/// Make sure we only ever write canvas files into the canvas directory and tables
+/// we know the name of.
+fn is_valid_snapshot_path(path: &str) -> bool {
+    let Some((directory, file_name)) = path.split_once('/') else {
+        return false;
+    };
+    if Path::new(file_name).components().count() != 1 {
+        return false;
+    }
+    match directory {
+        CANVASES_DIRECTORY => parse_canvas_filename(Path::new(file_name)).is_some(),
+        TABLES_DIRECTORY => file_name
+            .strip_suffix(".jsonl")
+            .is_some_and(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')),
+        _ => false,
+    }
+}
+
+fn list_canvas_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    if !directory.exists() {
+        return Ok(vec![]);
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+        .with_context(|| format!("Failed to read {}", directory.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| parse_canvas_filename(path).is_some());
+    paths.sort();
+    Ok(paths)
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn display_version(version: Option<u64>) -> String {
+    version.map_or_else(|| "none".to_string(), |version| version.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_snapshot_path() {
+        assert!(is_valid_snapshot_path("tables/pixel_history.jsonl"));
+        assert!(is_valid_snapshot_path("canvases/0x1.canvas"));
+        assert!(!is_valid_snapshot_path("manifest.json"));
+        assert!(!is_valid_snapshot_path("tables/../pixel_history.jsonl"));
+        assert!(!is_valid_snapshot_path("canvases/../0x1.canvas"));
+        assert!(!is_valid_snapshot_path("canvases/nested/0x1.canvas"));
+        assert!(!is_valid_snapshot_path("canvases//0x1.canvas"));
+        assert!(!is_valid_snapshot_path("other/0x1.canvas"));
+    }
+}
@@ -1,19 +1,98 @@
 use crate::{
-    api::ApiConfig, canvas_storage::MmapCanvasStorageConfig, db_storage::PostgresStorageConfig,
+    api::ApiConfig,
+    backfill::BackfillConfig,
+    canvas_storage::MmapCanvasStorageConfig,
+    commands::{
+        ExportSnapshotArgs, ImportSnapshotArgs, InspectArgs, ReconcileArgs, RenderArgs, VerifyArgs,
+    },
+    db_storage::DatabaseStorageConfig,
+    finaliser::FinaliserConfig,
     processor::CanvasProcessorConfig,
 };
 use anyhow::Context as AnyhowContext;
 use aptos_processor_framework::{
     CommonStorageConfig, DispatcherConfig, GrpcStreamSubscriberConfig,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Parser)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the processor and / or the API.
+    Run(RunArgs),
+    /// Render a canvas file as a png.
+    Render(RenderArgs),
+    /// Print information about a canvas file, e.g. its dimensions and colors.
+    Inspect(InspectArgs),
+    /// Check the structural integrity of canvas files.
+    Verify(VerifyArgs),
+    /// Compare a canvas file against the canvas on chain and optionally repair it.
+    Reconcile(ReconcileArgs),
+    /// Export the canvas files and the DB to a snapshot archive.
+    ExportSnapshot(ExportSnapshotArgs),
+    /// Import a snapshot archive made with `export-snapshot`.
+    ImportSnapshot(ImportSnapshotArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RunArgs {
     #[clap(long)]
     pub config_path: PathBuf,
+
+    /// Which components to run. There should only ever be a single instance running
+    /// the processor for a given DB and canvas directory, but it is fine to run
+    /// multiple instances that just run the API.
+    #[clap(long, value_enum, default_value_t = RunMode::All)]
+    pub mode: RunMode,
+
+    /// If set, as well as processing txns as usual, backfill from this version into
+    /// the staging storage in `backfill_config`, then swap it in once it has caught
+    /// up with the live processor. See the `backfill` module for more.
+    #[clap(long)]
+    pub backfill_start_version: Option<u64>,
+
+    /// The last version of the range to backfill. By default this is the last version
+    /// the processor processed.
+    #[clap(long, requires = "backfill_start_version")]
+    pub backfill_end_version: Option<u64>,
+
+    /// By default we refuse to start if the chain ID recorded in the DB or the canvas
+    /// directory differs from the one the txn stream reports. If set, the recorded
+    /// chain ID is overwritten instead. This is only meant for deliberate migrations.
+    #[clap(long)]
+    pub allow_chain_id_change: bool,
+
+    /// If set, every batch of txns received from the txn stream is also written to
+    /// this directory, so it can be replayed later with `--replay-stream`.
+    #[clap(long)]
+    pub record_stream: Option<PathBuf>,
+
+    /// If set, rather than connecting to the txn stream service, replay the txns
+    /// recorded in this directory with `--record-stream`.
+    #[clap(long, conflicts_with_all = ["record_stream", "backfill_start_version"])]
+    pub replay_stream: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum RunMode {
+    /// Run both the processor and the API.
+    All,
+    /// Only run the API. This only reads from storage, never writes to it.
+    Api,
+    /// Only run the processor, which ingests txns and writes to storage.
+    Processor,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -23,27 +102,118 @@ pub struct Config {
     pub common_storage_config: CommonStorageConfig,
     pub canvas_processor_config: CanvasProcessorConfig,
     pub canvas_storage_config: MmapCanvasStorageConfig,
-    pub postgres_storage_config: PostgresStorageConfig,
+    // We accept the old name for this key for the sake of existing configs.
+    #[serde(alias = "postgres_storage_config")]
+    pub database_storage_config: DatabaseStorageConfig,
     pub api_config: ApiConfig,
+    /// Only necessary when backfilling.
+    pub backfill_config: Option<BackfillConfig>,
+    /// How closed canvases are finalised, see `finaliser`.
+    #[serde(default)]
+    pub finaliser_config: FinaliserConfig,
 }
 
-impl TryFrom<Args> for Config {
-    type Error = anyhow::Error;
-
-    fn try_from(args: Args) -> Result<Self, Self::Error> {
-        let file = File::open(&args.config_path).with_context(|| {
-            format!(
-                "Failed to load config at {}",
-                args.config_path.to_string_lossy()
-            )
+impl Config {
+    pub fn load(config_path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(config_path).with_context(|| {
+            format!("Failed to load config at {}", config_path.to_string_lossy())
         })?;
         let reader = BufReader::new(file);
         let run_config: Config = serde_yaml::from_reader(reader).with_context(|| {
             format!(
                 "Failed to parse config at {}",
-                args.config_path.to_string_lossy()
+                config_path.to_string_lossy()
             )
         })?;
         Ok(run_config)
     }
+
+    /// Check the config for problems that serde can't catch, e.g. duplicate
+    /// deployments. Rather than stopping at the first problem, this reports all of
+    /// them at once.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+
+        let deployments = &self.canvas_processor_config.deployments;
+        if deployments.is_empty() {
+            problems.push("canvas_processor_config must have at least one deployment".to_string());
+        }
+        let mut labels = HashSet::new();
+        let mut contract_addresses = HashSet::new();
+        for deployment in deployments {
+            if deployment.label.is_empty() {
+                problems.push(format!(
+                    "The deployment of {} has an empty label",
+                    deployment.contract_address
+                ));
+            } else if !labels.insert(deployment.label.as_str()) {
+                problems.push(format!(
+                    "There is more than one deployment with the label {}",
+                    deployment.label
+                ));
+            }
+            if !contract_addresses.insert(deployment.contract_address) {
+                problems.push(format!(
+                    "There is more than one deployment of {}",
+                    deployment.contract_address
+                ));
+            }
+        }
+
+        let canvas_directory = &self.canvas_storage_config.canvas_storage_directory;
+        if !canvas_directory.is_dir() {
+            problems.push(format!(
+                "canvas_storage_directory {} is not a directory",
+                canvas_directory.display()
+            ));
+        }
+
+        let connection_string = &self.database_storage_config.connection_string;
+        if let Err(e) = validate_connection_string(connection_string) {
+            problems.push(format!("database_storage_config: {}", e));
+        }
+
+        if let Some(backfill_config) = &self.backfill_config {
+            if &backfill_config.staging_canvas_storage_directory == canvas_directory {
+                problems.push(
+                    "backfill_config must use a different canvas directory to the live one"
+                        .to_string(),
+                );
+            }
+            let staging_connection_string = &backfill_config
+                .staging_database_storage_config
+                .connection_string;
+            if let Err(e) = validate_connection_string(staging_connection_string) {
+                problems.push(format!(
+                    "backfill_config.staging_database_storage_config: {}",
+                    e
+                ));
+            }
+            if staging_connection_string == connection_string {
+                problems
+                    .push("backfill_config must use a different DB to the live one".to_string());
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid config:\n  - {}", problems.join("\n  - "));
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_connection_string(connection_string: &str) -> anyhow::Result<()> {
+    let scheme = connection_string
+        .split_once("://")
+        .map(|(scheme, _)| scheme)
+        .or_else(|| connection_string.strip_prefix("sqlite:").map(|_| "sqlite"))
+        .context("Connection string has no scheme")?;
+    match scheme {
+        "postgres" | "postgresql" | "sqlite" => Ok(()),
+        other => anyhow::bail!(
+            "Unsupported scheme {}, expected postgres, postgresql, or sqlite",
+            other
+        ),
+    }
 }
@@ -0,0 +1,2073 @@
+use anyhow::{Context, Result};
+use aptos_processor_framework::StorageTrait;
+use entities::{
+    canvas_artist_lists, canvas_finalisations, canvases, chain_id, contribution_timeouts,
+    contributions, funds_recipient_changes, last_processed_version, ownership_changes, payments,
+    pixel_history, pixel_writers, processor_status, quarantined_transactions,
+};
+use migrations::{Migrator, MigratorTrait};
+use sea_orm::{
+    sea_query::{Alias, Expr, OnConflict, Query, SelectStatement, Table},
+    AccessMode, ActiveModelTrait, ColumnTrait, ConnectOptions, ConnectionTrait, Database,
+    DatabaseConnection, DatabaseTransaction, DbBackend, EntityTrait, FromQueryResult,
+    IntoActiveModel, IsolationLevel, Iterable, PaginatorTrait, PrimaryKeyToColumn, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Schema, TransactionTrait,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+
+/// The values of `canvas_artist_lists::Model::list`.
+const ALLOWLIST: &str = "allowlist";
+const BLOCKLIST: &str = "blocklist";
+
+/// How many rows we read or write at a time when exporting or importing tables.
+const TABLE_PAGE_SIZE: u64 = 1000;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DatabaseStorageConfig {
+    /// Either a Postgres connection string, e.g. `postgres://user:@localhost/canvas`,
+    /// or a SQLite one, e.g. `sqlite:///tmp/canvas.db`. If the SQLite database file
+    /// doesn't exist yet it will be created.
+    pub connection_string: String,
+}
+
+/// Storage backed by a SQL database. Which database is used is determined by the
+/// scheme of the connection string, we support Postgres and SQLite.
+#[derive(Debug)]
+pub struct DatabaseStorage {
+    connection: DatabaseConnection,
+}
+
+impl DatabaseStorage {
+    pub async fn new(config: DatabaseStorageConfig) -> Result<Self> {
+        // Build the DB connection.
+        let connection = Database::connect(build_connect_options(&config.connection_string, false))
+            .await
+            .context("Failed to connect to DB")?;
+
+        // Apply migrations if necessary.
+        Migrator::up(&connection, None)
+            .await
+            .context("Failed to apply migrations")?;
+
+        info!(
+            backend = ?connection.get_database_backend(),
+            "Built database storage"
+        );
+
+        Ok(Self { connection })
+    }
+
+    /// Build storage for an instance that only reads from the DB, e.g. an API
+    /// replica. Rather than applying migrations, which is left to the processor, this
+    /// just checks they have all been applied, so it works with a read only DB user.
+    pub async fn new_read_only(config: DatabaseStorageConfig) -> Result<Self> {
+        let connection = Database::connect(build_connect_options(&config.connection_string, true))
+            .await
+            .context("Failed to connect to DB")?;
+
+        // We don't use `Migrator::get_pending_migrations` since it creates the
+        // migrations table if it doesn't exist.
+        let query = Query::select()
+            .column(Alias::new("version"))
+            .from(Migrator::migration_table_name())
+            .to_owned();
+        let applied = connection
+            .query_all(connection.get_database_backend().build(&query))
+            .await
+            .context("Failed to read applied migrations, has the processor run against this DB?")?
+            .into_iter()
+            .map(|row| row.try_get::<String>("", "version"))
+            .collect::<Result<HashSet<_>, _>>()
+            .context("Failed to read applied migrations")?;
+        let pending: Vec<String> = Migrator::migrations()
+            .iter()
+            .map(|migration| migration.name().to_string())
+            .filter(|name| !applied.contains(name))
+            .collect();
+        if !pending.is_empty() {
+            anyhow::bail!(
+                "The DB is missing migrations {}, run the processor to apply them",
+                pending.join(", ")
+            );
+        }
+
+        info!(
+            backend = ?connection.get_database_backend(),
+            "Built read only database storage"
+        );
+
+        Ok(Self { connection })
+    }
+
+    fn backend(&self) -> DbBackend {
+        self.connection.get_database_backend()
+    }
+
+    /// Read every row of every table, a page at a time, into `sink`. This is used
+    /// for building snapshots, see `commands::snapshot`. The tables are all read in a
+    /// single transaction, so they are consistent with each other.
+    pub async fn export_tables(&self, sink: &mut impl TableSink) -> Result<()> {
+        // SQLite transactions are always serializable, and it warns if asked otherwise.
+        let (isolation_level, access_mode) = match self.backend() {
+            DbBackend::Sqlite => (None, None),
+            _ => (
+                Some(IsolationLevel::RepeatableRead),
+                Some(AccessMode::ReadOnly),
+            ),
+        };
+        let txn = self
+            .connection
+            .begin_with_config(isolation_level, access_mode)
+            .await
+            .context("Failed to start transaction")?;
+        export_table::<canvas_artist_lists::Entity>(&txn, sink).await?;
+        export_table::<canvas_finalisations::Entity>(&txn, sink).await?;
+        export_table::<canvases::Entity>(&txn, sink).await?;
+        export_table::<chain_id::Entity>(&txn, sink).await?;
+        export_table::<contribution_timeouts::Entity>(&txn, sink).await?;
+        export_table::<contributions::Entity>(&txn, sink).await?;
+        export_table::<funds_recipient_changes::Entity>(&txn, sink).await?;
+        export_table::<last_processed_version::Entity>(&txn, sink).await?;
+        export_table::<ownership_changes::Entity>(&txn, sink).await?;
+        export_table::<payments::Entity>(&txn, sink).await?;
+        export_table::<pixel_history::Entity>(&txn, sink).await?;
+        export_table::<pixel_writers::Entity>(&txn, sink).await?;
+        export_table::<quarantined_transactions::Entity>(&txn, sink).await?;
+        txn.commit().await.context("Failed to finish transaction")
+    }
+
+    /// Start replacing the contents of every table with rows from `export_tables`.
+    /// Every table is cleared, the rows are then written with `TablesImport::write_rows`.
+    /// This all happens in a single transaction, so if the import fails before
+    /// `TablesImport::commit` the DB is left untouched.
+    pub async fn import_tables(&self) -> Result<TablesImport> {
+        let txn = self
+            .connection
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        clear_table::<canvas_artist_lists::Entity>(&txn).await?;
+        clear_table::<canvas_finalisations::Entity>(&txn).await?;
+        clear_table::<canvases::Entity>(&txn).await?;
+        clear_table::<chain_id::Entity>(&txn).await?;
+        clear_table::<contribution_timeouts::Entity>(&txn).await?;
+        clear_table::<contributions::Entity>(&txn).await?;
+        clear_table::<funds_recipient_changes::Entity>(&txn).await?;
+        clear_table::<last_processed_version::Entity>(&txn).await?;
+        clear_table::<ownership_changes::Entity>(&txn).await?;
+        clear_table::<payments::Entity>(&txn).await?;
+        clear_table::<pixel_history::Entity>(&txn).await?;
+        clear_table::<pixel_writers::Entity>(&txn).await?;
+        clear_table::<quarantined_transactions::Entity>(&txn).await?;
+        Ok(TablesImport { txn })
+    }
+
+    /// Copy the tables built from processing txns in `other` into staged copies of
+    /// them in this DB, a page at a time, ready for `swap_in_staged_tables`. The
+    /// tables in use aren't touched, so this can run while the processor is running.
+    /// The tables tracking the progress of the processor aren't copied, nor are the
+    /// final images of closed canvases, since those are never rebuilt.
+    pub async fn stage_indexed_tables_from(&self, other: &DatabaseStorage) -> Result<()> {
+        let (from, to) = (&other.connection, &self.connection);
+        stage_table::<canvas_artist_lists::Entity, _>(from, to).await?;
+        stage_table::<canvases::Entity, _>(from, to).await?;
+        stage_table::<contribution_timeouts::Entity, _>(from, to).await?;
+        stage_table::<contributions::Entity, _>(from, to).await?;
+        stage_table::<funds_recipient_changes::Entity, _>(from, to).await?;
+        stage_table::<ownership_changes::Entity, _>(from, to).await?;
+        stage_table::<payments::Entity, _>(from, to).await?;
+        stage_table::<pixel_history::Entity, _>(from, to).await?;
+        stage_table::<pixel_writers::Entity, _>(from, to).await?;
+        stage_table::<quarantined_transactions::Entity, _>(from, to).await?;
+        Ok(())
+    }
+
+    /// Replace the rows of the canvases in the copies made by
+    /// `stage_indexed_tables_from` with the copied rows. Rows of other canvases, e.g.
+    /// ones created before the backfill started, are left as they are. Quarantined
+    /// txns aren't tied to a canvas, so the copied ones are added to those already
+    /// recorded. The rows are copied within the DB in a single transaction, so this is
+    /// quick and readers never see a partial swap.
+    pub async fn swap_in_staged_tables(&self) -> Result<()> {
+        let txn = self
+            .connection
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        swap_in_staged_table::<canvas_artist_lists::Entity>(
+            &txn,
+            canvas_artist_lists::Column::CanvasAddress,
+        )
+        .await?;
+        swap_in_staged_table::<contribution_timeouts::Entity>(
+            &txn,
+            contribution_timeouts::Column::CanvasAddress,
+        )
+        .await?;
+        swap_in_staged_table::<contributions::Entity>(&txn, contributions::Column::CanvasAddress)
+            .await?;
+        swap_in_staged_table::<funds_recipient_changes::Entity>(
+            &txn,
+            funds_recipient_changes::Column::CanvasAddress,
+        )
+        .await?;
+        swap_in_staged_table::<ownership_changes::Entity>(
+            &txn,
+            ownership_changes::Column::ObjectAddress,
+        )
+        .await?;
+        swap_in_staged_table::<payments::Entity>(&txn, payments::Column::CanvasAddress).await?;
+        swap_in_staged_table::<pixel_history::Entity>(&txn, pixel_history::Column::CanvasAddress)
+            .await?;
+        swap_in_staged_table::<pixel_writers::Entity>(&txn, pixel_writers::Column::CanvasAddress)
+            .await?;
+        // The other tables are scoped by the staged canvases, so this goes last.
+        swap_in_staged_table::<canvases::Entity>(&txn, canvases::Column::Address).await?;
+        merge_in_staged_table::<quarantined_transactions::Entity>(&txn).await?;
+        txn.commit().await.context("Failed to commit swap")?;
+        Ok(())
+    }
+
+    /// Record newly created canvases. If a canvas is already recorded, e.g. because we
+    /// are reprocessing txns, it is overwritten.
+    pub async fn write_canvases(&self, canvases: Vec<canvases::Model>) -> Result<()> {
+        if canvases.is_empty() {
+            return Ok(());
+        }
+        let query = canvases::Entity::insert_many(
+            canvases.into_iter().map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::column(canvases::Column::Address)
+                .update_columns([
+                    canvases::Column::Deployment,
+                    canvases::Column::ContractAddress,
+                    canvases::Column::Width,
+                    canvases::Column::Height,
+                    canvases::Column::CreatedVersion,
+                    canvases::Column::Cost,
+                    canvases::Column::PerAccountTimeoutS,
+                    canvases::Column::CanDrawForS,
+                    canvases::Column::CreatedAtS,
+                    canvases::Column::FundsRecipient,
+                    canvases::Column::OwnerIsSuperAdmin,
+                ])
+                .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write canvases")?;
+
+        Ok(())
+    }
+
+    pub async fn read_canvas(&self, address: &str) -> Result<Option<canvases::Model>> {
+        canvases::Entity::find_by_id(address)
+            .one(&self.connection)
+            .await
+            .context("Failed to read canvas")
+    }
+
+    /// Read all the canvases, optionally only those from the given deployment, in
+    /// the order they were created.
+    pub async fn read_canvases(
+        &self,
+        deployment: Option<&str>,
+        owner_address: Option<&str>,
+    ) -> Result<Vec<canvases::Model>> {
+        let mut query = canvases::Entity::find().order_by_asc(canvases::Column::CreatedVersion);
+        if let Some(deployment) = deployment {
+            query = query.filter(canvases::Column::Deployment.eq(deployment));
+        }
+        if let Some(owner_address) = owner_address {
+            query = query.filter(canvases::Column::OwnerAddress.eq(owner_address));
+        }
+        query
+            .all(&self.connection)
+            .await
+            .context("Failed to read canvases")
+    }
+
+    /// Record txns that we failed to decode. If a txn is already quarantined, e.g.
+    /// because we are reprocessing txns, the error is overwritten.
+    pub async fn write_quarantined_transactions(
+        &self,
+        quarantined_transactions: Vec<quarantined_transactions::Model>,
+    ) -> Result<()> {
+        if quarantined_transactions.is_empty() {
+            return Ok(());
+        }
+        let query = quarantined_transactions::Entity::insert_many(
+            quarantined_transactions
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                quarantined_transactions::Column::Version,
+                quarantined_transactions::Column::Deployment,
+            ])
+            .update_columns([
+                quarantined_transactions::Column::Changes,
+                quarantined_transactions::Column::Error,
+            ])
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write quarantined transactions")?;
+
+        Ok(())
+    }
+
+    /// Read all the quarantined txns, optionally only those from the given
+    /// deployment, in version order.
+    pub async fn read_quarantined_transactions(
+        &self,
+        deployment: Option<&str>,
+    ) -> Result<Vec<quarantined_transactions::Model>> {
+        let mut query = quarantined_transactions::Entity::find()
+            .order_by_asc(quarantined_transactions::Column::Version);
+        if let Some(deployment) = deployment {
+            query = query.filter(quarantined_transactions::Column::Deployment.eq(deployment));
+        }
+        query
+            .all(&self.connection)
+            .await
+            .context("Failed to read quarantined transactions")
+    }
+
+    /// Remove a txn from the quarantine, e.g. once it has been replayed. Returns
+    /// whether there was anything to remove.
+    pub async fn delete_quarantined_transaction(
+        &self,
+        version: u64,
+        deployment: &str,
+    ) -> Result<bool> {
+        let result = quarantined_transactions::Entity::delete_by_id((
+            version as i64,
+            deployment.to_string(),
+        ))
+        .exec(&self.connection)
+        .await
+        .context("Failed to delete quarantined transaction")?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Add to the contributions of artists to canvases. Contributions that start at or
+    /// before the last contribution already recorded for that artist and canvas are
+    /// ignored, so reprocessing txns doesn't count them twice.
+    pub async fn write_contributions(
+        &self,
+        contributions: Vec<contributions::Model>,
+    ) -> Result<()> {
+        if contributions.is_empty() {
+            return Ok(());
+        }
+        let query = contributions::Entity::insert_many(
+            contributions
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                contributions::Column::CanvasAddress,
+                contributions::Column::ArtistAddress,
+            ])
+            .values([
+                (
+                    contributions::Column::PixelsDrawn,
+                    Expr::cust("contributions.pixels_drawn + excluded.pixels_drawn"),
+                ),
+                (
+                    contributions::Column::OctasSpent,
+                    Expr::cust("contributions.octas_spent + excluded.octas_spent"),
+                ),
+            ])
+            .update_columns([
+                contributions::Column::LastContributionVersion,
+                contributions::Column::LastContributionTimestamp,
+            ])
+            .action_and_where(Expr::cust(
+                "excluded.first_contribution_version > contributions.last_contribution_version",
+            ))
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write contributions")?;
+
+        Ok(())
+    }
+
+    /// Record who drew the pixels that are now visible.
+    pub async fn write_pixel_writers(
+        &self,
+        pixel_writers: Vec<pixel_writers::Model>,
+    ) -> Result<()> {
+        if pixel_writers.is_empty() {
+            return Ok(());
+        }
+        let query = pixel_writers::Entity::insert_many(
+            pixel_writers
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                pixel_writers::Column::CanvasAddress,
+                pixel_writers::Column::PixelIndex,
+            ])
+            .update_columns([
+                pixel_writers::Column::ArtistAddress,
+                pixel_writers::Column::Version,
+            ])
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write pixel writers")?;
+
+        Ok(())
+    }
+
+    /// Forget who drew the pixels of a canvas, e.g. because it was cleared.
+    pub async fn delete_pixel_writers(&self, canvas_address: &str) -> Result<()> {
+        pixel_writers::Entity::delete_many()
+            .filter(pixel_writers::Column::CanvasAddress.eq(canvas_address))
+            .exec(&self.connection)
+            .await
+            .context("Failed to delete pixel writers")?;
+        Ok(())
+    }
+
+    /// Count how many of the pixels currently showing on a canvas each artist drew,
+    /// most first.
+    pub async fn count_canvas_visible_pixels(
+        &self,
+        canvas_address: &str,
+    ) -> Result<Vec<(String, i64)>> {
+        pixel_writers::Entity::find()
+            .select_only()
+            .column(pixel_writers::Column::ArtistAddress)
+            .column_as(pixel_writers::Column::PixelIndex.count(), "count")
+            .filter(pixel_writers::Column::CanvasAddress.eq(canvas_address))
+            .group_by(pixel_writers::Column::ArtistAddress)
+            .order_by_desc(Expr::cust("COUNT(pixel_index)"))
+            .order_by_asc(pixel_writers::Column::ArtistAddress)
+            .into_tuple::<(String, i64)>()
+            .all(&self.connection)
+            .await
+            .context("Failed to count canvas visible pixels")
+    }
+
+    /// Read the indices of the pixels currently showing on a canvas that an artist
+    /// drew.
+    pub async fn read_artist_pixel_indices(
+        &self,
+        canvas_address: &str,
+        artist_address: &str,
+    ) -> Result<HashSet<u64>> {
+        let indices = pixel_writers::Entity::find()
+            .select_only()
+            .column(pixel_writers::Column::PixelIndex)
+            .filter(pixel_writers::Column::CanvasAddress.eq(canvas_address))
+            .filter(pixel_writers::Column::ArtistAddress.eq(artist_address))
+            .into_tuple::<i64>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read artist pixel indices")?;
+        Ok(indices.into_iter().map(|index| index as u64).collect())
+    }
+
+    /// Read who drew a pixel, if anyone has since the canvas was created or cleared.
+    pub async fn read_pixel_writer(
+        &self,
+        canvas_address: &str,
+        pixel_index: u64,
+    ) -> Result<Option<pixel_writers::Model>> {
+        pixel_writers::Entity::find_by_id((canvas_address.to_string(), pixel_index as i64))
+            .one(&self.connection)
+            .await
+            .context("Failed to read pixel writer")
+    }
+
+    /// Record when artists last drew on canvases with a per account timeout, as the
+    /// contract sees it.
+    pub async fn write_contribution_timeouts(
+        &self,
+        contribution_timeouts: Vec<contribution_timeouts::Model>,
+    ) -> Result<()> {
+        if contribution_timeouts.is_empty() {
+            return Ok(());
+        }
+        let query = contribution_timeouts::Entity::insert_many(
+            contribution_timeouts
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                contribution_timeouts::Column::CanvasAddress,
+                contribution_timeouts::Column::ArtistAddress,
+            ])
+            .update_columns([
+                contribution_timeouts::Column::LastContributionS,
+                contribution_timeouts::Column::Version,
+            ])
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write contribution timeouts")?;
+
+        Ok(())
+    }
+
+    /// Forget when artists last drew on a canvas, because a super admin called
+    /// `clear_contribution_timeouts`.
+    pub async fn delete_contribution_timeouts(&self, canvas_address: &str) -> Result<()> {
+        contribution_timeouts::Entity::delete_many()
+            .filter(contribution_timeouts::Column::CanvasAddress.eq(canvas_address))
+            .exec(&self.connection)
+            .await
+            .context("Failed to delete contribution timeouts")?;
+        Ok(())
+    }
+
+    /// Read when an artist last drew on a canvas, if the contract has it recorded.
+    pub async fn read_contribution_timeout(
+        &self,
+        canvas_address: &str,
+        artist_address: &str,
+    ) -> Result<Option<contribution_timeouts::Model>> {
+        contribution_timeouts::Entity::find_by_id((
+            canvas_address.to_string(),
+            artist_address.to_string(),
+        ))
+        .one(&self.connection)
+        .await
+        .context("Failed to read contribution timeout")
+    }
+
+    /// Update the config we record for a canvas from its current Canvas resource.
+    /// Nothing happens if the canvas isn't recorded.
+    pub async fn update_canvas_config(
+        &self,
+        canvas_address: &str,
+        per_account_timeout_s: i64,
+        can_draw_for_s: i64,
+        created_at_s: i64,
+        funds_recipient: Option<String>,
+        owner_is_super_admin: bool,
+    ) -> Result<()> {
+        canvases::Entity::update_many()
+            .col_expr(
+                canvases::Column::PerAccountTimeoutS,
+                Expr::value(per_account_timeout_s),
+            )
+            .col_expr(canvases::Column::CanDrawForS, Expr::value(can_draw_for_s))
+            .col_expr(canvases::Column::CreatedAtS, Expr::value(created_at_s))
+            .col_expr(
+                canvases::Column::FundsRecipient,
+                Expr::value(funds_recipient),
+            )
+            .col_expr(
+                canvases::Column::OwnerIsSuperAdmin,
+                Expr::value(owner_is_super_admin),
+            )
+            .filter(canvases::Column::Address.eq(canvas_address))
+            .exec(&self.connection)
+            .await
+            .context("Failed to update canvas config")?;
+        Ok(())
+    }
+
+    /// Record the payments made by draws. Payments that are already recorded, e.g.
+    /// because we are reprocessing txns, are left as they are.
+    pub async fn write_payments(&self, payments: Vec<payments::Model>) -> Result<()> {
+        if payments.is_empty() {
+            return Ok(());
+        }
+        let query = payments::Entity::insert_many(
+            payments.into_iter().map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([payments::Column::CanvasAddress, payments::Column::Version])
+                .do_nothing()
+                .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write payments")?;
+
+        Ok(())
+    }
+
+    /// Sum up the payments, optionally only those to one canvas or one recipient,
+    /// grouped by either the canvas or the recipient. The biggest earners come first.
+    pub async fn read_revenue_totals(
+        &self,
+        canvas_address: Option<&str>,
+        recipient_address: Option<&str>,
+        group_by: RevenueGrouping,
+    ) -> Result<Vec<RevenueTotals>> {
+        let mut query = payments::Entity::find();
+        if let Some(canvas_address) = canvas_address {
+            query = query.filter(payments::Column::CanvasAddress.eq(canvas_address));
+        }
+        if let Some(recipient_address) = recipient_address {
+            query = query.filter(payments::Column::RecipientAddress.eq(recipient_address));
+        }
+        let group_column = match group_by {
+            RevenueGrouping::Canvas => payments::Column::CanvasAddress,
+            RevenueGrouping::Recipient => payments::Column::RecipientAddress,
+        };
+
+        // Cast the sum back to BIGINT, otherwise Postgres returns NUMERIC.
+        query
+            .select_only()
+            .column_as(group_column, "address")
+            .column_as(Expr::cust("CAST(SUM(amount) AS BIGINT)"), "octas_received")
+            .column_as(payments::Column::Version.count(), "payments")
+            .column_as(payments::Column::Version.min(), "first_payment_version")
+            .column_as(payments::Column::Version.max(), "last_payment_version")
+            .group_by(group_column)
+            .order_by_desc(Expr::cust("SUM(amount)"))
+            .order_by_asc(group_column)
+            .into_model::<RevenueTotals>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read revenue")
+    }
+
+    /// Record changes to who receives the funds from draws on a canvas. Changes
+    /// that are already recorded are left as they are.
+    pub async fn write_funds_recipient_changes(
+        &self,
+        funds_recipient_changes: Vec<funds_recipient_changes::Model>,
+    ) -> Result<()> {
+        if funds_recipient_changes.is_empty() {
+            return Ok(());
+        }
+        let query = funds_recipient_changes::Entity::insert_many(
+            funds_recipient_changes
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                funds_recipient_changes::Column::CanvasAddress,
+                funds_recipient_changes::Column::Version,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write funds recipient changes")?;
+
+        Ok(())
+    }
+
+    /// Read who received the funds from draws on a canvas over time, oldest first.
+    pub async fn read_funds_recipient_changes(
+        &self,
+        canvas_address: &str,
+    ) -> Result<Vec<funds_recipient_changes::Model>> {
+        funds_recipient_changes::Entity::find()
+            .filter(funds_recipient_changes::Column::CanvasAddress.eq(canvas_address))
+            .order_by_asc(funds_recipient_changes::Column::Version)
+            .all(&self.connection)
+            .await
+            .context("Failed to read funds recipient changes")
+    }
+
+    /// Read the addresses of every canvas and of the collection, i.e. the objects we
+    /// track the owner of.
+    pub async fn read_tracked_object_addresses(&self) -> Result<HashSet<String>> {
+        let mut addresses: HashSet<String> = canvases::Entity::find()
+            .select_only()
+            .column(canvases::Column::Address)
+            .into_tuple::<String>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read canvas addresses")?
+            .into_iter()
+            .collect();
+        addresses.extend(
+            ownership_changes::Entity::find()
+                .select_only()
+                .column(ownership_changes::Column::ObjectAddress)
+                .filter(ownership_changes::Column::Kind.eq(ObjectKind::Collection.as_str()))
+                .distinct()
+                .into_tuple::<String>()
+                .all(&self.connection)
+                .await
+                .context("Failed to read collection addresses")?,
+        );
+        Ok(addresses)
+    }
+
+    /// Find which of the given objects are canvases or the collection, and who we
+    /// have them recorded as being owned by.
+    pub async fn read_tracked_objects(
+        &self,
+        object_addresses: &[String],
+    ) -> Result<HashMap<String, TrackedObject>> {
+        let mut tracked_objects: HashMap<String, TrackedObject> = canvases::Entity::find()
+            .select_only()
+            .column(canvases::Column::Address)
+            .column(canvases::Column::OwnerAddress)
+            .filter(canvases::Column::Address.is_in(object_addresses.iter().cloned()))
+            .into_tuple::<(String, Option<String>)>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read canvas owners")?
+            .into_iter()
+            .map(|(address, owner_address)| {
+                (
+                    address,
+                    TrackedObject {
+                        kind: ObjectKind::Canvas,
+                        owner_address,
+                    },
+                )
+            })
+            .collect();
+
+        // We only know the collection from the changes to its owner, the latest of
+        // which is its current owner.
+        let collection_changes = ownership_changes::Entity::find()
+            .filter(ownership_changes::Column::Kind.eq(ObjectKind::Collection.as_str()))
+            .filter(
+                ownership_changes::Column::ObjectAddress.is_in(object_addresses.iter().cloned()),
+            )
+            .order_by_asc(ownership_changes::Column::Version)
+            .all(&self.connection)
+            .await
+            .context("Failed to read collection owners")?;
+        for change in collection_changes {
+            tracked_objects.insert(
+                change.object_address,
+                TrackedObject {
+                    kind: ObjectKind::Collection,
+                    owner_address: Some(change.owner_address),
+                },
+            );
+        }
+        Ok(tracked_objects)
+    }
+
+    /// Record changes to the owners of canvases and the collection. Changes that are
+    /// already recorded are left as they are.
+    pub async fn write_ownership_changes(
+        &self,
+        ownership_changes: Vec<ownership_changes::Model>,
+    ) -> Result<()> {
+        if ownership_changes.is_empty() {
+            return Ok(());
+        }
+        let query = ownership_changes::Entity::insert_many(
+            ownership_changes
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                ownership_changes::Column::ObjectAddress,
+                ownership_changes::Column::Version,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write ownership changes")?;
+
+        Ok(())
+    }
+
+    /// Read the owners of a canvas or the collection over time, oldest first.
+    pub async fn read_ownership_changes(
+        &self,
+        object_address: &str,
+    ) -> Result<Vec<ownership_changes::Model>> {
+        ownership_changes::Entity::find()
+            .filter(ownership_changes::Column::ObjectAddress.eq(object_address))
+            .order_by_asc(ownership_changes::Column::Version)
+            .all(&self.connection)
+            .await
+            .context("Failed to read ownership changes")
+    }
+
+    /// Record the current owner of a canvas.
+    pub async fn update_canvas_owner(
+        &self,
+        canvas_address: &str,
+        owner_address: &str,
+    ) -> Result<()> {
+        canvases::Entity::update_many()
+            .col_expr(canvases::Column::OwnerAddress, Expr::value(owner_address))
+            .filter(canvases::Column::Address.eq(canvas_address))
+            .exec(&self.connection)
+            .await
+            .context("Failed to update canvas owner")?;
+        Ok(())
+    }
+
+    /// Read the canvases that closed before `closed_before_s` but haven't been
+    /// finalised yet, in the order they were created.
+    pub async fn read_canvases_to_finalise(
+        &self,
+        closed_before_s: i64,
+    ) -> Result<Vec<canvases::Model>> {
+        canvases::Entity::find()
+            .filter(canvases::Column::CanDrawForS.gt(0))
+            .filter(
+                Expr::expr(
+                    Expr::col(canvases::Column::CreatedAtS)
+                        .add(Expr::col(canvases::Column::CanDrawForS)),
+                )
+                .lt(closed_before_s),
+            )
+            .filter(
+                canvases::Column::Address.not_in_subquery(
+                    Query::select()
+                        .column(canvas_finalisations::Column::CanvasAddress)
+                        .from(canvas_finalisations::Entity)
+                        .to_owned(),
+                ),
+            )
+            .order_by_asc(canvases::Column::CreatedVersion)
+            .all(&self.connection)
+            .await
+            .context("Failed to read canvases to finalise")
+    }
+
+    /// Record the final image of a closed canvas. If the canvas was already
+    /// finalised the existing image is kept, it must never change.
+    pub async fn write_canvas_finalisation(
+        &self,
+        finalisation: canvas_finalisations::Model,
+    ) -> Result<()> {
+        let query = canvas_finalisations::Entity::insert(finalisation.into_active_model())
+            .on_conflict(
+                OnConflict::column(canvas_finalisations::Column::CanvasAddress)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write canvas finalisation")?;
+
+        Ok(())
+    }
+
+    pub async fn read_canvas_finalisation(
+        &self,
+        canvas_address: &str,
+    ) -> Result<Option<canvas_finalisations::Model>> {
+        canvas_finalisations::Entity::find_by_id(canvas_address)
+            .one(&self.connection)
+            .await
+            .context("Failed to read canvas finalisation")
+    }
+
+    /// Record draws in the history of the pixels they drew. Draws that are already
+    /// recorded, e.g. because we are reprocessing txns, are left as they are.
+    pub async fn write_pixel_history(
+        &self,
+        pixel_history: Vec<pixel_history::Model>,
+    ) -> Result<()> {
+        if pixel_history.is_empty() {
+            return Ok(());
+        }
+        let query = pixel_history::Entity::insert_many(
+            pixel_history
+                .into_iter()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .on_conflict(
+            OnConflict::columns([
+                pixel_history::Column::CanvasAddress,
+                pixel_history::Column::PixelIndex,
+                pixel_history::Column::Version,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write pixel history")?;
+
+        Ok(())
+    }
+
+    /// Read a page of the draws of a pixel, newest first, along with how many
+    /// times it has been drawn in total.
+    pub async fn read_pixel_history(
+        &self,
+        canvas_address: &str,
+        pixel_index: u64,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(u64, Vec<pixel_history::Model>)> {
+        let query = pixel_history::Entity::find()
+            .filter(pixel_history::Column::CanvasAddress.eq(canvas_address))
+            .filter(pixel_history::Column::PixelIndex.eq(pixel_index as i64));
+        let total = query
+            .clone()
+            .count(&self.connection)
+            .await
+            .context("Failed to count pixel history")?;
+        let pixel_history = query
+            .order_by_desc(pixel_history::Column::Version)
+            .limit(limit)
+            .offset(offset)
+            .all(&self.connection)
+            .await
+            .context("Failed to read pixel history")?;
+        Ok((total, pixel_history))
+    }
+
+    /// Read a page of the draws by an artist across every canvas, newest first, along
+    /// with how many draws they've made in total.
+    pub async fn read_artist_pixel_history(
+        &self,
+        artist_address: &str,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(u64, Vec<pixel_history::Model>)> {
+        let query = pixel_history::Entity::find()
+            .filter(pixel_history::Column::ArtistAddress.eq(artist_address));
+        let total = query
+            .clone()
+            .count(&self.connection)
+            .await
+            .context("Failed to count artist pixel history")?;
+        let pixel_history = query
+            .order_by_desc(pixel_history::Column::Version)
+            .order_by_asc(pixel_history::Column::CanvasAddress)
+            .order_by_asc(pixel_history::Column::PixelIndex)
+            .limit(limit)
+            .offset(offset)
+            .all(&self.connection)
+            .await
+            .context("Failed to read artist pixel history")?;
+        Ok((total, pixel_history))
+    }
+
+    /// Replace the allowlist and blocklist of a canvas with its current ones.
+    pub async fn write_canvas_artist_lists(
+        &self,
+        canvas_address: &str,
+        allowlisted_artists: Vec<String>,
+        blocklisted_artists: Vec<String>,
+    ) -> Result<()> {
+        let models: Vec<canvas_artist_lists::ActiveModel> = allowlisted_artists
+            .into_iter()
+            .map(|artist_address| (ALLOWLIST, artist_address))
+            .chain(
+                blocklisted_artists
+                    .into_iter()
+                    .map(|artist_address| (BLOCKLIST, artist_address)),
+            )
+            .map(|(list, artist_address)| {
+                canvas_artist_lists::Model {
+                    canvas_address: canvas_address.to_string(),
+                    list: list.to_string(),
+                    artist_address,
+                }
+                .into_active_model()
+            })
+            .collect();
+
+        let txn = self
+            .connection
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        canvas_artist_lists::Entity::delete_many()
+            .filter(canvas_artist_lists::Column::CanvasAddress.eq(canvas_address))
+            .exec(&txn)
+            .await
+            .context("Failed to delete canvas artist lists")?;
+        if !models.is_empty() {
+            canvas_artist_lists::Entity::insert_many(models)
+                .exec(&txn)
+                .await
+                .context("Failed to write canvas artist lists")?;
+        }
+        txn.commit()
+            .await
+            .context("Failed to commit canvas artist lists")?;
+        Ok(())
+    }
+
+    /// Work out whether an artist may draw on each of the given canvases, based on
+    /// their allowlists and blocklists.
+    pub async fn read_artist_permissions(
+        &self,
+        artist_address: &str,
+        canvas_addresses: &[String],
+    ) -> Result<HashMap<String, ArtistPermission>> {
+        let memberships: HashSet<(String, String)> = canvas_artist_lists::Entity::find()
+            .filter(canvas_artist_lists::Column::ArtistAddress.eq(artist_address))
+            .filter(
+                canvas_artist_lists::Column::CanvasAddress.is_in(canvas_addresses.iter().cloned()),
+            )
+            .all(&self.connection)
+            .await
+            .context("Failed to read artist lists")?
+            .into_iter()
+            .map(|model| (model.canvas_address, model.list))
+            .collect();
+        let canvases_with_allowlist: HashSet<String> = canvas_artist_lists::Entity::find()
+            .select_only()
+            .column(canvas_artist_lists::Column::CanvasAddress)
+            .filter(canvas_artist_lists::Column::List.eq(ALLOWLIST))
+            .filter(
+                canvas_artist_lists::Column::CanvasAddress.is_in(canvas_addresses.iter().cloned()),
+            )
+            .distinct()
+            .into_tuple::<String>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read canvases with allowlists")?
+            .into_iter()
+            .collect();
+
+        // This mirrors `allowlisted_to_draw` in the contract.
+        Ok(canvas_addresses
+            .iter()
+            .map(|canvas_address| {
+                let is_on =
+                    |list: &str| memberships.contains(&(canvas_address.clone(), list.to_string()));
+                let permission = if is_on(BLOCKLIST) {
+                    ArtistPermission::InBlocklist
+                } else if canvases_with_allowlist.contains(canvas_address) && !is_on(ALLOWLIST) {
+                    ArtistPermission::NotInAllowlist
+                } else {
+                    ArtistPermission::Allowed
+                };
+                (canvas_address.clone(), permission)
+            })
+            .collect())
+    }
+
+    /// Read the contributions of an artist to each canvas, in the order they first
+    /// contributed.
+    pub async fn read_artist_contributions(
+        &self,
+        artist_address: &str,
+    ) -> Result<Vec<contributions::Model>> {
+        contributions::Entity::find()
+            .filter(contributions::Column::ArtistAddress.eq(artist_address))
+            .order_by_asc(contributions::Column::FirstContributionVersion)
+            .all(&self.connection)
+            .await
+            .context("Failed to read artist contributions")
+    }
+
+    /// Count how many of the pixels currently showing on each canvas an artist drew.
+    pub async fn count_artist_visible_pixels(
+        &self,
+        artist_address: &str,
+    ) -> Result<HashMap<String, i64>> {
+        let counts = pixel_writers::Entity::find()
+            .select_only()
+            .column(pixel_writers::Column::CanvasAddress)
+            .column_as(pixel_writers::Column::PixelIndex.count(), "count")
+            .filter(pixel_writers::Column::ArtistAddress.eq(artist_address))
+            .group_by(pixel_writers::Column::CanvasAddress)
+            .into_tuple::<(String, i64)>()
+            .all(&self.connection)
+            .await
+            .context("Failed to count artist visible pixels")?;
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Read a page of the artists who contributed the most to a canvas, or to any
+    /// canvas if `canvas_address` isn't given, along with how many artists there are
+    /// in total.
+    pub async fn read_leaderboard(
+        &self,
+        canvas_address: Option<&str>,
+        limit: u64,
+        offset: u64,
+    ) -> Result<(u64, Vec<ContributionTotals>)> {
+        let filter = |query: sea_orm::Select<contributions::Entity>| match canvas_address {
+            Some(canvas_address) => {
+                query.filter(contributions::Column::CanvasAddress.eq(canvas_address))
+            },
+            None => query,
+        };
+
+        let total = filter(contributions::Entity::find())
+            .select_only()
+            .column(contributions::Column::ArtistAddress)
+            .distinct()
+            .count(&self.connection)
+            .await
+            .context("Failed to count artists")?;
+
+        // Cast the sums back to BIGINT, otherwise Postgres returns NUMERIC.
+        let totals = filter(contributions::Entity::find())
+            .select_only()
+            .column(contributions::Column::ArtistAddress)
+            .column_as(
+                Expr::cust("CAST(SUM(pixels_drawn) AS BIGINT)"),
+                "pixels_drawn",
+            )
+            .column_as(
+                contributions::Column::FirstContributionVersion.min(),
+                "first_contribution_version",
+            )
+            .column_as(
+                contributions::Column::FirstContributionTimestamp.min(),
+                "first_contribution_timestamp",
+            )
+            .column_as(
+                contributions::Column::LastContributionVersion.max(),
+                "last_contribution_version",
+            )
+            .column_as(
+                contributions::Column::LastContributionTimestamp.max(),
+                "last_contribution_timestamp",
+            )
+            .column_as(
+                Expr::cust("CAST(SUM(octas_spent) AS BIGINT)"),
+                "octas_spent",
+            )
+            .group_by(contributions::Column::ArtistAddress)
+            .order_by_desc(Expr::cust("SUM(pixels_drawn)"))
+            .order_by_asc(contributions::Column::ArtistAddress)
+            .limit(limit)
+            .offset(offset)
+            .into_model::<ContributionTotals>()
+            .all(&self.connection)
+            .await
+            .context("Failed to read leaderboard")?;
+
+        Ok((total, totals))
+    }
+
+    /// Count how many of the visible pixels of a canvas, or of every canvas if
+    /// `canvas_address` isn't given, each of the artists drew.
+    pub async fn count_visible_pixels(
+        &self,
+        canvas_address: Option<&str>,
+        artist_addresses: &[String],
+    ) -> Result<HashMap<String, i64>> {
+        let mut query = pixel_writers::Entity::find()
+            .select_only()
+            .column(pixel_writers::Column::ArtistAddress)
+            .column_as(pixel_writers::Column::PixelIndex.count(), "count")
+            .filter(pixel_writers::Column::ArtistAddress.is_in(artist_addresses.iter().cloned()))
+            .group_by(pixel_writers::Column::ArtistAddress);
+        if let Some(canvas_address) = canvas_address {
+            query = query.filter(pixel_writers::Column::CanvasAddress.eq(canvas_address));
+        }
+        let counts = query
+            .into_tuple::<(String, i64)>()
+            .all(&self.connection)
+            .await
+            .context("Failed to count visible pixels")?;
+        Ok(counts.into_iter().collect())
+    }
+
+    pub async fn delete_last_processed_version(&self, processor_name: &str) -> Result<()> {
+        last_processed_version::Entity::delete_by_id(processor_name)
+            .exec(&self.connection)
+            .await
+            .context("Failed to delete last processed version")?;
+        Ok(())
+    }
+
+    /// Record how far along a processor is, see `api::health`. This isn't part of
+    /// `export_tables`, it only describes the processor currently running.
+    pub async fn write_processor_status(&self, status: processor_status::Model) -> Result<()> {
+        let query = processor_status::Entity::insert(status.into_active_model())
+            .on_conflict(
+                OnConflict::column(processor_status::Column::ProcessorName)
+                    .update_columns([
+                        processor_status::Column::ProcessedVersion,
+                        processor_status::Column::LastTransactionTimestamp,
+                        processor_status::Column::LatestStreamVersion,
+                    ])
+                    .to_owned(),
+            )
+            .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to write processor status")?;
+
+        Ok(())
+    }
+
+    pub async fn read_processor_status(
+        &self,
+        processor_name: &str,
+    ) -> Result<Option<processor_status::Model>> {
+        processor_status::Entity::find_by_id(processor_name)
+            .one(&self.connection)
+            .await
+            .context("Failed to read processor status")
+    }
+}
+
+/// The kinds of object we track the owners of.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    Canvas,
+    Collection,
+}
+
+impl ObjectKind {
+    /// The value of `ownership_changes::Model::kind`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Canvas => "canvas",
+            ObjectKind::Collection => "collection",
+        }
+    }
+}
+
+/// An object we track the owner of, see `read_tracked_objects`.
+#[derive(Clone, Debug)]
+pub struct TrackedObject {
+    pub kind: ObjectKind,
+    /// `None` if we haven't seen the owner of the canvas yet.
+    pub owner_address: Option<String>,
+}
+
+/// What to group payments by when summing them up.
+#[derive(Clone, Copy, Debug)]
+pub enum RevenueGrouping {
+    Canvas,
+    Recipient,
+}
+
+/// The payments to a canvas or a recipient, see `read_revenue_totals`.
+#[derive(Clone, Debug, FromQueryResult, Serialize)]
+pub struct RevenueTotals {
+    /// The canvas or the recipient, depending on what the payments are grouped by.
+    pub address: String,
+    pub octas_received: i64,
+    pub payments: i64,
+    pub first_payment_version: i64,
+    pub last_payment_version: i64,
+}
+
+/// The contributions of an artist, either to a single canvas or summed across them.
+#[derive(Clone, Debug, FromQueryResult, Serialize)]
+pub struct ContributionTotals {
+    pub artist_address: String,
+    pub pixels_drawn: i64,
+    pub first_contribution_version: i64,
+    pub first_contribution_timestamp: Option<i64>,
+    pub last_contribution_version: i64,
+    pub last_contribution_timestamp: Option<i64>,
+    pub octas_spent: i64,
+}
+
+/// Whether an artist may draw on a canvas, given its allowlist and blocklist.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtistPermission {
+    Allowed,
+    InBlocklist,
+    NotInAllowlist,
+}
+
+/// Where `DatabaseStorage::export_tables` writes the rows of each table.
+pub trait TableSink {
+    /// Called with each page of rows of a table, in primary key order.
+    fn write_rows(&mut self, table_name: &str, rows: Vec<Value>) -> Result<()>;
+
+    /// Called once all the rows of a table have been written, including for tables
+    /// with no rows.
+    fn finish_table(&mut self, table_name: &str) -> Result<()>;
+}
+
+/// Collect every table in memory, which is handy for tests.
+impl TableSink for BTreeMap<String, Vec<Value>> {
+    fn write_rows(&mut self, table_name: &str, rows: Vec<Value>) -> Result<()> {
+        self.entry(table_name.to_string()).or_default().extend(rows);
+        Ok(())
+    }
+
+    fn finish_table(&mut self, table_name: &str) -> Result<()> {
+        self.entry(table_name.to_string()).or_default();
+        Ok(())
+    }
+}
+
+/// An import started by `DatabaseStorage::import_tables`.
+pub struct TablesImport {
+    txn: DatabaseTransaction,
+}
+
+impl TablesImport {
+    /// Insert a page of rows, as written by `export_tables`, into a table.
+    pub async fn write_rows(&self, table_name: &str, rows: &[Value]) -> Result<()> {
+        let txn = &self.txn;
+        let inserted = insert_rows::<canvas_artist_lists::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<canvas_finalisations::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<canvases::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<chain_id::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<contribution_timeouts::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<contributions::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<funds_recipient_changes::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<last_processed_version::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<ownership_changes::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<payments::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<pixel_history::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<pixel_writers::Entity, _>(txn, table_name, rows).await?
+            || insert_rows::<quarantined_transactions::Entity, _>(txn, table_name, rows).await?;
+        if !inserted {
+            anyhow::bail!("Unknown table {}", table_name);
+        }
+        Ok(())
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.txn.commit().await.context("Failed to commit import")
+    }
+}
+
+/// Write every row of a table to `sink`, a page at a time.
+async fn export_table<E>(txn: &DatabaseTransaction, sink: &mut impl TableSink) -> Result<()>
+where
+    E: EntityTrait,
+    E::Model: Serialize + Sync,
+{
+    let table_name = E::default().table_name().to_string();
+    let mut query = E::find();
+    // Page in a stable order so no rows are skipped or repeated.
+    for primary_key in E::PrimaryKey::iter() {
+        query = query.order_by_asc(primary_key.into_column());
+    }
+    let mut pages = query.paginate(txn, TABLE_PAGE_SIZE);
+    while let Some(models) = pages
+        .fetch_and_next()
+        .await
+        .with_context(|| format!("Failed to read {}", table_name))?
+    {
+        let rows = models
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to serialize {}", table_name))?;
+        sink.write_rows(&table_name, rows)?;
+    }
+    sink.finish_table(&table_name)
+}
+
+/// The name of the copy of the table of `E` made by `stage_table`.
+fn staged_table_name<E: EntityTrait>() -> Alias {
+    Alias::new(format!("staged_{}", E::default().table_name()))
+}
+
+/// Copy every row of the table of `E` in `from` into a fresh copy of the table in
+/// `to`, a page at a time.
+async fn stage_table<E, A>(from: &DatabaseConnection, to: &DatabaseConnection) -> Result<()>
+where
+    E: EntityTrait,
+    E::Model: IntoActiveModel<A> + Sync,
+    A: ActiveModelTrait<Entity = E>,
+{
+    let table_name = E::default().table_name().to_string();
+    let staged_table_name = staged_table_name::<E>();
+    let backend = to.get_database_backend();
+    to.execute(backend.build(Table::drop().table(staged_table_name.clone()).if_exists()))
+        .await
+        .with_context(|| format!("Failed to drop staged {}", table_name))?;
+    to.execute(
+        backend.build(
+            Schema::new(backend)
+                .create_table_from_entity(E::default())
+                .table(staged_table_name.clone()),
+        ),
+    )
+    .await
+    .with_context(|| format!("Failed to create staged {}", table_name))?;
+
+    let mut query = E::find();
+    // Page in a stable order so no rows are skipped or repeated.
+    for primary_key in E::PrimaryKey::iter() {
+        query = query.order_by_asc(primary_key.into_column());
+    }
+    let mut pages = query.paginate(from, TABLE_PAGE_SIZE);
+    while let Some(models) = pages
+        .fetch_and_next()
+        .await
+        .with_context(|| format!("Failed to read {}", table_name))?
+    {
+        let mut insert =
+            E::insert_many(models.into_iter().map(IntoActiveModel::into_active_model)).into_query();
+        insert.into_table(staged_table_name.clone());
+        to.execute(backend.build(&insert))
+            .await
+            .with_context(|| format!("Failed to insert into staged {}", table_name))?;
+    }
+    Ok(())
+}
+
+/// Select the addresses of the canvases in the copy of the canvases table made by
+/// `stage_table`.
+fn select_staged_canvas_addresses() -> SelectStatement {
+    Query::select()
+        .column(canvases::Column::Address)
+        .from(staged_table_name::<canvases::Entity>())
+        .to_owned()
+}
+
+/// Replace the rows of the table of `E` for the canvases in the copy of the canvases
+/// table with those of the copy of the table of `E` made by `stage_table`, then drop
+/// the copy. `canvas_column` is the column of `E` with the canvas address.
+async fn swap_in_staged_table<E: EntityTrait>(
+    connection: &impl ConnectionTrait,
+    canvas_column: E::Column,
+) -> Result<()> {
+    let table_name = E::default().table_name().to_string();
+    let staged_table_name = staged_table_name::<E>();
+    let backend = connection.get_database_backend();
+    let delete = Query::delete()
+        .from_table(E::default())
+        .and_where(Expr::col(canvas_column).in_subquery(select_staged_canvas_addresses()))
+        .to_owned();
+    connection
+        .execute(backend.build(&delete))
+        .await
+        .with_context(|| format!("Failed to delete swapped rows from {}", table_name))?;
+    let insert = Query::insert()
+        .into_table(E::default())
+        .columns(E::Column::iter())
+        .select_from(
+            Query::select()
+                .columns(E::Column::iter())
+                .from(staged_table_name.clone())
+                .and_where(Expr::col(canvas_column).in_subquery(select_staged_canvas_addresses()))
+                .to_owned(),
+        )
+        .with_context(|| format!("Failed to build copy into {}", table_name))?
+        .to_owned();
+    connection
+        .execute(backend.build(&insert))
+        .await
+        .with_context(|| format!("Failed to copy staged rows into {}", table_name))?;
+    connection
+        .execute(backend.build(Table::drop().table(staged_table_name)))
+        .await
+        .with_context(|| format!("Failed to drop staged {}", table_name))?;
+    Ok(())
+}
+
+/// Add the rows of the copy of the table of `E` made by `stage_table` that aren't in
+/// the table already, then drop the copy.
+async fn merge_in_staged_table<E: EntityTrait>(connection: &impl ConnectionTrait) -> Result<()> {
+    let table_name = E::default().table_name().to_string();
+    let staged_table_name = staged_table_name::<E>();
+    let backend = connection.get_database_backend();
+    let insert = Query::insert()
+        .into_table(E::default())
+        .columns(E::Column::iter())
+        .select_from(
+            Query::select()
+                .columns(E::Column::iter())
+                .from(staged_table_name.clone())
+                // SQLite can't tell an ON CONFLICT clause apart from a join constraint
+                // unless the select has a WHERE clause.
+                .and_where(Expr::cust("TRUE"))
+                .to_owned(),
+        )
+        .with_context(|| format!("Failed to build copy into {}", table_name))?
+        .on_conflict(
+            OnConflict::columns(E::PrimaryKey::iter().map(PrimaryKeyToColumn::into_column))
+                .do_nothing()
+                .to_owned(),
+        )
+        .to_owned();
+    connection
+        .execute(backend.build(&insert))
+        .await
+        .with_context(|| format!("Failed to copy staged rows into {}", table_name))?;
+    connection
+        .execute(backend.build(Table::drop().table(staged_table_name)))
+        .await
+        .with_context(|| format!("Failed to drop staged {}", table_name))?;
+    Ok(())
+}
+
+async fn clear_table<E: EntityTrait>(connection: &impl ConnectionTrait) -> Result<()> {
+    E::delete_many()
+        .exec(connection)
+        .await
+        .with_context(|| format!("Failed to clear {}", E::default().table_name()))?;
+    Ok(())
+}
+
+/// Insert rows into a table if it is the table of `E`. Returns whether it was.
+async fn insert_rows<E, A>(
+    connection: &impl ConnectionTrait,
+    table_name: &str,
+    rows: &[Value],
+) -> Result<bool>
+where
+    E: EntityTrait,
+    E::Model: DeserializeOwned + IntoActiveModel<A>,
+    A: ActiveModelTrait<Entity = E>,
+{
+    if E::default().table_name() != table_name {
+        return Ok(false);
+    }
+    let models = rows
+        .iter()
+        .map(|row| {
+            serde_json::from_value::<E::Model>(row.clone()).map(IntoActiveModel::into_active_model)
+        })
+        .collect::<Result<Vec<A>, _>>()
+        .with_context(|| format!("Failed to deserialize {}", table_name))?;
+
+    // Insert in chunks to stay under the limit on the number of bind parameters.
+    for chunk in models.chunks(TABLE_PAGE_SIZE as usize) {
+        E::insert_many(chunk.iter().cloned())
+            .exec(connection)
+            .await
+            .with_context(|| format!("Failed to insert into {}", table_name))?;
+    }
+
+    Ok(true)
+}
+
+/// Build the options for connecting to the DB. For SQLite we make sure the DB file
+/// gets created if necessary, or opened read only if `read_only` is set, and that we
+/// only use a single connection. The latter is
+/// necessary for in memory DBs, since each connection would otherwise get its own DB,
+/// and it avoids writers contending for the lock on the DB file.
+fn build_connect_options(connection_string: &str, read_only: bool) -> ConnectOptions {
+    if !connection_string.starts_with("sqlite:") {
+        return ConnectOptions::new(connection_string.to_string());
+    }
+
+    let mode = if read_only { "ro" } else { "rwc" };
+    let connection_string =
+        if connection_string.contains(":memory:") || connection_string.contains("mode=") {
+            connection_string.to_string()
+        } else if connection_string.contains('?') {
+            format!("{}&mode={}", connection_string, mode)
+        } else {
+            format!("{}?mode={}", connection_string, mode)
+        };
+
+    let mut options = ConnectOptions::new(connection_string);
+    options.max_connections(1).min_connections(1);
+    options
+}
+
+#[async_trait::async_trait]
+impl StorageTrait for DatabaseStorage {
+    async fn read_chain_id(&self) -> Result<Option<u8>> {
+        Ok(chain_id::Entity::find()
+            .one(&self.connection)
+            .await
+            .context("Failed to read ChainId")?
+            .map(|chain_id| chain_id.chain_id as u8))
+    }
+
+    async fn write_chain_id(&self, chain_id: u8) -> Result<()> {
+        let new_chain_id = chain_id::ActiveModel {
+            chain_id: sea_orm::Set(chain_id as i16),
+        };
+
+        let query = chain_id::Entity::insert(new_chain_id)
+            .on_conflict(
+                OnConflict::column(chain_id::Column::ChainId)
+                    .value(chain_id::Column::ChainId, chain_id)
+                    .to_owned(),
+            )
+            .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to update chain ID")?;
+
+        Ok(())
+    }
+
+    async fn read_last_processed_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(last_processed_version::Entity::find_by_id(processor_name)
+            .one(&self.connection)
+            .await
+            .context("Failed to read last processed version")?
+            .map(|lpv| lpv.version as u64))
+    }
+
+    async fn write_last_processed_version(&self, processor_name: &str, version: u64) -> Result<()> {
+        let new_last_processed_version = last_processed_version::ActiveModel {
+            processor_name: sea_orm::Set(processor_name.to_string()),
+            version: sea_orm::Set(version as i64),
+        };
+
+        let query = last_processed_version::Entity::insert(new_last_processed_version)
+            .on_conflict(
+                OnConflict::column(last_processed_version::Column::ProcessorName)
+                    .update_column(last_processed_version::Column::ProcessorName)
+                    .value(last_processed_version::Column::Version, version)
+                    .to_owned(),
+            )
+            .build(self.backend());
+
+        self.connection
+            .execute(query)
+            .await
+            .context("Failed to update last processed version")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn build_storage() -> DatabaseStorage {
+        DatabaseStorage::new(DatabaseStorageConfig {
+            connection_string: "sqlite::memory:".to_string(),
+        })
+        .await
+        .unwrap()
+    }
+
+    fn build_contribution(
+        canvas_address: &str,
+        artist_address: &str,
+        pixels_drawn: i64,
+        first_version: i64,
+        last_version: i64,
+    ) -> contributions::Model {
+        contributions::Model {
+            canvas_address: canvas_address.to_string(),
+            artist_address: artist_address.to_string(),
+            pixels_drawn,
+            first_contribution_version: first_version,
+            first_contribution_timestamp: Some(first_version * 10),
+            last_contribution_version: last_version,
+            last_contribution_timestamp: Some(last_version * 10),
+            octas_spent: pixels_drawn * 5,
+        }
+    }
+
+    fn build_pixel_writer(
+        canvas_address: &str,
+        pixel_index: i64,
+        artist_address: &str,
+    ) -> pixel_writers::Model {
+        pixel_writers::Model {
+            canvas_address: canvas_address.to_string(),
+            pixel_index,
+            artist_address: artist_address.to_string(),
+            version: pixel_index,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard() {
+        let storage = build_storage().await;
+        storage
+            .write_contributions(vec![
+                build_contribution("0xc1", "0xa1", 3, 1, 5),
+                build_contribution("0xc1", "0xa2", 1, 2, 2),
+                build_contribution("0xc2", "0xa2", 5, 6, 9),
+            ])
+            .await
+            .unwrap();
+        // Later contributions are added on.
+        storage
+            .write_contributions(vec![build_contribution("0xc1", "0xa1", 2, 10, 11)])
+            .await
+            .unwrap();
+        // Reprocessing contributions that were already counted changes nothing.
+        storage
+            .write_contributions(vec![build_contribution("0xc1", "0xa1", 2, 10, 11)])
+            .await
+            .unwrap();
+        storage
+            .write_pixel_writers(vec![
+                build_pixel_writer("0xc1", 0, "0xa1"),
+                build_pixel_writer("0xc1", 1, "0xa2"),
+                build_pixel_writer("0xc2", 0, "0xa2"),
+                build_pixel_writer("0xc2", 1, "0xa2"),
+            ])
+            .await
+            .unwrap();
+
+        let (total, totals) = storage.read_leaderboard(Some("0xc1"), 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(totals[0].artist_address, "0xa1");
+        assert_eq!(totals[0].pixels_drawn, 5);
+        assert_eq!(totals[0].octas_spent, 25);
+        assert_eq!(totals[0].first_contribution_version, 1);
+        assert_eq!(totals[0].last_contribution_version, 11);
+        assert_eq!(totals[0].last_contribution_timestamp, Some(110));
+        assert_eq!(totals[1].artist_address, "0xa2");
+
+        // Across the collection, 0xa2 has drawn the most.
+        let (total, totals) = storage.read_leaderboard(None, 1, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].artist_address, "0xa2");
+        assert_eq!(totals[0].pixels_drawn, 6);
+        assert_eq!(totals[0].first_contribution_version, 2);
+        assert_eq!(totals[0].last_contribution_version, 9);
+        let (_, totals) = storage.read_leaderboard(None, 1, 1).await.unwrap();
+        assert_eq!(totals[0].artist_address, "0xa1");
+
+        let artists = ["0xa1".to_string(), "0xa2".to_string()];
+        let visible = storage.count_visible_pixels(None, &artists).await.unwrap();
+        assert_eq!(visible["0xa1"], 1);
+        assert_eq!(visible["0xa2"], 3);
+        assert_eq!(
+            storage.count_canvas_visible_pixels("0xc2").await.unwrap(),
+            vec![("0xa2".to_string(), 2)]
+        );
+        assert_eq!(
+            storage
+                .read_artist_pixel_indices("0xc2", "0xa2")
+                .await
+                .unwrap(),
+            HashSet::from([0, 1])
+        );
+        let writer = storage.read_pixel_writer("0xc1", 1).await.unwrap().unwrap();
+        assert_eq!(writer.artist_address, "0xa2");
+
+        // Clearing a canvas removes all the pixels from it.
+        storage.delete_pixel_writers("0xc2").await.unwrap();
+        let visible = storage.count_visible_pixels(None, &artists).await.unwrap();
+        assert_eq!(visible["0xa2"], 1);
+        let visible = storage
+            .count_visible_pixels(Some("0xc2"), &artists)
+            .await
+            .unwrap();
+        assert!(visible.is_empty());
+    }
+
+    fn build_pixel_write(
+        canvas_address: &str,
+        pixel_index: i64,
+        version: i64,
+    ) -> pixel_history::Model {
+        pixel_history::Model {
+            canvas_address: canvas_address.to_string(),
+            pixel_index,
+            version,
+            artist_address: "0xa1".to_string(),
+            r: 255,
+            g: 0,
+            b: 0,
+            transaction_hash: format!("0x{:064x}", version),
+            timestamp: Some(version * 10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pixel_history() {
+        let storage = build_storage().await;
+        let pixel_history = vec![
+            build_pixel_write("0xc1", 0, 1),
+            build_pixel_write("0xc1", 0, 3),
+            build_pixel_write("0xc1", 0, 2),
+            build_pixel_write("0xc1", 1, 4),
+            build_pixel_write("0xc2", 0, 5),
+        ];
+        storage
+            .write_pixel_history(pixel_history.clone())
+            .await
+            .unwrap();
+        // Reprocessing draws that were already recorded changes nothing.
+        storage.write_pixel_history(pixel_history).await.unwrap();
+
+        let (total, page) = storage.read_pixel_history("0xc1", 0, 2, 0).await.unwrap();
+        assert_eq!(total, 3);
+        let versions: Vec<i64> = page.iter().map(|write| write.version).collect();
+        assert_eq!(versions, vec![3, 2]);
+        let (_, page) = storage.read_pixel_history("0xc1", 0, 2, 2).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].version, 1);
+
+        let (total, page) = storage.read_pixel_history("0xc1", 2, 10, 0).await.unwrap();
+        assert_eq!(total, 0);
+        assert!(page.is_empty());
+    }
+
+    fn build_canvas(address: &str, created_version: i64) -> canvases::Model {
+        canvases::Model {
+            address: address.to_string(),
+            deployment: "test".to_string(),
+            contract_address: "0xcafe".to_string(),
+            width: 2,
+            height: 2,
+            created_version,
+            cost: 0,
+            per_account_timeout_s: 0,
+            can_draw_for_s: 0,
+            created_at_s: created_version,
+            funds_recipient: None,
+            owner_address: None,
+            owner_is_super_admin: false,
+        }
+    }
+
+    fn build_quarantined_transaction(version: i64, error: &str) -> quarantined_transactions::Model {
+        quarantined_transactions::Model {
+            version,
+            deployment: "test".to_string(),
+            changes: "[]".to_string(),
+            error: error.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_swap_in_staged_tables() {
+        let storage = build_storage().await;
+        storage
+            .write_canvases(vec![build_canvas("0xc2", 1)])
+            .await
+            .unwrap();
+        storage
+            .write_pixel_history(vec![build_pixel_write("0xc2", 1, 1)])
+            .await
+            .unwrap();
+        storage
+            .write_last_processed_version("processor", 7)
+            .await
+            .unwrap();
+        // More than a page, so the copy takes a few pages.
+        let staging_storage = build_storage().await;
+        staging_storage
+            .write_canvases(vec![build_canvas("0xc2", 1)])
+            .await
+            .unwrap();
+        staging_storage
+            .write_pixel_history(
+                (0..TABLE_PAGE_SIZE as i64 + 1)
+                    .map(|version| build_pixel_write("0xc2", 0, version))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+
+        // Staging the tables leaves the ones in use alone.
+        storage
+            .stage_indexed_tables_from(&staging_storage)
+            .await
+            .unwrap();
+        let (total, _) = storage.read_pixel_history("0xc2", 1, 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+
+        storage.swap_in_staged_tables().await.unwrap();
+        let (total, _) = storage.read_pixel_history("0xc2", 1, 10, 0).await.unwrap();
+        assert_eq!(total, 0);
+        let (total, _) = storage.read_pixel_history("0xc2", 0, 10, 0).await.unwrap();
+        assert_eq!(total, TABLE_PAGE_SIZE + 1);
+        assert_eq!(
+            storage
+                .read_last_processed_version("processor")
+                .await
+                .unwrap(),
+            Some(7)
+        );
+
+        // The staged tables are dropped once swapped in, so we can stage again.
+        storage
+            .stage_indexed_tables_from(&staging_storage)
+            .await
+            .unwrap();
+        storage.swap_in_staged_tables().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_swap_in_staged_tables_keeps_older_canvases() {
+        // The live DB has a canvas created before the backfill started.
+        let storage = build_storage().await;
+        storage
+            .write_canvases(vec![build_canvas("0xc1", 1), build_canvas("0xc2", 5)])
+            .await
+            .unwrap();
+        storage
+            .write_contributions(vec![
+                build_contribution("0xc1", "0xa1", 2, 2, 3),
+                build_contribution("0xc2", "0xa1", 9, 6, 6),
+            ])
+            .await
+            .unwrap();
+        storage
+            .write_pixel_writers(vec![build_pixel_writer("0xc1", 0, "0xa1")])
+            .await
+            .unwrap();
+        storage
+            .write_quarantined_transactions(vec![build_quarantined_transaction(3, "live")])
+            .await
+            .unwrap();
+
+        // The backfill started after it, so only has the canvases created since.
+        let staging_storage = build_storage().await;
+        staging_storage
+            .write_canvases(vec![build_canvas("0xc2", 5)])
+            .await
+            .unwrap();
+        staging_storage
+            .write_contributions(vec![build_contribution("0xc2", "0xa1", 1, 6, 6)])
+            .await
+            .unwrap();
+        staging_storage
+            .write_quarantined_transactions(vec![
+                build_quarantined_transaction(3, "staging"),
+                build_quarantined_transaction(6, "staging"),
+            ])
+            .await
+            .unwrap();
+
+        storage
+            .stage_indexed_tables_from(&staging_storage)
+            .await
+            .unwrap();
+        storage.swap_in_staged_tables().await.unwrap();
+
+        // The older canvas keeps its rows, the backfilled one gets the staged ones.
+        assert!(storage.read_canvas("0xc1").await.unwrap().is_some());
+        assert!(storage.read_canvas("0xc2").await.unwrap().is_some());
+        let contributions: Vec<_> = storage
+            .read_artist_contributions("0xa1")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|contribution| (contribution.canvas_address, contribution.pixels_drawn))
+            .collect();
+        assert_eq!(
+            contributions,
+            vec![("0xc1".to_string(), 2), ("0xc2".to_string(), 1)]
+        );
+        assert!(storage
+            .read_pixel_writer("0xc1", 0)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Quarantined txns already recorded are kept, and new ones are added.
+        let quarantined: Vec<_> = storage
+            .read_quarantined_transactions(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|quarantined| (quarantined.version, quarantined.error))
+            .collect();
+        assert_eq!(
+            quarantined,
+            vec![(3, "live".to_string()), (6, "staging".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artist_permissions() {
+        let storage = build_storage().await;
+        let canvases = ["0xc1".to_string(), "0xc2".to_string(), "0xc3".to_string()];
+        storage
+            .write_canvas_artist_lists("0xc1", vec![], vec!["0xa1".to_string()])
+            .await
+            .unwrap();
+        storage
+            .write_canvas_artist_lists("0xc2", vec!["0xa2".to_string()], vec![])
+            .await
+            .unwrap();
+
+        let permissions = storage
+            .read_artist_permissions("0xa1", &canvases)
+            .await
+            .unwrap();
+        assert_eq!(permissions["0xc1"], ArtistPermission::InBlocklist);
+        assert_eq!(permissions["0xc2"], ArtistPermission::NotInAllowlist);
+        assert_eq!(permissions["0xc3"], ArtistPermission::Allowed);
+        let permissions = storage
+            .read_artist_permissions("0xa2", &canvases)
+            .await
+            .unwrap();
+        assert_eq!(permissions["0xc1"], ArtistPermission::Allowed);
+        assert_eq!(permissions["0xc2"], ArtistPermission::Allowed);
+
+        // Writing the lists again replaces them.
+        storage
+            .write_canvas_artist_lists("0xc1", vec![], vec![])
+            .await
+            .unwrap();
+        let permissions = storage
+            .read_artist_permissions("0xa1", &canvases)
+            .await
+            .unwrap();
+        assert_eq!(permissions["0xc1"], ArtistPermission::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let directory =
+            std::env::temp_dir().join(format!("read-only-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let config = DatabaseStorageConfig {
+            connection_string: format!("sqlite://{}", directory.join("canvas.db").display()),
+        };
+
+        // The processor hasn't created the DB yet, and we won't create it.
+        assert!(DatabaseStorage::new_read_only(config.clone())
+            .await
+            .is_err());
+        assert!(!directory.join("canvas.db").exists());
+
+        // Once the processor has migrated it we can read from it, but not write to it.
+        DatabaseStorage::new(config.clone()).await.unwrap();
+        let storage = DatabaseStorage::new_read_only(config).await.unwrap();
+        assert!(storage.read_canvases(None, None).await.unwrap().is_empty());
+        assert!(storage.write_chain_id(1).await.is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
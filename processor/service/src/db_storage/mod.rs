@@ -1,5 +1,8 @@
+mod database;
+#[cfg(test)]
 mod memory;
-mod postgres;
 
-pub use memory::MemoryStorage;
-pub use postgres::{PostgresStorage, PostgresStorageConfig};
+pub use database::{
+    ArtistPermission, ContributionTotals, DatabaseStorage, DatabaseStorageConfig, ObjectKind,
+    RevenueGrouping, RevenueTotals, TableSink, TrackedObject,
+};
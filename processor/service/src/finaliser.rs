@@ -0,0 +1,259 @@
+//! Finalising freezes the image of a canvas once it has closed, i.e. it is past its
+//! `can_draw_for_s` deadline. The PNG and its sha256 are stored in the DB and never
+//! change after that, so they can be used e.g. as the image of an NFT, even if the
+//! canvas itself is later cleared.
+//!
+//! We only consider a canvas closed once the processor has processed a txn from
+//! after the deadline. Txn timestamps never go backwards, so by then no more draws
+//! can land on the canvas and the image in canvas storage is final.
+
+use crate::{
+    canvas_storage::CanvasStorageTrait, db_storage::DatabaseStorage, metrics::CANVASES_FINALISED,
+    processor::CANVAS_PROCESSOR_NAME,
+};
+use anyhow::{Context, Result};
+use aptos_move_graphql_scalars::Address;
+use entities::{canvas_finalisations, canvases};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FinaliserConfig {
+    /// How often to check for canvases that have closed.
+    #[serde(default = "FinaliserConfig::default_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+impl FinaliserConfig {
+    pub fn default_check_interval_secs() -> u64 {
+        60
+    }
+}
+
+impl Default for FinaliserConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: Self::default_check_interval_secs(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finaliser {
+    config: FinaliserConfig,
+    canvas_storage: Arc<dyn CanvasStorageTrait>,
+    storage: Arc<DatabaseStorage>,
+}
+
+impl Finaliser {
+    pub fn new(
+        config: FinaliserConfig,
+        canvas_storage: Arc<dyn CanvasStorageTrait>,
+        storage: Arc<DatabaseStorage>,
+    ) -> Self {
+        Self {
+            config,
+            canvas_storage,
+            storage,
+        }
+    }
+
+    /// Finalise canvases as they close, forever. Failures are logged and retried on
+    /// the next check, they shouldn't stop the processor.
+    pub async fn run(&self) {
+        let interval = Duration::from_secs(self.config.check_interval_secs);
+        loop {
+            if let Err(e) = self.finalise_closed_canvases().await {
+                error!("Failed to finalise closed canvases: {:#}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Finalise every canvas that has closed but hasn't been finalised yet, returning
+    /// how many were finalised. A canvas that fails to finalise doesn't stop the
+    /// others, it is just tried again next time.
+    pub async fn finalise_closed_canvases(&self) -> Result<usize> {
+        let processed_until_s = match self
+            .storage
+            .read_processor_status(CANVAS_PROCESSOR_NAME)
+            .await?
+            .and_then(|status| status.last_transaction_timestamp)
+        {
+            Some(processed_until_s) => processed_until_s,
+            // The processor hasn't processed anything yet.
+            None => return Ok(0),
+        };
+        let mut num_finalised = 0;
+        for canvas in self
+            .storage
+            .read_canvases_to_finalise(processed_until_s)
+            .await?
+        {
+            match self.finalise_canvas(&canvas).await {
+                Ok(()) => num_finalised += 1,
+                Err(e) => error!(
+                    canvas_address = canvas.address,
+                    "Failed to finalise canvas: {:#}", e
+                ),
+            }
+        }
+        Ok(num_finalised)
+    }
+
+    async fn finalise_canvas(&self, canvas: &canvases::Model) -> Result<()> {
+        let address = Address::from_str(&canvas.address).context("Invalid canvas address")?;
+        let png = self
+            .canvas_storage
+            .get_canvas_as_png(&address)
+            .await
+            .context("Failed to get image")?;
+        let finalised_at_s = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System time is before the epoch")?
+            .as_secs() as i64;
+        let applied_version = png.applied_version;
+        let content_hash = hex::encode(Sha256::digest(&png.data));
+        self.storage
+            .write_canvas_finalisation(canvas_finalisations::Model {
+                canvas_address: canvas.address.clone(),
+                closed_at_s: canvas.created_at_s + canvas.can_draw_for_s,
+                applied_version: applied_version as i64,
+                png: png.data,
+                content_hash: content_hash.clone(),
+                finalised_at_s,
+            })
+            .await?;
+        CANVASES_FINALISED
+            .with_label_values(&[&canvas.deployment])
+            .inc();
+        info!(
+            canvas_address = canvas.address,
+            applied_version = applied_version,
+            content_hash = content_hash,
+            "Finalised canvas"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        canvas_storage::{CreateCanvasIntent, MemoryCanvasStorage},
+        db_storage::DatabaseStorageConfig,
+        generated::Color,
+    };
+    use entities::processor_status;
+
+    fn build_canvas(address: &str, created_at_s: i64, can_draw_for_s: i64) -> canvases::Model {
+        canvases::Model {
+            address: address.to_string(),
+            deployment: "test".to_string(),
+            contract_address: "0xcafe".to_string(),
+            width: 2,
+            height: 2,
+            created_version: created_at_s,
+            cost: 0,
+            per_account_timeout_s: 0,
+            can_draw_for_s,
+            created_at_s,
+            funds_recipient: None,
+            owner_address: None,
+            owner_is_super_admin: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finalise_closed_canvases() {
+        let canvas_storage = Arc::new(MemoryCanvasStorage::new());
+        let storage = Arc::new(
+            DatabaseStorage::new(DatabaseStorageConfig {
+                connection_string: "sqlite::memory:".to_string(),
+            })
+            .await
+            .unwrap(),
+        );
+        let finaliser = Finaliser::new(
+            FinaliserConfig::default(),
+            canvas_storage.clone(),
+            storage.clone(),
+        );
+
+        // 0xc1 closes at 150, 0xc2 at 300, and 0xc3 never closes.
+        let canvases = vec![
+            build_canvas("0xc1", 100, 50),
+            build_canvas("0xc2", 100, 200),
+            build_canvas("0xc3", 100, 0),
+        ];
+        for canvas in &canvases {
+            canvas_storage
+                .create_canvas(CreateCanvasIntent {
+                    canvas_address: Address::from_str(&canvas.address).unwrap(),
+                    width: 2,
+                    height: 2,
+                    default_color: Color { r: 0, g: 0, b: 0 },
+                })
+                .await
+                .unwrap();
+        }
+        storage.write_canvases(canvases).await.unwrap();
+
+        // Nothing is finalised until the processor has processed something.
+        assert_eq!(finaliser.finalise_closed_canvases().await.unwrap(), 0);
+
+        // Artists can still draw at the deadline itself.
+        let write_status = |last_transaction_timestamp| processor_status::Model {
+            processor_name: CANVAS_PROCESSOR_NAME.to_string(),
+            processed_version: 10,
+            last_transaction_timestamp: Some(last_transaction_timestamp),
+            latest_stream_version: 10,
+        };
+        storage
+            .write_processor_status(write_status(150))
+            .await
+            .unwrap();
+        assert_eq!(finaliser.finalise_closed_canvases().await.unwrap(), 0);
+
+        storage
+            .write_processor_status(write_status(151))
+            .await
+            .unwrap();
+        assert_eq!(finaliser.finalise_closed_canvases().await.unwrap(), 1);
+        let finalisation = storage
+            .read_canvas_finalisation("0xc1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(finalisation.closed_at_s, 150);
+        assert_eq!(
+            finalisation.content_hash,
+            hex::encode(Sha256::digest(&finalisation.png))
+        );
+        assert!(storage
+            .read_canvas_finalisation("0xc2")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Canvases are only finalised once, and canvases that never close never are.
+        storage
+            .write_processor_status(write_status(1000))
+            .await
+            .unwrap();
+        assert_eq!(finaliser.finalise_closed_canvases().await.unwrap(), 1);
+        assert_eq!(finaliser.finalise_closed_canvases().await.unwrap(), 0);
+        assert!(storage
+            .read_canvas_finalisation("0xc3")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! Hand written types for the resources and table items we decode. The generated
+//! types can't express Move generics, so e.g. the key and value of a SmartTable
+//! `Entry` come out as `Any`. These types shadow the generated types of the same
+//! name, see `mod.rs`.
+
+use super::objects::{
+    _0x0000000000000000000000000000000000000000000000000000000000000004__token__MutatorRef, Color,
+    ExtendRef, TableWithLength,
+};
+use aptos_move_graphql_scalars::{Address, U64, U8};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Canvas {
+    pub config: CanvasConfig,
+    pub pixels: SmartTable<U64, Color>,
+    pub last_contribution_s: SmartTable<Address, U64>,
+    pub allowlisted_artists: SimpleSet<Address>,
+    pub blocklisted_artists: SimpleSet<Address>,
+    pub admins: SimpleSet<Address>,
+    pub created_at_s: U64,
+    pub extend_ref: ExtendRef,
+    pub mutator_ref:
+        _0x0000000000000000000000000000000000000000000000000000000000000004__token__MutatorRef,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CanvasConfig {
+    pub width: U64,
+    pub height: U64,
+    pub per_account_timeout_s: U64,
+    pub can_draw_for_s: U64,
+    pub palette: Vec<Color>,
+    pub cost: U64,
+    pub funds_recipient: MoveOption<Address>,
+    pub default_color: Color,
+    pub owner_is_super_admin: bool,
+}
+
+/// A `0x1::smart_table::SmartTable<K, V>`. The entries aren't part of the resource,
+/// they're stored in the `buckets` table, where each item is a `SmartTableBucket`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SmartTable<K, V> {
+    pub buckets: TableWithLength,
+    pub num_buckets: U64,
+    pub level: U8,
+    pub size: U64,
+    pub split_load_threshold: U8,
+    pub target_bucket_size: U64,
+    #[serde(skip)]
+    _entry: PhantomData<(K, V)>,
+}
+
+/// The value of an item in the `buckets` table of a `SmartTable<K, V>`. Every time
+/// an entry in a bucket changes, the whole bucket is written.
+pub type SmartTableBucket<K, V> = Vec<Entry<K, V>>;
+
+/// A `0x1::smart_table::Entry<K, V>`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry<K, V> {
+    pub hash: U64,
+    pub key: K,
+    pub value: V,
+}
+
+/// A `SimpleSet<T>`, a set backed by a vector.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimpleSet<T> {
+    pub data: Vec<T>,
+}
+
+/// A `0x1::option::Option<T>`, which is represented as a vector with at most one
+/// element.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MoveOption<T> {
+    pub vec: Vec<T>,
+}
+
+impl<T> MoveOption<T> {
+    pub fn as_option(&self) -> Option<&T> {
+        self.vec.first()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+
+    /// The data of a `Canvas` `WriteResource` from a create txn.
+    const CANVAS_JSON: &str = r#"{
+        "admins": {"data": ["0xfbc45a84bd65b000d259ac91a8f314c93313e6d6787dbac71bdaf044f661a4f8"]},
+        "allowlisted_artists": {"data": []},
+        "blocklisted_artists": {"data": ["0x000000000000000000000000000000000000000000000000000000000000beef"]},
+        "config": {
+            "can_draw_for_s": "86400",
+            "cost": "0",
+            "default_color": {"b": 255, "g": 255, "r": 255},
+            "funds_recipient": {"vec": ["0xfbc45a84bd65b000d259ac91a8f314c93313e6d6787dbac71bdaf044f661a4f8"]},
+            "height": "1000",
+            "owner_is_super_admin": true,
+            "palette": [{"b": 0, "g": 0, "r": 0}, {"b": 255, "g": 255, "r": 255}],
+            "per_account_timeout_s": "5",
+            "width": "1000"
+        },
+        "created_at_s": "1693526400",
+        "extend_ref": {"self": "0x5c1d2e0e5a7f3b2f3b55f1e0a1b1f2e9e5a6a1c1d2b4f9b3c2e6c7e8e9f0a1b2"},
+        "last_contribution_s": {
+            "buckets": {
+                "inner": {"handle": "0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f801"},
+                "length": "1"
+            },
+            "level": 0,
+            "num_buckets": "1",
+            "size": "0",
+            "split_load_threshold": 75,
+            "target_bucket_size": "0"
+        },
+        "mutator_ref": {"self": "0x5c1d2e0e5a7f3b2f3b55f1e0a1b1f2e9e5a6a1c1d2b4f9b3c2e6c7e8e9f0a1b2"},
+        "pixels": {
+            "buckets": {
+                "inner": {"handle": "0x9f8e7d6c5b4a39281706f5e4d3c2b1a09f8e7d6c5b4a39281706f5e4d3c2b1a0"},
+                "length": "2"
+            },
+            "level": 1,
+            "num_buckets": "2",
+            "size": "3",
+            "split_load_threshold": 75,
+            "target_bucket_size": "2"
+        }
+    }"#;
+
+    /// The value of a pixels `WriteTableItem` from a draw txn.
+    const BUCKET_JSON: &str = r#"[
+        {"hash": "8354184394138436213", "key": "4", "value": {"b": 0, "g": 0, "r": 0}},
+        {"hash": "1183713384726514912", "key": "1999", "value": {"b": 12, "g": 200, "r": 255}}
+    ]"#;
+
+    /// Deserialize `json` as `T`, serialize it again, and check nothing was lost.
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(json: &str) -> T {
+        let original: Value = serde_json::from_str(json).unwrap();
+        let decoded: T = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), original);
+        decoded
+    }
+
+    #[test]
+    fn test_canvas_round_trip() {
+        let canvas: Canvas = round_trip(CANVAS_JSON);
+        assert_eq!(canvas.config.width.0, 1000);
+        assert_eq!(canvas.config.height.0, 1000);
+        assert_eq!(canvas.config.palette.len(), 2);
+        assert_eq!(
+            canvas.config.funds_recipient.as_option().copied(),
+            Some(canvas.admins.data[0])
+        );
+        assert_eq!(canvas.pixels.num_buckets.0, 2);
+        assert_eq!(canvas.blocklisted_artists.data.len(), 1);
+        assert!(canvas.allowlisted_artists.data.is_empty());
+    }
+
+    #[test]
+    fn test_canvas_without_funds_recipient() {
+        let mut json: Value = serde_json::from_str(CANVAS_JSON).unwrap();
+        json["config"]["funds_recipient"] = json!({"vec": []});
+        let canvas: Canvas = round_trip(&json.to_string());
+        assert!(canvas.config.funds_recipient.as_option().is_none());
+    }
+
+    #[test]
+    fn test_bucket_round_trip() {
+        let bucket: SmartTableBucket<U64, Color> = round_trip(BUCKET_JSON);
+        assert_eq!(bucket.len(), 2);
+        assert_eq!(bucket[1].key.0, 1999);
+        assert_eq!(
+            (bucket[1].value.r, bucket[1].value.g, bucket[1].value.b),
+            (255, 200, 12)
+        );
+    }
+
+    #[test]
+    fn test_simple_set_round_trip() {
+        let set: SimpleSet<Address> = round_trip(
+            r#"{"data": ["0x0000000000000000000000000000000000000000000000000000000000000001"]}"#,
+        );
+        assert_eq!(set.data, vec![Address::from_str("0x1").unwrap()]);
+    }
+
+    #[test]
+    fn test_invalid_bucket() {
+        // A key that isn't a u64 shouldn't decode.
+        let json = r#"[{"hash": "1", "key": "-4", "value": {"b": 0, "g": 0, "r": 0}}]"#;
+        assert!(serde_json::from_str::<SmartTableBucket<U64, Color>>(json).is_err());
+    }
+}
@@ -9,6 +9,14 @@
 // This file was generated by https://github.com/tacogips/async-graphql-reverse
 mod objects;
 pub use objects::*;
+// These are hand written, see the module for why. Importing them explicitly means they
+// take precedence over the generated types of the same name.
+#[allow(dead_code)]
+mod canvas;
+#[allow(unused_imports)]
+pub use canvas::{
+    Canvas, CanvasConfig, Entry, MoveOption, SimpleSet, SmartTable, SmartTableBucket,
+};
 // Skip building schema_builder() due to no query defined.
 // // example schema_builder()
 // pub fn schema_builder() -> SchemaBuilder<YourQueryType, EmptyMutation, EmptySubscription> {
@@ -1,13 +1,29 @@
 mod api;
+mod backfill;
 mod canvas_storage;
+mod chain_id;
+mod commands;
 mod config;
 mod db_storage;
+mod finaliser;
 mod generated;
+mod metrics;
 mod processor;
+mod stream_recording;
+mod stream_tracking;
+mod txn_utils;
 
 use crate::{
-    config::{Args, Config},
+    backfill::Backfill,
+    chain_id::{check_chain_id, fetch_stream_chain_id},
+    config::{Args, Command, Config, RunArgs, RunMode},
+    finaliser::Finaliser,
     processor::CanvasProcessor,
+    stream_recording::{
+        read_recording_chain_id, write_recording_chain_id, RecordingStreamSubscriber,
+        ReplayStreamSubscriber,
+    },
+    stream_tracking::VersionTrackingStreamSubscriber,
 };
 use anyhow::{Context as AnyhowContext, Result};
 use api::Api;
@@ -16,15 +32,14 @@ use aptos_processor_framework::{
 };
 use canvas_storage::MmapCanvasStorage;
 use clap::Parser;
-use db_storage::PostgresStorage;
+use db_storage::DatabaseStorage;
 use std::sync::Arc;
-use tracing::Level;
+use tracing::{error, Level};
 use tracing_subscriber::FmtSubscriber;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    let config = Config::try_from(args)?;
 
     let subscriber = FmtSubscriber::builder()
         // All spans of this level or more severe will be written to stdout.
@@ -34,24 +49,128 @@ async fn main() -> Result<()> {
     tracing::subscriber::set_global_default(subscriber)
         .context("Setting default tracing subscriber failed")?;
 
+    match args.command {
+        Command::Run(run_args) => run(run_args).await,
+        Command::Render(render_args) => commands::render(render_args),
+        Command::Inspect(inspect_args) => commands::inspect(inspect_args),
+        Command::Verify(verify_args) => commands::verify(verify_args),
+        Command::Reconcile(reconcile_args) => commands::reconcile(reconcile_args).await,
+        Command::ExportSnapshot(export_args) => commands::export_snapshot(export_args).await,
+        Command::ImportSnapshot(import_args) => commands::import_snapshot(import_args).await,
+    }
+}
+
+async fn run(run_args: RunArgs) -> Result<()> {
+    let config = Config::load(&run_args.config_path)?;
+    config.validate()?;
+
+    if run_args.backfill_start_version.is_some() && run_args.mode == RunMode::Api {
+        anyhow::bail!("Backfilling requires running the processor");
+    }
+
     // Build canvas storage, which is what lets us read and write to the representation
-    // of the canvas on disk.
-    let canvas_storage = Arc::new(MmapCanvasStorage::new(config.canvas_storage_config.clone()));
+    // of the canvas on disk. If we're only running the API we don't own the canvas
+    // files, we just follow along with the processor writing them.
+    let canvas_storage = Arc::new(match run_args.mode {
+        RunMode::Api => MmapCanvasStorage::new_follower(config.canvas_storage_config.clone())?,
+        RunMode::All | RunMode::Processor => {
+            MmapCanvasStorage::new(config.canvas_storage_config.clone())
+        },
+    });
 
     // Build the storage, which is what lets us read and write to the DB. This is
     // generally necessary for all processors since they need somewhere to at least
-    // keep track of the last version they processed.
-    let storage = Arc::new(
-        PostgresStorage::new(config.postgres_storage_config.clone())
+    // keep track of the last version they processed. If we're only running the API
+    // we only read from it, and leave migrating it to the processor.
+    let storage = Arc::new(match run_args.mode {
+        RunMode::Api => DatabaseStorage::new_read_only(config.database_storage_config.clone())
             .await
-            .context("Failed to initialize Postgres storage")?,
+            .context("Failed to initialize read only database storage")?,
+        RunMode::All | RunMode::Processor => {
+            DatabaseStorage::new(config.database_storage_config.clone())
+                .await
+                .context("Failed to initialize database storage")?
+        },
+    });
+
+    // Build the API, which can serve the canvases as pngs and also serve any of the
+    // restructured information the processor put in the DB.
+    let api = Api::new(
+        config.api_config.clone(),
+        canvas_storage.clone(),
+        storage.clone(),
+        config
+            .canvas_storage_config
+            .canvas_storage_directory
+            .clone(),
+        run_args.mode == RunMode::Api,
+    );
+
+    // Build the finaliser, which freezes the images of canvases once they close.
+    // Like the processor, only one instance should run it.
+    let finaliser = Finaliser::new(
+        config.finaliser_config.clone(),
+        canvas_storage.clone(),
+        storage.clone(),
     );
 
+    match run_args.mode {
+        RunMode::All => {
+            let (mut dispatcher, backfill) =
+                build_dispatcher(&config, &run_args, canvas_storage, storage).await?;
+            let api_fut = api.start_api();
+            let dispatcher_fut = dispatcher.dispatch();
+            let result = futures::join!(
+                api_fut,
+                dispatcher_fut,
+                run_backfill(backfill),
+                finaliser.run()
+            );
+            Err(anyhow::anyhow!(
+                "One of the futures finished unexpectedly: {:#?}",
+                result
+            ))
+        },
+        RunMode::Api => api.start_api().await,
+        RunMode::Processor => {
+            let (mut dispatcher, backfill) =
+                build_dispatcher(&config, &run_args, canvas_storage, storage).await?;
+            let metrics_fut = async {
+                match config.api_config.metrics_port {
+                    Some(port) => api.start_metrics_server(port).await,
+                    // There is nothing to serve, so just wait with the dispatcher.
+                    None => futures::future::pending().await,
+                }
+            };
+            let result = futures::join!(
+                metrics_fut,
+                dispatcher.dispatch(),
+                run_backfill(backfill),
+                finaliser.run()
+            );
+            Err(anyhow::anyhow!(
+                "One of the futures finished unexpectedly: {:#?}",
+                result
+            ))
+        },
+    }
+}
+
+/// Build everything necessary for ingesting txns and return the dispatcher that
+/// drives it. This also starts the stream subscriber. If a backfill was requested,
+/// that is built too.
+async fn build_dispatcher(
+    config: &Config,
+    run_args: &RunArgs,
+    canvas_storage: Arc<MmapCanvasStorage>,
+    storage: Arc<DatabaseStorage>,
+) -> Result<(Dispatcher, Option<Backfill>)> {
     // Build the canvas processor, which is what processes transactions and updates the
     // canvas storage and the DB.
     let processor = Arc::new(CanvasProcessor::new(
         config.canvas_processor_config.clone(),
         canvas_storage.clone(),
+        storage.clone(),
     ));
 
     // From the DB, read the last version we processed.
@@ -64,42 +183,112 @@ async fn main() -> Result<()> {
     let starting_version = config
         .common_storage_config
         .determine_starting_version(starting_version_from_db);
+    *processor.processed_version().lock().await = starting_version.checked_sub(1);
+
+    // Make sure the DB and canvas directory were built from the same chain the txn
+    // stream is serving, so we never mix data from different networks.
+    let stream_chain_id = match &run_args.replay_stream {
+        Some(directory) => read_recording_chain_id(directory)?,
+        None => {
+            fetch_stream_chain_id(
+                &config.stream_subscriber_config,
+                processor.name(),
+                starting_version,
+            )
+            .await?
+        },
+    };
+    check_chain_id(
+        stream_chain_id,
+        &storage,
+        &config.canvas_storage_config.canvas_storage_directory,
+        run_args.allow_chain_id_change,
+    )
+    .await?;
 
     // Build the stream subscriber, which subscribes to txn stream service and pushes
-    // the txns to an internal channel.
-    let stream_subscriber = GrpcStreamSubscriber {
+    // the txns to an internal channel. Alternatively it can record what it receives,
+    // or replay a recording instead of subscribing to the txn stream service.
+    let grpc_stream_subscriber = GrpcStreamSubscriber {
         config: config.stream_subscriber_config.clone(),
         processor_name: processor.name().to_string(),
         starting_version,
     };
+    let stream_subscriber: Box<dyn StreamSubscriberTrait> =
+        match (&run_args.replay_stream, &run_args.record_stream) {
+            (Some(directory), _) => Box::new(ReplayStreamSubscriber {
+                directory: directory.clone(),
+                starting_version,
+            }),
+            (None, Some(directory)) => {
+                write_recording_chain_id(directory, stream_chain_id)?;
+                Box::new(RecordingStreamSubscriber {
+                    inner: grpc_stream_subscriber,
+                    directory: directory.clone(),
+                })
+            },
+            (None, None) => Box::new(grpc_stream_subscriber),
+        };
+
+    // Keep track of how far along the stream is, so we can report how far behind it
+    // the processor is.
+    let stream_subscriber = VersionTrackingStreamSubscriber {
+        inner: stream_subscriber,
+        latest_version: processor.latest_stream_version(),
+    };
 
     // Start the stream subscriber.
     // TODO: Idk if this should spawn a tokio worker, it should just return a
     // future that the caller and do whatever they want with.
     let channel_handle = stream_subscriber.start().await?;
 
+    // Build the backfill, which runs alongside the processor so it can swap its
+    // results in without stopping the processor.
+    let backfill = match run_args.backfill_start_version {
+        Some(backfill_start_version) => Some(
+            Backfill::new(
+                config
+                    .backfill_config
+                    .clone()
+                    .context("Backfilling requires backfill_config to be set")?,
+                backfill_start_version,
+                run_args.backfill_end_version,
+                config.stream_subscriber_config.clone(),
+                config.canvas_processor_config.clone(),
+                config
+                    .canvas_storage_config
+                    .canvas_storage_directory
+                    .clone(),
+                canvas_storage,
+                storage.clone(),
+                processor.processed_version(),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
     // Build the dispatcher, which is what reads from the channel and dispatches txns
     // to the processor.
-    let mut dispatcher = Dispatcher {
+    let dispatcher = Dispatcher {
         config: config.dispatcher_config.clone(),
-        storage: storage.clone(),
-        processor: processor.clone(),
+        storage,
+        processor,
         receiver: channel_handle.receiver,
         starting_version,
     };
 
-    // Build the API, which can serve the canvases as pngs and also serve any of the
-    // restructured information the processor put in the DB.
-    let api = Api::new(config.api_config.clone(), canvas_storage.clone());
-
-    let api_fut = api.start_api();
-    let dispatcher_fut = dispatcher.dispatch();
-    let result = futures::join!(api_fut, dispatcher_fut);
+    Ok((dispatcher, backfill))
+}
 
-    Err(anyhow::anyhow!(
-        "One of the futures finished unexpectedly: {:#?}",
-        result
-    ))
+/// Run the backfill, if there is one. If it fails we just log it, it shouldn't stop
+/// the processor.
+async fn run_backfill(backfill: Option<Backfill>) {
+    if let Some(backfill) = backfill {
+        if let Err(e) = backfill.run().await {
+            error!("Backfill failed: {:#}", e);
+        }
+    }
 }
 
 // For some reason the below snippet fails to compile with the following error:
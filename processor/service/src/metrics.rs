@@ -0,0 +1,135 @@
+//! Prometheus metrics. These are all registered in the default registry, which is
+//! served at `/metrics` by the API.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec, TextEncoder,
+};
+
+/// The last version each processor finished processing, by processor name.
+pub static PROCESSED_VERSION: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "canvas_processor_processed_version",
+        "Last version the processor finished processing",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// How far behind the chain each processor is, based on the timestamp of the last
+/// txn it processed.
+pub static LEDGER_LAG_SECONDS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "canvas_processor_ledger_lag_seconds",
+        "Seconds between the timestamp of the last txn processed and now",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// How long each batch took to process, by processor name.
+pub static BATCH_PROCESSING_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "canvas_processor_batch_processing_seconds",
+        "Time taken to process a batch of txns",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// How many pixels were written.
+pub static PIXELS_WRITTEN: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("canvas_pixels_written_total", "Number of pixels written").unwrap()
+});
+
+/// How many canvases were created, by deployment.
+pub static CANVASES_CREATED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "canvas_canvases_created_total",
+        "Number of canvases created",
+        &["deployment"]
+    )
+    .unwrap()
+});
+
+/// How many canvases were finalised after closing, by deployment.
+pub static CANVASES_FINALISED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "canvas_canvases_finalised_total",
+        "Number of canvases finalised after closing",
+        &["deployment"]
+    )
+    .unwrap()
+});
+
+/// How many txns failed to decode, by deployment. This counts them whatever the
+/// decode failure policy is.
+pub static DECODE_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "canvas_decode_failures_total",
+        "Number of txns that failed to decode",
+        &["deployment"]
+    )
+    .unwrap()
+});
+
+/// How many txns failed to decode and were quarantined, by deployment.
+pub static QUARANTINED_TRANSACTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "canvas_quarantined_transactions_total",
+        "Number of txns that failed to decode and were quarantined",
+        &["deployment"]
+    )
+    .unwrap()
+});
+
+/// How many draws paid for we couldn't record a payment for, because we couldn't
+/// tell who was paid.
+pub static UNRECORDED_PAYMENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "canvas_unrecorded_payments_total",
+        "Number of draws paid for that we couldn't record a payment for"
+    )
+    .unwrap()
+});
+
+/// Lookups of the mmap cache used to render canvases, by result (`hit` or `miss`).
+pub static RENDER_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "canvas_render_cache_lookups_total",
+        "Number of lookups of the canvas mmap cache",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// How long it took to encode a canvas as a PNG.
+pub static PNG_ENCODE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "canvas_png_encode_seconds",
+        "Time taken to encode a canvas as a PNG"
+    )
+    .unwrap()
+});
+
+/// How long the API took to respond, by method, route and status code.
+pub static HTTP_REQUEST_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "canvas_http_request_seconds",
+        "Time taken to respond to HTTP requests",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+/// Encode every registered metric in the Prometheus text format.
+pub fn encode_metrics() -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .context("Failed to encode metrics")?;
+    Ok(buffer)
+}
@@ -1,47 +1,180 @@
 use crate::{
     canvas_storage::{CanvasStorageTrait, CreateCanvasIntent, WritePixelIntent},
-    generated::{Canvas, Color, Entry, Object},
+    db_storage::{DatabaseStorage, ObjectKind, TrackedObject},
+    generated::{Canvas, Color, Object, ObjectCore, SmartTableBucket},
+    metrics::{
+        BATCH_PROCESSING_SECONDS, CANVASES_CREATED, DECODE_FAILURES, LEDGER_LAG_SECONDS,
+        PIXELS_WRITTEN, PROCESSED_VERSION, QUARANTINED_TRANSACTIONS, UNRECORDED_PAYMENTS,
+    },
+    txn_utils::{
+        get_entry_function_id, get_entry_function_payload, get_transaction_kind,
+        get_user_transaction, get_user_transaction_request, is_successful, TransactionKind,
+    },
 };
 use anyhow::{Context as AnyhowContext, Result};
-use aptos_move_graphql_scalars::Address;
+use aptos_move_graphql_scalars::{Address, U64};
 use aptos_processor_framework::{
     indexer_protos::transaction::v1::{
-        transaction::TxnData, transaction_payload::Payload, write_set_change::Change,
-        EntryFunctionId, MoveModuleId, MoveStructTag, Transaction,
+        write_set_change::Change, EntryFunctionId, MoveModuleId, MoveStructTag, Transaction,
+        WriteSetChange,
     },
     txn_parsers::get_clean_entry_function_payload,
     ProcessingResult, ProcessorTrait,
 };
+use entities::{
+    canvases, contribution_timeouts, contributions, funds_recipient_changes, ownership_changes,
+    payments, pixel_history, pixel_writers, processor_status, quarantined_transactions,
+};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::{str::FromStr, sync::Arc};
-use tracing::info;
+use std::{
+    collections::{hash_map, BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
 const CANVAS_TOKEN_MODULE_NAME: &str = "canvas_token";
+const CANVAS_COLLECTION_MODULE_NAME: &str = "canvas_collection";
+
+/// The name the processor records its progress under in the DB.
+pub const CANVAS_PROCESSOR_NAME: &str = "CanvasProcessor";
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "RawCanvasProcessorConfig")]
 pub struct CanvasProcessorConfig {
-    // TODO: This should be an Address instead
-    pub canvas_contract_address: String,
+    /// All the deployments of the canvas contract to process txns for.
+    pub deployments: Vec<Deployment>,
+
+    /// What to do when we fail to decode a txn for one of the deployments.
+    pub decode_failure_policy: DecodeFailurePolicy,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodeFailurePolicy {
+    /// Fail the batch. The dispatcher will keep retrying it, so processing stalls
+    /// until the problem is fixed.
+    #[default]
+    Fail,
+    /// Record the txn in the `quarantined_transactions` table and carry on without
+    /// it. Once the problem is fixed the txn can be replayed with a backfill.
+    Quarantine,
+}
+
+/// A deployment of the canvas contract. Every canvas belongs to exactly one
+/// deployment, the one whose contract created it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Deployment {
+    /// A unique name for the deployment, e.g. `phase1`. Canvases are tagged with this
+    /// in the DB and the API.
+    pub label: String,
+
+    pub contract_address: Address,
+
+    /// Txns before this version are ignored for this deployment. Note that this
+    /// doesn't affect where the processor starts, that is still determined by the
+    /// `common_storage_config`. If you add a deployment with a starting version
+    /// before where the processor is up to, use a backfill to process the txns the
+    /// processor already passed.
+    #[serde(default)]
+    pub starting_version: u64,
+}
+
+#[derive(Deserialize)]
+struct RawCanvasProcessorConfig {
+    #[serde(flatten)]
+    deployments: RawDeployments,
+    #[serde(default)]
+    decode_failure_policy: DecodeFailurePolicy,
+}
+
+/// We used to only support a single deployment, we still accept configs in that
+/// format.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDeployments {
+    Deployments { deployments: Vec<Deployment> },
+    Legacy { canvas_contract_address: Address },
+}
+
+impl From<RawCanvasProcessorConfig> for CanvasProcessorConfig {
+    fn from(raw: RawCanvasProcessorConfig) -> Self {
+        let deployments = match raw.deployments {
+            RawDeployments::Deployments { deployments } => deployments,
+            RawDeployments::Legacy {
+                canvas_contract_address,
+            } => vec![Deployment {
+                label: DEFAULT_DEPLOYMENT_LABEL.to_string(),
+                contract_address: canvas_contract_address,
+                starting_version: 0,
+            }],
+        };
+        Self {
+            deployments,
+            decode_failure_policy: raw.decode_failure_policy,
+        }
+    }
 }
 
+/// The label of the deployment when using the legacy config format.
+const DEFAULT_DEPLOYMENT_LABEL: &str = "default";
+
 #[derive(Debug)]
 pub struct CanvasProcessor {
     config: CanvasProcessorConfig,
     canvas_storage: Arc<dyn CanvasStorageTrait>,
+    storage: Arc<DatabaseStorage>,
+    name: &'static str,
+
+    /// The last version this processor finished processing. This is locked for the
+    /// whole time a batch is being processed, so holding the lock pauses processing.
+    processed_version: Arc<Mutex<Option<u64>>>,
+
+    /// The last version received from the txn stream, see `stream_tracking`. This is
+    /// only used to report how far behind the stream we are.
+    latest_stream_version: Arc<AtomicU64>,
 }
 
 impl CanvasProcessor {
-    pub fn new(config: CanvasProcessorConfig, canvas_storage: Arc<dyn CanvasStorageTrait>) -> Self {
+    pub fn new(
+        config: CanvasProcessorConfig,
+        canvas_storage: Arc<dyn CanvasStorageTrait>,
+        storage: Arc<DatabaseStorage>,
+    ) -> Self {
         Self {
             config,
             canvas_storage,
+            storage,
+            name: CANVAS_PROCESSOR_NAME,
+            processed_version: Arc::new(Mutex::new(None)),
+            latest_stream_version: Arc::new(AtomicU64::new(0)),
         }
     }
 
-    pub fn get_canvas_struct_tag(&self) -> MoveStructTag {
+    /// Use a different name to record progress under, e.g. for backfilling.
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn processed_version(&self) -> Arc<Mutex<Option<u64>>> {
+        self.processed_version.clone()
+    }
+
+    pub fn latest_stream_version(&self) -> Arc<AtomicU64> {
+        self.latest_stream_version.clone()
+    }
+
+    pub fn get_canvas_struct_tag(deployment: &Deployment) -> MoveStructTag {
         MoveStructTag {
-            address: self.config.canvas_contract_address.clone(),
+            address: address_to_string(&deployment.contract_address),
             module: CANVAS_TOKEN_MODULE_NAME.to_string(),
             name: "Canvas".to_string(),
             generic_type_params: vec![],
@@ -53,7 +186,7 @@ impl CanvasProcessor {
 #[async_trait::async_trait]
 impl ProcessorTrait for CanvasProcessor {
     fn name(&self) -> &'static str {
-        "CanvasProcessor"
+        self.name
     }
 
     async fn process_transactions(
@@ -62,44 +195,217 @@ impl ProcessorTrait for CanvasProcessor {
         start_version: u64,
         end_version: u64,
     ) -> Result<ProcessingResult> {
+        let mut processed_version = self.processed_version.lock().await;
+        let _timer = BATCH_PROCESSING_SECONDS
+            .with_label_values(&[self.name()])
+            .start_timer();
+        let last_transaction_timestamp = transactions
+            .last()
+            .and_then(|transaction| transaction.timestamp.as_ref())
+            .map(|timestamp| timestamp.seconds);
+
         let mut all_create_canvas_intents = Vec::new();
         let mut all_write_pixel_intents = Vec::new();
+        let mut all_canvas_models = Vec::new();
+        let mut all_quarantined_transactions = Vec::new();
+        let mut all_contributions = Vec::new();
+        // The version each canvas cleared in this batch was last cleared at.
+        let mut cleared_canvases = HashMap::new();
+        // The width of each canvas we've needed to look up, `None` if it's unknown.
+        let mut canvas_widths = HashMap::new();
+        // The latest state of each Canvas resource written in this batch.
+        let mut latest_canvases = HashMap::new();
+        // When artists last drew as the contract sees it, by canvas and artist.
+        let mut all_contribution_timeouts = BTreeMap::new();
+        // The canvases whose contribution timeouts were cleared in this batch.
+        let mut cleared_contribution_timeouts = HashSet::new();
+        let mut all_funds_recipient_changes = Vec::new();
+        // Every write to the owner of a canvas or the collection in this batch.
+        let mut all_object_owner_writes = Vec::new();
+        let mut all_collection_addresses = HashSet::new();
+        // The objects whose owner we track, including those created in this batch.
+        let mut tracked_object_addresses = self
+            .storage
+            .read_tracked_object_addresses()
+            .await
+            .context("Failed to read tracked objects from DB")?;
         for transaction in transactions {
             // todo process canvas_token::create and create images for that
             // todo create a storage interface with like create that takes in a default color
             // a width and height, then methods for writing pixels to it, and also reading
             // the full thing. it should handle the read update write process inside it
-            let write_pixel_intents = self
-                .process_draw(&transaction)
-                .context("Failed at process_draw")?;
-            all_write_pixel_intents.extend(write_pixel_intents);
-            let create_canvas_intent = self
-                .process_create(&transaction)
-                .context("Failed at process_create")?;
-            if let Some(create_canvas_intent) = create_canvas_intent {
-                all_create_canvas_intents.push(create_canvas_intent);
+
+            // Only user txns can call the contract, and failed txns don't change
+            // anything, so there is nothing to process in either case.
+            if get_transaction_kind(&transaction) != TransactionKind::User
+                || !is_successful(&transaction)
+            {
+                continue;
+            }
+            for deployment in &self.config.deployments {
+                if transaction.version < deployment.starting_version {
+                    continue;
+                }
+                let mut decoded = match self.decode_transaction(&transaction, deployment) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        DECODE_FAILURES
+                            .with_label_values(&[&deployment.label])
+                            .inc();
+                        match self.config.decode_failure_policy {
+                            DecodeFailurePolicy::Fail => return Err(e),
+                            DecodeFailurePolicy::Quarantine => {
+                                warn!(
+                                    version = transaction.version,
+                                    deployment = deployment.label,
+                                    "Quarantining txn that failed to decode: {:#}",
+                                    e
+                                );
+                                all_quarantined_transactions.push(build_quarantined_transaction(
+                                    &transaction,
+                                    deployment,
+                                    &e,
+                                )?);
+                                continue;
+                            },
+                        }
+                    },
+                };
+                if let Some((create_canvas_intent, _)) = &decoded.created_canvas {
+                    canvas_widths.insert(
+                        create_canvas_intent.canvas_address,
+                        Some(create_canvas_intent.width),
+                    );
+                }
+                self.record_drawn_color(&mut decoded, &mut canvas_widths)
+                    .await
+                    .context("Failed to find the drawn pixel")?;
+                all_write_pixel_intents.extend(decoded.write_pixel_intents);
+                if let Some((create_canvas_intent, canvas)) = decoded.created_canvas {
+                    tracked_object_addresses
+                        .insert(address_to_string(&create_canvas_intent.canvas_address));
+                    all_canvas_models.push(canvases::Model {
+                        address: address_to_string(&create_canvas_intent.canvas_address),
+                        deployment: deployment.label.clone(),
+                        contract_address: address_to_string(&deployment.contract_address),
+                        width: create_canvas_intent.width as i64,
+                        height: create_canvas_intent.height as i64,
+                        created_version: transaction.version as i64,
+                        cost: canvas.config.cost.0 as i64,
+                        per_account_timeout_s: canvas.config.per_account_timeout_s.0 as i64,
+                        can_draw_for_s: canvas.config.can_draw_for_s.0 as i64,
+                        created_at_s: canvas.created_at_s.0 as i64,
+                        funds_recipient: canvas
+                            .config
+                            .funds_recipient
+                            .as_option()
+                            .map(address_to_string),
+                        owner_address: None,
+                        owner_is_super_admin: canvas.config.owner_is_super_admin,
+                    });
+                    all_create_canvas_intents.push(create_canvas_intent);
+                }
+                if let Some(clear_canvas_intent) = decoded.cleared_canvas {
+                    // Canvases are (re)created before any pixels are written, so drop
+                    // the pixels written earlier in the batch since they're cleared.
+                    all_write_pixel_intents.retain(|write_pixel_intent: &WritePixelIntent| {
+                        write_pixel_intent.canvas_address != clear_canvas_intent.canvas_address
+                    });
+                    cleared_canvases
+                        .insert(clear_canvas_intent.canvas_address, transaction.version);
+                    all_create_canvas_intents.push(clear_canvas_intent);
+                }
+                all_contributions.extend(decoded.contribution);
+                latest_canvases.extend(decoded.canvas_writes);
+                all_funds_recipient_changes.extend(decoded.funds_recipient_change);
+                if let Some(collection_address) = decoded.collection_address {
+                    tracked_object_addresses.insert(address_to_string(&collection_address));
+                    all_collection_addresses.insert(collection_address);
+                }
+                if let Some(canvas_address) = decoded.cleared_contribution_timeouts {
+                    // Like with clears, drop what was recorded earlier in the batch.
+                    let canvas_address = address_to_string(&canvas_address);
+                    all_contribution_timeouts.retain(
+                        |(timeout_canvas_address, _): &(String, String), _| {
+                            *timeout_canvas_address != canvas_address
+                        },
+                    );
+                    cleared_contribution_timeouts.insert(canvas_address);
+                }
+                for contribution_timeout in decoded.contribution_timeouts {
+                    all_contribution_timeouts.insert(
+                        (
+                            contribution_timeout.canvas_address.clone(),
+                            contribution_timeout.artist_address.clone(),
+                        ),
+                        contribution_timeout,
+                    );
+                }
             }
+            // Every deployment sees the same owner writes, so look for them once.
+            all_object_owner_writes
+                .extend(self.process_object_owner_writes(&transaction, &tracked_object_addresses));
         }
         info!(
             start_version = start_version,
             end_version = end_version,
             processor_name = self.name(),
             num_canvases_to_create = all_create_canvas_intents.len(),
-            num_pixels_to_write = all_write_pixel_intents.len()
+            num_pixels_to_write = all_write_pixel_intents.len(),
+            num_quarantined = all_quarantined_transactions.len()
         );
 
+        // Quarantine any txns we failed to decode.
+        let quarantined_deployments: Vec<String> = all_quarantined_transactions
+            .iter()
+            .map(|quarantined_transaction| quarantined_transaction.deployment.clone())
+            .collect();
+        self.storage
+            .write_quarantined_transactions(all_quarantined_transactions)
+            .await
+            .context("Failed to write quarantined transactions to DB")?;
+        for deployment in quarantined_deployments {
+            QUARANTINED_TRANSACTIONS
+                .with_label_values(&[&deployment])
+                .inc();
+        }
+
         // TODO: Parallelize this.
 
-        // Create canvases.
+        // Keep track of which canvases we touch in this batch.
+        let mut touched_canvases = HashSet::new();
+
+        // Create canvases, including recreating any canvases that were cleared.
         for create_canvas_intent in all_create_canvas_intents {
             info!("Creating canvas {}", create_canvas_intent.canvas_address);
+            touched_canvases.insert(create_canvas_intent.canvas_address);
             self.canvas_storage
                 .create_canvas(create_canvas_intent)
                 .await
                 .context("Failed to create canvas in storage")?;
         }
+        let created_deployments: Vec<String> = all_canvas_models
+            .iter()
+            .map(|canvas_model| canvas_model.deployment.clone())
+            .collect();
+        self.storage
+            .write_canvases(all_canvas_models)
+            .await
+            .context("Failed to write canvases to DB")?;
+        for deployment in created_deployments {
+            CANVASES_CREATED.with_label_values(&[&deployment]).inc();
+        }
+
+        // Record who owns the canvases and the collection. This needs the canvases
+        // created in this batch to be recorded first.
+        self.write_ownership_changes(all_object_owner_writes, &all_collection_addresses)
+            .await
+            .context("Failed to write ownership changes to DB")?;
 
-        // Write pixels.
+        // Write pixels. We write each canvas's pixels in one go so readers only have to
+        // wait for those writes, not the rest of the batch.
+        let mut canvas_to_write_pixel_intents: HashMap<Address, Vec<WritePixelIntent>> =
+            HashMap::new();
         for write_pixel_intent in all_write_pixel_intents {
             info!(
                 "Writing pixel to canvas {} index {} (from txns {} to {})",
@@ -108,10 +414,120 @@ impl ProcessorTrait for CanvasProcessor {
                 start_version,
                 end_version
             );
+            touched_canvases.insert(write_pixel_intent.canvas_address);
+            canvas_to_write_pixel_intents
+                .entry(write_pixel_intent.canvas_address)
+                .or_default()
+                .push(write_pixel_intent);
+        }
+        for (canvas_address, write_pixel_intents) in canvas_to_write_pixel_intents {
+            let num_pixels = write_pixel_intents.len() as u64;
             self.canvas_storage
-                .write_pixel(write_pixel_intent)
+                .write_pixels(&canvas_address, write_pixel_intents)
+                .await
+                .context("Failed to write pixels in storage")?;
+            PIXELS_WRITTEN.inc_by(num_pixels);
+        }
+
+        // Record who was meant to get paid for draws, and who got paid. The payments
+        // need the funds recipient changes to be recorded first.
+        self.storage
+            .write_funds_recipient_changes(all_funds_recipient_changes)
+            .await
+            .context("Failed to write funds recipient changes to DB")?;
+        self.write_payments(&all_contributions)
+            .await
+            .context("Failed to write payments to DB")?;
+
+        // Record who drew what.
+        self.write_contributions(all_contributions, &cleared_canvases)
+            .await
+            .context("Failed to write contributions to DB")?;
+
+        // Record when artists may next draw.
+        for canvas_address in &cleared_contribution_timeouts {
+            self.storage
+                .delete_contribution_timeouts(canvas_address)
                 .await
-                .context("Failed to write pixel in storage")?;
+                .context("Failed to delete contribution timeouts from DB")?;
+        }
+        self.storage
+            .write_contribution_timeouts(all_contribution_timeouts.into_values().collect())
+            .await
+            .context("Failed to write contribution timeouts to DB")?;
+
+        // Record who may draw on each canvas and the config deciding when they may.
+        for (canvas_address, canvas) in &latest_canvases {
+            self.storage
+                .update_canvas_config(
+                    &address_to_string(canvas_address),
+                    canvas.config.per_account_timeout_s.0 as i64,
+                    canvas.config.can_draw_for_s.0 as i64,
+                    canvas.created_at_s.0 as i64,
+                    canvas
+                        .config
+                        .funds_recipient
+                        .as_option()
+                        .map(address_to_string),
+                    canvas.config.owner_is_super_admin,
+                )
+                .await
+                .context("Failed to update canvas config in DB")?;
+            self.storage
+                .write_canvas_artist_lists(
+                    &address_to_string(canvas_address),
+                    canvas
+                        .allowlisted_artists
+                        .data
+                        .iter()
+                        .map(address_to_string)
+                        .collect(),
+                    canvas
+                        .blocklisted_artists
+                        .data
+                        .iter()
+                        .map(address_to_string)
+                        .collect(),
+                )
+                .await
+                .context("Failed to write canvas artist lists to DB")?;
+        }
+
+        // Now that everything in this batch has been applied, record that in the
+        // canvases we touched.
+        for canvas_address in touched_canvases {
+            self.canvas_storage
+                .set_applied_version(&canvas_address, end_version)
+                .await
+                .context("Failed to set applied version in storage")?;
+        }
+
+        // Record how far along we are, for the readiness check.
+        let latest_stream_version = self
+            .latest_stream_version
+            .load(Ordering::Relaxed)
+            .max(end_version);
+        self.storage
+            .write_processor_status(processor_status::Model {
+                processor_name: self.name().to_string(),
+                processed_version: end_version as i64,
+                last_transaction_timestamp,
+                latest_stream_version: latest_stream_version as i64,
+            })
+            .await
+            .context("Failed to write processor status to DB")?;
+
+        *processed_version = Some(end_version);
+        PROCESSED_VERSION
+            .with_label_values(&[self.name()])
+            .set(end_version as i64);
+        if let Some(last_transaction_timestamp) = last_transaction_timestamp {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .context("System time is before the epoch")?;
+            LEDGER_LAG_SECONDS
+                .with_label_values(&[self.name()])
+                .set(now.as_secs() as i64 - last_transaction_timestamp);
         }
 
         Ok((start_version, end_version))
@@ -119,148 +535,1238 @@ impl ProcessorTrait for CanvasProcessor {
 }
 
 impl CanvasProcessor {
-    fn process_draw(&self, transaction: &Transaction) -> Result<Vec<WritePixelIntent>> {
+    /// Get everything we need from a txn for the given deployment.
+    fn decode_transaction(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<DecodedTransaction> {
+        Ok(DecodedTransaction {
+            write_pixel_intents: self
+                .process_draw(transaction, deployment)
+                .context("Failed at process_draw")?,
+            created_canvas: self
+                .process_create(transaction, deployment)
+                .context("Failed at process_create")?,
+            cleared_canvas: self
+                .process_clear(transaction, deployment)
+                .context("Failed at process_clear")?,
+            contribution: self
+                .process_contribution(transaction, deployment)
+                .context("Failed at process_contribution")?,
+            canvas_writes: self
+                .decode_canvas_writes(transaction, deployment)
+                .context("Failed at decode_canvas_writes")?,
+            contribution_timeouts: self
+                .process_contribution_timeouts(transaction, deployment)
+                .context("Failed at process_contribution_timeouts")?,
+            cleared_contribution_timeouts: self
+                .process_clear_contribution_timeouts(transaction, deployment)
+                .context("Failed at process_clear_contribution_timeouts")?,
+            funds_recipient_change: self
+                .process_funds_recipient_change(transaction, deployment)
+                .context("Failed at process_funds_recipient_change")?,
+            collection_address: self
+                .process_collection(transaction, deployment)
+                .context("Failed at process_collection")?,
+        })
+    }
+
+    /// If this is a call to `draw`, get its arguments.
+    fn parse_draw_arguments(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<DrawArguments>> {
         // Skip this transaction if this wasn't a draw transaction.
         let draw_function_id = EntryFunctionId {
             module: Some(MoveModuleId {
-                address: self.config.canvas_contract_address.clone(),
+                address: address_to_string(&deployment.contract_address),
                 name: CANVAS_TOKEN_MODULE_NAME.to_string(),
             }),
             name: "draw".to_string(),
         };
         if !entry_function_id_matches(transaction, &draw_function_id) {
-            return Ok(vec![]);
+            return Ok(None);
         }
 
-        let txn_data = transaction.txn_data.as_ref().context("No txn_data")?;
-        let user_transaction = match txn_data {
-            TxnData::User(user_transaction) => user_transaction,
-            _ => return Ok(vec![]),
-        };
-        let request = user_transaction.request.as_ref().context("No request")?;
-        let payload = request.payload.as_ref().unwrap();
-        let entry_function_payload = match payload.payload.as_ref().context("No payload")? {
-            Payload::EntryFunctionPayload(payload) => payload,
-            _ => return Ok(vec![]),
-        };
+        let entry_function_payload =
+            get_entry_function_payload(transaction).context("No entry function payload")?;
 
         let clean_entry_function_payload =
             get_clean_entry_function_payload(entry_function_payload, 0);
+        let mut arguments = clean_entry_function_payload.arguments.into_iter();
 
-        let first_arg = clean_entry_function_payload.arguments[0].clone();
+        let obj: Object = serde_json::from_value(arguments.next().context("No canvas argument")?)
+            .context("Failed to parse first argument as Object")?;
+        let x: U64 = serde_json::from_value(arguments.next().context("No x argument")?)
+            .context("Failed to parse x argument")?;
+        let y: U64 = serde_json::from_value(arguments.next().context("No y argument")?)
+            .context("Failed to parse y argument")?;
 
-        let obj: Object = serde_json::from_value(first_arg).unwrap();
-        let canvas_address = obj.inner;
+        Ok(Some(DrawArguments {
+            canvas_address: obj.inner,
+            x: x.0,
+            y: y.0,
+        }))
+    }
 
-        let draw_value_type = format!(
+    fn process_draw(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Vec<WritePixelIntent>> {
+        let canvas_address = match self.parse_draw_arguments(transaction, deployment)? {
+            Some(draw_arguments) => draw_arguments.canvas_address,
+            None => return Ok(vec![]),
+        };
+
+        let draw_value_type = canonicalize_type_string(&format!(
             "vector<0x1::smart_table::Entry<u64, {}::canvas_token::Color>>",
-            self.config.canvas_contract_address
-        );
+            address_to_string(&deployment.contract_address)
+        ));
 
         let info = transaction.info.as_ref().context("No info")?;
 
+        // A SmartTable internally is a Table where the values are vectors of entries
+        // (buckets), so each WriteTableItem has the full new bucket being written. A
+        // draw can write to many buckets, e.g. when drawing many pixels or when the
+        // table splits a bucket, so we need to look at all of them.
+        let mut intents = vec![];
         for change in &info.changes {
             match change.change.as_ref().context("No change")? {
                 Change::WriteTableItem(resource) => {
                     let data = resource.data.as_ref().context("No WriteTableItem data")?;
-                    if data.value_type != draw_value_type {
+                    if canonicalize_type_string(&data.value_type) != draw_value_type {
                         continue;
                     }
-                    let values: Vec<Value> = serde_json::from_str(&data.value).unwrap();
-                    // There could be many values because a SmartTable internally is a
-                    // Table where the values are vectors. This means each WriteTableItem
-                    // will have the full new vector being written.
-                    let mut intents = vec![];
-                    for value in values {
-                        let value: Entry =
-                            serde_json::from_value(value).context("Failed to parse as Entry")?;
-                        let index = value.key.as_str().unwrap().parse::<u64>().unwrap();
-                        let color: Color = serde_json::from_value(value.value).unwrap();
-                        intents.push(WritePixelIntent {
-                            canvas_address,
-                            index,
-                            color,
-                        });
+                    let bucket: SmartTableBucket<U64, Color> = serde_json::from_str(&data.value)
+                        .context("Failed to parse WriteTableItem value as bucket")?;
+                    intents.extend(bucket.into_iter().map(|entry| WritePixelIntent {
+                        canvas_address,
+                        index: entry.key.0,
+                        color: entry.value,
+                    }));
+                },
+                _ => continue,
+            }
+        }
+        Ok(intents)
+    }
+
+    /// If this is a call to `draw`, get who drew which pixel.
+    fn process_contribution(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<Contribution>> {
+        let draw_arguments = match self.parse_draw_arguments(transaction, deployment)? {
+            Some(draw_arguments) => draw_arguments,
+            None => return Ok(None),
+        };
+        let sender = &get_user_transaction_request(transaction)
+            .context("No user transaction request")?
+            .sender;
+        let artist_address = Address::from_str(sender).context("Invalid sender address")?;
+        let info = transaction.info.as_ref().context("No info")?;
+        Ok(Some(Contribution {
+            canvas_address: draw_arguments.canvas_address,
+            artist_address,
+            x: draw_arguments.x,
+            y: draw_arguments.y,
+            version: transaction.version,
+            transaction_hash: format!("0x{}", hex::encode(&info.hash)),
+            timestamp: transaction
+                .timestamp
+                .as_ref()
+                .map(|timestamp| timestamp.seconds),
+            color: None,
+            payment: self
+                .process_payment(transaction, &artist_address)
+                .context("Failed at process_payment")?,
+        }))
+    }
+
+    /// Get the payment a call to `draw` made from the withdrawals and deposits it
+    /// made, if it made any. The contract transfers the cost of the canvas from the
+    /// artist to whoever gets the funds, either with `coin::transfer`, which emits
+    /// events from the CoinStores of both, or into fungible stores, whose events only
+    /// have the address of the store. In that case we find the owner of the store
+    /// from its ObjectCore, which the txn writes along with the store. Gas isn't
+    /// deposited anywhere, so the deposits are all to whoever got paid.
+    fn process_payment(
+        &self,
+        transaction: &Transaction,
+        artist_address: &Address,
+    ) -> Result<Option<DrawPayment>> {
+        let user_transaction = get_user_transaction(transaction).context("No user transaction")?;
+        let info = transaction.info.as_ref().context("No info")?;
+        let store_owners = get_object_owners(&info.changes);
+
+        let coin_withdraw_type = canonicalize_type_string("0x1::coin::WithdrawEvent");
+        let coin_deposit_type = canonicalize_type_string("0x1::coin::DepositEvent");
+        let store_withdraw_type = canonicalize_type_string("0x1::fungible_asset::Withdraw");
+        let store_deposit_type = canonicalize_type_string("0x1::fungible_asset::Deposit");
+
+        // Who each withdrawal was from and each deposit was into, with the amount.
+        let mut withdrawals = vec![];
+        let mut deposits = vec![];
+        for event in &user_transaction.events {
+            let event_type = canonicalize_type_string(&event.type_str);
+            let transfers = if event_type == coin_withdraw_type || event_type == store_withdraw_type
+            {
+                &mut withdrawals
+            } else if event_type == coin_deposit_type || event_type == store_deposit_type {
+                &mut deposits
+            } else {
+                continue;
+            };
+            if event_type == coin_withdraw_type || event_type == coin_deposit_type {
+                let account = &event
+                    .key
+                    .as_ref()
+                    .context("No coin event key")?
+                    .account_address;
+                let coin_event: CoinEvent =
+                    serde_json::from_str(&event.data).context("Failed to parse coin event")?;
+                transfers.push((
+                    Some(Address::from_str(account).context("Invalid coin event account")?),
+                    coin_event.amount.0,
+                ));
+            } else {
+                let store_event: StoreEvent =
+                    serde_json::from_str(&event.data).context("Failed to parse store event")?;
+                transfers.push((
+                    store_owners.get(&store_event.store).copied(),
+                    store_event.amount.0,
+                ));
+            }
+        }
+
+        let Some((payee, _)) = deposits.first() else {
+            return Ok(None);
+        };
+        let payee = *payee;
+        if deposits.iter().any(|(account, _)| *account != payee) {
+            anyhow::bail!("Draw paid more than one account");
+        }
+        // Whoever the withdrawals were from paid, or the artist if we can't tell.
+        let payer = match withdrawals.first() {
+            Some((Some(payer), _))
+                if withdrawals
+                    .iter()
+                    .all(|(account, _)| *account == Some(*payer)) =>
+            {
+                *payer
+            },
+            _ => *artist_address,
+        };
+        Ok(Some(DrawPayment {
+            payer,
+            payee,
+            amount: deposits.iter().map(|(_, amount)| amount).sum(),
+        }))
+    }
+
+    /// If this is a call to `draw` on a canvas with a per account timeout, get the
+    /// `last_contribution_s` entries it wrote. Like with pixels, each write is a
+    /// whole bucket, so this includes the entries of other artists in the bucket,
+    /// which are unchanged.
+    fn process_contribution_timeouts(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Vec<contribution_timeouts::Model>> {
+        let canvas_address = match self.parse_draw_arguments(transaction, deployment)? {
+            Some(draw_arguments) => address_to_string(&draw_arguments.canvas_address),
+            None => return Ok(vec![]),
+        };
+
+        // The only other SmartTable a draw writes to is `pixels`, which has u64 keys.
+        let timeout_value_type =
+            canonicalize_type_string("vector<0x1::smart_table::Entry<address, u64>>");
+
+        let info = transaction.info.as_ref().context("No info")?;
+
+        let mut contribution_timeouts = vec![];
+        for change in &info.changes {
+            match change.change.as_ref().context("No change")? {
+                Change::WriteTableItem(resource) => {
+                    let data = resource.data.as_ref().context("No WriteTableItem data")?;
+                    if canonicalize_type_string(&data.value_type) != timeout_value_type {
+                        continue;
                     }
-                    return Ok(intents);
+                    let bucket: SmartTableBucket<Address, U64> = serde_json::from_str(&data.value)
+                        .context("Failed to parse WriteTableItem value as bucket")?;
+                    contribution_timeouts.extend(bucket.into_iter().map(|entry| {
+                        contribution_timeouts::Model {
+                            canvas_address: canvas_address.clone(),
+                            artist_address: address_to_string(&entry.key),
+                            last_contribution_s: entry.value.0 as i64,
+                            version: transaction.version as i64,
+                        }
+                    }));
                 },
                 _ => continue,
             }
         }
-        Ok(vec![])
+        Ok(contribution_timeouts)
     }
 
-    fn process_create(&self, transaction: &Transaction) -> Result<Option<CreateCanvasIntent>> {
-        // Skip this transaction if this wasn't a draw transaction.
-        let draw_function_id = EntryFunctionId {
+    /// If this is a call to `clear_contribution_timeouts`, get the canvas it cleared.
+    /// This replaces `last_contribution_s` with an empty table, which we can't tell
+    /// from the changes alone.
+    fn process_clear_contribution_timeouts(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<Address>> {
+        let function_id = EntryFunctionId {
             module: Some(MoveModuleId {
-                address: self.config.canvas_contract_address.clone(),
+                address: address_to_string(&deployment.contract_address),
                 name: CANVAS_TOKEN_MODULE_NAME.to_string(),
             }),
-            name: "create".to_string(),
+            name: "clear_contribution_timeouts".to_string(),
         };
-        if !entry_function_id_matches(transaction, &draw_function_id) {
+        if !entry_function_id_matches(transaction, &function_id) {
+            return Ok(None);
+        }
+
+        let entry_function_payload =
+            get_entry_function_payload(transaction).context("No entry function payload")?;
+        let clean_entry_function_payload =
+            get_clean_entry_function_payload(entry_function_payload, 0);
+        let obj: Object = serde_json::from_value(
+            clean_entry_function_payload
+                .arguments
+                .into_iter()
+                .next()
+                .context("No canvas argument")?,
+        )
+        .context("Failed to parse first argument as Object")?;
+        Ok(Some(obj.inner))
+    }
+
+    /// If this txn created a canvas or called `set_funds_recipient`, get who now
+    /// receives the funds from draws on it.
+    fn process_funds_recipient_change(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<funds_recipient_changes::Model>> {
+        let mut canvas_write = None;
+        for function_name in ["create", "set_funds_recipient"] {
+            if let Some((create_canvas_intent, canvas)) =
+                self.process_canvas_resource_write(transaction, deployment, function_name)?
+            {
+                canvas_write = Some((create_canvas_intent.canvas_address, canvas));
+            }
+        }
+        let Some((canvas_address, canvas)) = canvas_write else {
+            return Ok(None);
+        };
+        Ok(Some(funds_recipient_changes::Model {
+            canvas_address: address_to_string(&canvas_address),
+            version: transaction.version as i64,
+            funds_recipient: canvas
+                .config
+                .funds_recipient
+                .as_option()
+                .map(address_to_string),
+            timestamp: transaction
+                .timestamp
+                .as_ref()
+                .map(|timestamp| timestamp.seconds),
+        }))
+    }
+
+    /// Get the owner of every tracked object whose ObjectCore the txn wrote, i.e.
+    /// the canvases and the collection. Canvases can be transferred with
+    /// `0x1::object::transfer`, so we can't rely on which function the txn called.
+    /// This looks at every user txn, so an ObjectCore we can't make sense of is
+    /// skipped rather than failing the txn.
+    fn process_object_owner_writes(
+        &self,
+        transaction: &Transaction,
+        tracked_object_addresses: &HashSet<String>,
+    ) -> Vec<ObjectOwnerWrite> {
+        let object_core_struct_tag = MoveStructTag {
+            address: "0x1".to_string(),
+            module: "object".to_string(),
+            name: "ObjectCore".to_string(),
+            generic_type_params: vec![],
+        };
+
+        let Some(info) = transaction.info.as_ref() else {
+            return vec![];
+        };
+
+        let mut object_owner_writes = vec![];
+        for change in &info.changes {
+            let Some(Change::WriteResource(resource)) = change.change.as_ref() else {
+                continue;
+            };
+            let matches = resource
+                .r#type
+                .as_ref()
+                .is_some_and(|r#type| struct_tags_match(r#type, &object_core_struct_tag));
+            if !matches {
+                continue;
+            }
+            let Ok(object_address) = Address::from_str(&resource.address) else {
+                continue;
+            };
+            let object_address = address_to_string(&object_address);
+            if !tracked_object_addresses.contains(&object_address) {
+                continue;
+            }
+            let object_core: ObjectCore = match serde_json::from_str(&resource.data) {
+                Ok(object_core) => object_core,
+                Err(e) => {
+                    warn!(
+                        version = transaction.version,
+                        "Skipping ObjectCore of {} that failed to parse: {:#}", object_address, e
+                    );
+                    continue;
+                },
+            };
+            object_owner_writes.push(ObjectOwnerWrite {
+                object_address,
+                owner_address: address_to_string(&object_core.owner),
+                version: transaction.version,
+                transaction_hash: format!("0x{}", hex::encode(&info.hash)),
+                timestamp: transaction
+                    .timestamp
+                    .as_ref()
+                    .map(|timestamp| timestamp.seconds),
+            });
+        }
+        object_owner_writes
+    }
+
+    /// If the txn created or transferred the collection, get its address. Otherwise
+    /// we have no way to tell the collection apart from any other object.
+    fn process_collection(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<Address>> {
+        let transfer_function_id = EntryFunctionId {
+            module: Some(MoveModuleId {
+                address: address_to_string(&deployment.contract_address),
+                name: CANVAS_COLLECTION_MODULE_NAME.to_string(),
+            }),
+            name: "transfer".to_string(),
+        };
+        if entry_function_id_matches(transaction, &transfer_function_id) {
+            let entry_function_payload =
+                get_entry_function_payload(transaction).context("No entry function payload")?;
+            let clean_entry_function_payload =
+                get_clean_entry_function_payload(entry_function_payload, 0);
+            let obj: Object = serde_json::from_value(
+                clean_entry_function_payload
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .context("No collection argument")?,
+            )
+            .context("Failed to parse first argument as Object")?;
+            return Ok(Some(obj.inner));
+        }
+
+        // The collection is created along with the CollectionRefs resource.
+        let collection_refs_struct_tag = MoveStructTag {
+            address: address_to_string(&deployment.contract_address),
+            module: CANVAS_COLLECTION_MODULE_NAME.to_string(),
+            name: "CollectionRefs".to_string(),
+            generic_type_params: vec![],
+        };
+        let info = transaction.info.as_ref().context("No info")?;
+        for change in &info.changes {
+            if let Change::WriteResource(resource) = change.change.as_ref().context("No change")? {
+                let matches = resource
+                    .r#type
+                    .as_ref()
+                    .is_some_and(|r#type| struct_tags_match(r#type, &collection_refs_struct_tag));
+                if matches {
+                    return Ok(Some(
+                        Address::from_str(&resource.address)
+                            .context("Invalid collection address")?,
+                    ));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Record the color the artist drew in the contribution, from the write of the
+    /// pixel they drew. A draw rewrites every pixel in the buckets it wrote, so we
+    /// need to find the right one. The draw arguments are coordinates, so we need
+    /// the width of the canvas to find the index.
+    async fn record_drawn_color(
+        &self,
+        decoded: &mut DecodedTransaction,
+        canvas_widths: &mut HashMap<Address, Option<u64>>,
+    ) -> Result<()> {
+        let Some(contribution) = &mut decoded.contribution else {
+            return Ok(());
+        };
+        let width = match canvas_widths.entry(contribution.canvas_address) {
+            hash_map::Entry::Occupied(entry) => *entry.get(),
+            hash_map::Entry::Vacant(entry) => *entry.insert(
+                self.storage
+                    .read_canvas(&address_to_string(&contribution.canvas_address))
+                    .await?
+                    .map(|canvas| canvas.width as u64),
+            ),
+        };
+        // E.g. the canvas was created before the deployment's starting version.
+        let Some(width) = width else {
+            return Ok(());
+        };
+        let index = contribution.y * width + contribution.x;
+        for write_pixel_intent in &decoded.write_pixel_intents {
+            if write_pixel_intent.canvas_address == contribution.canvas_address
+                && write_pixel_intent.index == index
+            {
+                contribution.color = Some(write_pixel_intent.color.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the Canvas resource along with the intent, since we record some of
+    /// its config in the DB.
+    fn process_create(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<(CreateCanvasIntent, Canvas)>> {
+        self.process_canvas_resource_write(transaction, deployment, "create")
+    }
+
+    /// Clearing a canvas replaces its pixels table with an empty one, which is the
+    /// same as creating the canvas again, minus recording it in the DB.
+    fn process_clear(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Option<CreateCanvasIntent>> {
+        Ok(self
+            .process_canvas_resource_write(transaction, deployment, "clear")?
+            .map(|(create_canvas_intent, _)| create_canvas_intent))
+    }
+
+    /// If the txn called `function_name`, build an intent for (re)creating the canvas
+    /// from the Canvas resource the txn wrote.
+    fn process_canvas_resource_write(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+        function_name: &str,
+    ) -> Result<Option<(CreateCanvasIntent, Canvas)>> {
+        // Skip this transaction if it didn't call the function.
+        let function_id = EntryFunctionId {
+            module: Some(MoveModuleId {
+                address: address_to_string(&deployment.contract_address),
+                name: CANVAS_TOKEN_MODULE_NAME.to_string(),
+            }),
+            name: function_name.to_string(),
+        };
+        if !entry_function_id_matches(transaction, &function_id) {
             return Ok(None);
         }
 
+        let Some((canvas_address, canvas)) = self
+            .decode_canvas_writes(transaction, deployment)?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        let create_canvas_intent = CreateCanvasIntent {
+            canvas_address,
+            width: canvas.config.width.0,
+            height: canvas.config.height.0,
+            default_color: canvas.config.default_color.clone(),
+        };
+        Ok(Some((create_canvas_intent, canvas)))
+    }
+
+    /// Get every Canvas resource the txn wrote, whatever function it called.
+    fn decode_canvas_writes(
+        &self,
+        transaction: &Transaction,
+        deployment: &Deployment,
+    ) -> Result<Vec<(Address, Canvas)>> {
         let info = transaction.info.as_ref().context("No info")?;
 
+        let mut canvases = vec![];
         for change in &info.changes {
             match change.change.as_ref().context("No change")? {
                 Change::WriteResource(resource) => {
-                    if resource.r#type.as_ref().unwrap() != &self.get_canvas_struct_tag() {
+                    let matches = resource.r#type.as_ref().is_some_and(|r#type| {
+                        struct_tags_match(r#type, &Self::get_canvas_struct_tag(deployment))
+                    });
+                    if !matches {
                         continue;
                     }
                     let canvas: Canvas =
                         serde_json::from_str(&resource.data).context("Failed to parse Canvas")?;
-                    return Ok(Some(CreateCanvasIntent {
-                        canvas_address: Address::from_str(&resource.address).unwrap(),
-                        width: canvas.config.width.0,
-                        height: canvas.config.height.0,
-                        default_color: canvas.config.default_color,
-                    }));
+                    let canvas_address =
+                        Address::from_str(&resource.address).context("Invalid Canvas address")?;
+                    canvases.push((canvas_address, canvas));
                 },
                 _ => continue,
             }
         }
-        Ok(None)
+        Ok(canvases)
+    }
+
+    /// Record the owner writes that changed the owner of a canvas or the collection,
+    /// in the order they happened, and keep the current owner of each canvas up to
+    /// date. `collection_addresses` has the collection if it was created or
+    /// transferred in the batch.
+    async fn write_ownership_changes(
+        &self,
+        object_owner_writes: Vec<ObjectOwnerWrite>,
+        collection_addresses: &HashSet<Address>,
+    ) -> Result<()> {
+        if object_owner_writes.is_empty() {
+            return Ok(());
+        }
+        let object_addresses: Vec<String> = object_owner_writes
+            .iter()
+            .map(|object_owner_write| object_owner_write.object_address.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let mut tracked_objects = self.storage.read_tracked_objects(&object_addresses).await?;
+        for collection_address in collection_addresses {
+            tracked_objects
+                .entry(address_to_string(collection_address))
+                .or_insert(TrackedObject {
+                    kind: ObjectKind::Collection,
+                    owner_address: None,
+                });
+        }
+
+        let mut ownership_change_models = Vec::new();
+        let mut canvas_owners = BTreeMap::new();
+        for object_owner_write in object_owner_writes {
+            let Some(tracked_object) = tracked_objects.get_mut(&object_owner_write.object_address)
+            else {
+                continue;
+            };
+            // E.g. the ObjectCore was written because the object created a GUID.
+            if tracked_object.owner_address.as_ref() == Some(&object_owner_write.owner_address) {
+                continue;
+            }
+            tracked_object.owner_address = Some(object_owner_write.owner_address.clone());
+            if tracked_object.kind == ObjectKind::Canvas {
+                canvas_owners.insert(
+                    object_owner_write.object_address.clone(),
+                    object_owner_write.owner_address.clone(),
+                );
+            }
+            ownership_change_models.push(ownership_changes::Model {
+                object_address: object_owner_write.object_address,
+                version: object_owner_write.version as i64,
+                kind: tracked_object.kind.as_str().to_string(),
+                owner_address: object_owner_write.owner_address,
+                transaction_hash: object_owner_write.transaction_hash,
+                timestamp: object_owner_write.timestamp,
+            });
+        }
+
+        self.storage
+            .write_ownership_changes(ownership_change_models)
+            .await?;
+        for (canvas_address, owner_address) in canvas_owners {
+            self.storage
+                .update_canvas_owner(&canvas_address, &owner_address)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Record the payment each draw on a canvas with a cost made. We use who paid
+    /// whom how much from the txn where we can, see `process_payment`. Otherwise we
+    /// work it out from the canvas: the contract transfers its cost to its funds
+    /// recipient, or to its owner if there isn't one, so we use who those were when
+    /// the draw happened. That needs the changes to both up to the draw to be
+    /// recorded first.
+    async fn write_payments(&self, contributions: &[Contribution]) -> Result<()> {
+        let mut canvases = HashMap::new();
+        let mut payment_models = Vec::new();
+        for contribution in contributions {
+            let canvas_address = address_to_string(&contribution.canvas_address);
+            let payment = contribution.payment.as_ref();
+            let (recipient_address, amount) = match payment {
+                Some(DrawPayment {
+                    payee: Some(payee),
+                    amount,
+                    ..
+                }) => (Some(address_to_string(payee)), *amount as i64),
+                _ => {
+                    let canvas = match canvases.entry(contribution.canvas_address) {
+                        hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                        hash_map::Entry::Vacant(entry) => {
+                            entry.insert(self.read_canvas_recipients(&canvas_address).await?)
+                        },
+                    };
+                    match canvas {
+                        Some(canvas) => {
+                            let amount =
+                                payment.map_or(canvas.canvas.cost, |payment| payment.amount as i64);
+                            // Drawing on the canvas is free.
+                            if amount == 0 {
+                                continue;
+                            }
+                            (canvas.recipient_at(contribution.version as i64), amount)
+                        },
+                        // The canvas was created before the deployment's starting
+                        // version, so we only know about the payment if the txn said.
+                        None if payment.is_some() => (None, 0),
+                        None => continue,
+                    }
+                },
+            };
+            let Some(recipient_address) = recipient_address else {
+                UNRECORDED_PAYMENTS.inc();
+                warn!(
+                    version = contribution.version,
+                    "Failed to find who was paid for draw on canvas {}", canvas_address
+                );
+                continue;
+            };
+            payment_models.push(payments::Model {
+                canvas_address,
+                version: contribution.version as i64,
+                artist_address: address_to_string(
+                    &payment.map_or(contribution.artist_address, |payment| payment.payer),
+                ),
+                recipient_address,
+                amount,
+                transaction_hash: contribution.transaction_hash.clone(),
+                timestamp: contribution.timestamp,
+            });
+        }
+        self.storage.write_payments(payment_models).await
+    }
+
+    /// Read a canvas along with the changes to who gets the funds from draws on it,
+    /// if it is recorded.
+    async fn read_canvas_recipients(
+        &self,
+        canvas_address: &str,
+    ) -> Result<Option<CanvasRecipients>> {
+        let Some(canvas) = self.storage.read_canvas(canvas_address).await? else {
+            return Ok(None);
+        };
+        Ok(Some(CanvasRecipients {
+            canvas,
+            funds_recipient_changes: self
+                .storage
+                .read_funds_recipient_changes(canvas_address)
+                .await?,
+            ownership_changes: self.storage.read_ownership_changes(canvas_address).await?,
+        }))
+    }
+
+    /// Add the contributions to the counters in the DB, record who drew each pixel
+    /// that is still visible, and add the contributions to the history of each
+    /// pixel. `cleared_canvases` has the version each canvas cleared in the batch
+    /// was last cleared at, since that removes everyone's pixels.
+    async fn write_contributions(
+        &self,
+        contributions: Vec<Contribution>,
+        cleared_canvases: &HashMap<Address, u64>,
+    ) -> Result<()> {
+        for canvas_address in cleared_canvases.keys() {
+            self.storage
+                .delete_pixel_writers(&address_to_string(canvas_address))
+                .await?;
+        }
+
+        let mut canvases = HashMap::new();
+        let mut contribution_models = BTreeMap::new();
+        let mut pixel_writer_models = BTreeMap::new();
+        let mut pixel_history_models = Vec::new();
+        for contribution in contributions {
+            let canvas_address = address_to_string(&contribution.canvas_address);
+            let canvas = match canvases.entry(contribution.canvas_address) {
+                hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.storage.read_canvas(&canvas_address).await?)
+                },
+            };
+            let canvas: &canvases::Model = match canvas {
+                Some(canvas) => canvas,
+                // E.g. the canvas was created before the deployment's starting version.
+                None => {
+                    warn!(
+                        version = contribution.version,
+                        "Skipping contribution to unknown canvas {}", canvas_address
+                    );
+                    continue;
+                },
+            };
+
+            let artist_address = address_to_string(&contribution.artist_address);
+            let contribution_model = contribution_models
+                .entry((canvas_address.clone(), artist_address.clone()))
+                .or_insert_with(|| contributions::Model {
+                    canvas_address: canvas_address.clone(),
+                    artist_address: artist_address.clone(),
+                    pixels_drawn: 0,
+                    first_contribution_version: contribution.version as i64,
+                    first_contribution_timestamp: contribution.timestamp,
+                    last_contribution_version: contribution.version as i64,
+                    last_contribution_timestamp: contribution.timestamp,
+                    octas_spent: 0,
+                });
+            contribution_model.pixels_drawn += 1;
+            contribution_model.last_contribution_version = contribution.version as i64;
+            contribution_model.last_contribution_timestamp = contribution.timestamp;
+            contribution_model.octas_spent += canvas.cost;
+
+            let pixel_index = (contribution.y * canvas.width as u64 + contribution.x) as i64;
+            // The history of a pixel is kept even when the canvas is cleared.
+            if let Some(color) = &contribution.color {
+                pixel_history_models.push(pixel_history::Model {
+                    canvas_address: canvas_address.clone(),
+                    pixel_index,
+                    version: contribution.version as i64,
+                    artist_address: artist_address.clone(),
+                    r: color.r as i16,
+                    g: color.g as i16,
+                    b: color.b as i16,
+                    transaction_hash: contribution.transaction_hash.clone(),
+                    timestamp: contribution.timestamp,
+                });
+            }
+
+            let cleared_since = cleared_canvases
+                .get(&contribution.canvas_address)
+                .is_some_and(|cleared_version| *cleared_version > contribution.version);
+            if cleared_since {
+                continue;
+            }
+            pixel_writer_models.insert(
+                (canvas_address.clone(), pixel_index),
+                pixel_writers::Model {
+                    canvas_address,
+                    pixel_index,
+                    artist_address,
+                    version: contribution.version as i64,
+                },
+            );
+        }
+
+        self.storage
+            .write_contributions(contribution_models.into_values().collect())
+            .await?;
+        self.storage
+            .write_pixel_writers(pixel_writer_models.into_values().collect())
+            .await?;
+        self.storage.write_pixel_history(pixel_history_models).await
     }
 }
 
+/// Everything we need from a txn for one deployment.
+struct DecodedTransaction {
+    write_pixel_intents: Vec<WritePixelIntent>,
+    created_canvas: Option<(CreateCanvasIntent, Canvas)>,
+    cleared_canvas: Option<CreateCanvasIntent>,
+    contribution: Option<Contribution>,
+    /// Every Canvas resource the txn wrote.
+    canvas_writes: Vec<(Address, Canvas)>,
+    contribution_timeouts: Vec<contribution_timeouts::Model>,
+    cleared_contribution_timeouts: Option<Address>,
+    funds_recipient_change: Option<funds_recipient_changes::Model>,
+    collection_address: Option<Address>,
+}
+
+/// A write to the ObjectCore of an object, which may or may not have changed its
+/// owner.
+struct ObjectOwnerWrite {
+    object_address: String,
+    owner_address: String,
+    version: u64,
+    transaction_hash: String,
+    /// The timestamp of the txn in seconds.
+    timestamp: Option<i64>,
+}
+
+/// Who a draw paid and how much, from the withdrawals and deposits of the txn.
+struct DrawPayment {
+    payer: Address,
+    /// `None` if it was paid into a fungible store whose owner the txn doesn't say.
+    payee: Option<Address>,
+    amount: u64,
+}
+
+/// A canvas along with the changes to who gets the funds from draws on it, in
+/// version order.
+struct CanvasRecipients {
+    canvas: canvases::Model,
+    funds_recipient_changes: Vec<funds_recipient_changes::Model>,
+    ownership_changes: Vec<ownership_changes::Model>,
+}
+
+impl CanvasRecipients {
+    /// Who got the funds from draws on the canvas as of `version`.
+    fn recipient_at(&self, version: i64) -> Option<String> {
+        let funds_recipient = match self
+            .funds_recipient_changes
+            .iter()
+            .rev()
+            .find(|change| change.version <= version)
+        {
+            Some(change) => change.funds_recipient.clone(),
+            None => self.canvas.funds_recipient.clone(),
+        };
+        let owner = match self
+            .ownership_changes
+            .iter()
+            .rev()
+            .find(|change| change.version <= version)
+        {
+            Some(change) => Some(change.owner_address.clone()),
+            None => self.canvas.owner_address.clone(),
+        };
+        funds_recipient.or(owner)
+    }
+}
+
+/// A `0x1::coin::WithdrawEvent` or `0x1::coin::DepositEvent`. Which account it was
+/// made from or into is in the key of the event rather than in the event itself.
+#[derive(Deserialize)]
+struct CoinEvent {
+    amount: U64,
+}
+
+/// A `0x1::fungible_asset::Withdraw` or `0x1::fungible_asset::Deposit`.
+#[derive(Deserialize)]
+struct StoreEvent {
+    store: Address,
+    amount: U64,
+}
+
+/// The arguments of a call to `draw` we care about.
+struct DrawArguments {
+    canvas_address: Address,
+    x: u64,
+    y: u64,
+}
+
+/// A pixel drawn by an artist.
+struct Contribution {
+    canvas_address: Address,
+    artist_address: Address,
+    x: u64,
+    y: u64,
+    version: u64,
+    transaction_hash: String,
+    /// The timestamp of the txn in seconds.
+    timestamp: Option<i64>,
+    /// The color drawn, found from the pixels the draw wrote, see `record_drawn_color`.
+    color: Option<Color>,
+    /// What the draw paid, if the txn says, see `process_payment`.
+    payment: Option<DrawPayment>,
+}
+
+/// Record the changes of a txn we failed to decode, so we can fix the problem and
+/// replay it later.
+fn build_quarantined_transaction(
+    transaction: &Transaction,
+    deployment: &Deployment,
+    error: &anyhow::Error,
+) -> Result<quarantined_transactions::Model> {
+    let changes = transaction.info.as_ref().map(|info| &info.changes);
+    Ok(quarantined_transactions::Model {
+        version: transaction.version as i64,
+        deployment: deployment.label.clone(),
+        changes: serde_json::to_string(&changes).context("Failed to serialize changes")?,
+        error: format!("{:#}", error),
+    })
+}
+
+/// Get the owner of every object whose ObjectCore was written by the changes of a
+/// txn. ObjectCores that fail to parse are skipped.
+fn get_object_owners(changes: &[WriteSetChange]) -> HashMap<Address, Address> {
+    let object_core_struct_tag = MoveStructTag {
+        address: "0x1".to_string(),
+        module: "object".to_string(),
+        name: "ObjectCore".to_string(),
+        generic_type_params: vec![],
+    };
+    changes
+        .iter()
+        .filter_map(|change| {
+            let Some(Change::WriteResource(resource)) = change.change.as_ref() else {
+                return None;
+            };
+            if !struct_tags_match(resource.r#type.as_ref()?, &object_core_struct_tag) {
+                return None;
+            }
+            let object_core: ObjectCore = serde_json::from_str(&resource.data).ok()?;
+            Some((
+                Address::from_str(&resource.address).ok()?,
+                object_core.owner,
+            ))
+        })
+        .collect()
+}
+
 fn entry_function_id_matches(
     transaction: &Transaction,
     entry_function_id: &EntryFunctionId,
 ) -> bool {
-    let txn_data = transaction
-        .txn_data
-        .as_ref()
-        .context("No txn_data")
-        .unwrap();
-    let user_transaction = match txn_data {
-        TxnData::User(user_transaction) => user_transaction,
-        _ => return false,
-    };
-    let request = user_transaction
-        .request
-        .as_ref()
-        .context("No request")
-        .unwrap();
-    let payload = request.payload.as_ref().unwrap();
-    let entry_function_payload = match payload.payload.as_ref().context("No payload").unwrap() {
-        Payload::EntryFunctionPayload(payload) => payload,
-        _ => return false,
-    };
+    get_entry_function_id(transaction)
+        .is_some_and(|function_id| entry_function_ids_match(function_id, entry_function_id))
+}
+
+/// The form of an address we use in the DB and the API, `0x` followed by all 64 hex
+/// characters.
+pub fn address_to_string(address: &Address) -> String {
+    format!("0x{}", address.to_canonical_string())
+}
+
+/// Addresses in txns aren't necessarily formatted the same way as we format them,
+/// e.g. leading zeros might be stripped, so we compare them by parsing them.
+fn addresses_match(a: &str, b: &str) -> bool {
+    match (Address::from_str(a), Address::from_str(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
 
-    let function_id = entry_function_payload
-        .function
-        .as_ref()
-        .context("No function")
-        .unwrap();
+fn entry_function_ids_match(a: &EntryFunctionId, b: &EntryFunctionId) -> bool {
+    a.name == b.name
+        && match (&a.module, &b.module) {
+            (Some(a), Some(b)) => a.name == b.name && addresses_match(&a.address, &b.address),
+            (None, None) => true,
+            _ => false,
+        }
+}
 
-    function_id == entry_function_id
+fn struct_tags_match(a: &MoveStructTag, b: &MoveStructTag) -> bool {
+    a.module == b.module
+        && a.name == b.name
+        && a.generic_type_params == b.generic_type_params
+        && addresses_match(&a.address, &b.address)
+}
+
+/// Rewrite every address in a type string, e.g. `vector<0x1::string::String>`, in
+/// the form from `address_to_string`, so type strings can be compared as strings.
+fn canonicalize_type_string(type_string: &str) -> String {
+    static ADDRESS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"0x[0-9a-fA-F]+").unwrap());
+    ADDRESS_REGEX
+        .replace_all(type_string, |captures: &Captures| {
+            match Address::from_str(&captures[0]) {
+                Ok(address) => address_to_string(&address),
+                Err(_) => captures[0].to_string(),
+            }
+        })
+        .to_string()
 }
 
 // Functions we need:
 // - Make it easier to pull out the entry function payload, one function.
 // - Something like get_clean_* for each of the Change:: variants, like WriteTableData.
 // - This entry_function_id_matches function above.
+
+#[cfg(test)]
+mod tests {
+    //! Golden tests for the processor. Each fixture in `fixtures/processor` is a JSON
+    //! array of txns in the same format the txn stream recordings use. The txns are
+    //! processed as a single batch against in-memory storage, and the resulting
+    //! canvases and DB tables are compared against `<fixture>.golden.json`. To update
+    //! the golden files after an intended change, run the tests with `UPDATE_GOLDEN=1`
+    //! and review the diff.
+
+    use super::*;
+    use crate::{canvas_storage::MemoryCanvasStorage, db_storage::DatabaseStorageConfig};
+    use sea_orm::EntityName;
+    use serde_json::Value;
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    const UPDATE_GOLDEN_ENV_VAR: &str = "UPDATE_GOLDEN";
+
+    /// The contract the txns in the fixtures are for.
+    const CONTRACT_ADDRESS: &str = "0xcafe";
+
+    #[derive(Serialize)]
+    struct GoldenOutput {
+        canvases: Vec<GoldenCanvas>,
+        tables: BTreeMap<String, Vec<Value>>,
+    }
+
+    #[derive(Serialize)]
+    struct GoldenCanvas {
+        address: String,
+        width: u64,
+        height: u64,
+        applied_version: u64,
+        /// One string per row, with each pixel as `rrggbb`.
+        rows: Vec<String>,
+    }
+
+    fn get_fixtures_directory() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/processor")
+    }
+
+    async fn build_processor() -> (
+        CanvasProcessor,
+        Arc<MemoryCanvasStorage>,
+        Arc<DatabaseStorage>,
+    ) {
+        let config = CanvasProcessorConfig {
+            deployments: vec![Deployment {
+                label: "test".to_string(),
+                contract_address: Address::from_str(CONTRACT_ADDRESS).unwrap(),
+                starting_version: 0,
+            }],
+            decode_failure_policy: DecodeFailurePolicy::Quarantine,
+        };
+        let canvas_storage = Arc::new(MemoryCanvasStorage::new());
+        let storage = Arc::new(
+            DatabaseStorage::new(DatabaseStorageConfig {
+                connection_string: "sqlite::memory:".to_string(),
+            })
+            .await
+            .unwrap(),
+        );
+        let processor = CanvasProcessor::new(config, canvas_storage.clone(), storage.clone());
+        (processor, canvas_storage, storage)
+    }
+
+    async fn build_golden_output(
+        canvas_storage: &MemoryCanvasStorage,
+        storage: &DatabaseStorage,
+    ) -> GoldenOutput {
+        let mut canvas_addresses = canvas_storage.get_canvas_addresses();
+        canvas_addresses.sort_by_key(address_to_string);
+        let canvases = canvas_addresses
+            .iter()
+            .map(|canvas_address| {
+                let canvas = canvas_storage.get_canvas(canvas_address).unwrap();
+                let rows = canvas
+                    .pixels
+                    .chunks(canvas.header.width as usize)
+                    .map(|row| {
+                        row.iter()
+                            .map(|color| format!("{:02x}{:02x}{:02x}", color.r, color.g, color.b))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect();
+                GoldenCanvas {
+                    address: address_to_string(canvas_address),
+                    width: canvas.header.width,
+                    height: canvas.header.height,
+                    applied_version: canvas.header.applied_version,
+                    rows,
+                }
+            })
+            .collect();
+
+        let mut tables = BTreeMap::new();
+        storage.export_tables(&mut tables).await.unwrap();
+        // The changes of quarantined txns are just the txn serialized again, which
+        // depends on the proto types rather than on the processor, so leave them out.
+        for row in tables
+            .get_mut(quarantined_transactions::Entity.table_name())
+            .into_iter()
+            .flatten()
+        {
+            row.as_object_mut().unwrap().remove("changes");
+        }
+        GoldenOutput { canvases, tables }
+    }
+
+    async fn check_fixture(name: &str) {
+        let directory = get_fixtures_directory();
+        let fixture_path = directory.join(format!("{}.json", name));
+        let golden_path = directory.join(format!("{}.golden.json", name));
+
+        let transactions: Vec<Transaction> =
+            serde_json::from_str(&std::fs::read_to_string(&fixture_path).unwrap()).unwrap();
+        let start_version = transactions.first().unwrap().version;
+        let end_version = transactions.last().unwrap().version;
+
+        let (processor, canvas_storage, storage) = build_processor().await;
+        processor
+            .process_transactions(transactions, start_version, end_version)
+            .await
+            .unwrap();
+
+        let output = build_golden_output(&canvas_storage, &storage).await;
+        let output = format!("{}\n", serde_json::to_string_pretty(&output).unwrap());
+        if std::env::var(UPDATE_GOLDEN_ENV_VAR).is_ok() {
+            std::fs::write(&golden_path, output).unwrap();
+            return;
+        }
+        let golden = std::fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+            panic!(
+                "No golden file for {}, run with {}=1 to create it",
+                name, UPDATE_GOLDEN_ENV_VAR
+            )
+        });
+        assert_eq!(
+            output,
+            golden,
+            "Output for {} doesn't match {}, if this is intended run with {}=1 to update it",
+            name,
+            golden_path.display(),
+            UPDATE_GOLDEN_ENV_VAR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create() {
+        check_fixture("create").await;
+    }
+
+    #[tokio::test]
+    async fn test_draw() {
+        check_fixture("draw").await;
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        check_fixture("clear").await;
+    }
+
+    #[tokio::test]
+    async fn test_failed() {
+        check_fixture("failed").await;
+    }
+
+    #[tokio::test]
+    async fn test_multi_bucket() {
+        check_fixture("multi_bucket").await;
+    }
+
+    #[tokio::test]
+    async fn test_contributions() {
+        check_fixture("contributions").await;
+    }
+
+    #[tokio::test]
+    async fn test_contribution_timeouts() {
+        check_fixture("contribution_timeouts").await;
+    }
+
+    #[tokio::test]
+    async fn test_payments() {
+        check_fixture("payments").await;
+    }
+
+    #[tokio::test]
+    async fn test_ownership() {
+        check_fixture("ownership").await;
+    }
+}
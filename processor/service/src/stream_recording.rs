@@ -0,0 +1,245 @@
+//! Recording and replaying the txn stream, so we can reproduce problems and build
+//! tests without connecting to the txn stream service.
+//!
+//! A recording is a directory with one gzipped JSON file per batch of txns, named
+//! after the first and last version in the batch so that sorting the filenames sorts
+//! the batches. The chain ID the stream reported is kept alongside them in the same
+//! kind of marker file we keep in the canvas directory.
+
+use crate::chain_id::{read_chain_id_marker, write_chain_id_marker};
+use anyhow::{Context, Result};
+use aptos_processor_framework::{
+    indexer_protos::transaction::v1::Transaction, ChannelHandle, StreamSubscriberTrait,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+/// How many batches can be waiting in the channel before we stop reading more.
+const CHANNEL_SIZE: usize = 10;
+
+const RECORDING_EXTENSION: &str = ".json.gz";
+
+/// Wraps another stream subscriber, writing every batch it receives to the
+/// recording directory before passing it on.
+pub struct RecordingStreamSubscriber<S> {
+    pub inner: S,
+    pub directory: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl<S: StreamSubscriberTrait> StreamSubscriberTrait for RecordingStreamSubscriber<S> {
+    async fn start(&self) -> Result<ChannelHandle> {
+        fs::create_dir_all(&self.directory)
+            .with_context(|| format!("Failed to create {}", self.directory.display()))?;
+        let mut inner_receiver = self.inner.start().await?.receiver;
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        let directory = self.directory.clone();
+        let join_handle = tokio::spawn(async move {
+            while let Some(transactions) = inner_receiver.recv().await {
+                if let Err(e) = write_batch(&directory, &transactions) {
+                    error!("Failed to record txns: {:#}", e);
+                    return;
+                }
+                if sender.send(transactions).await.is_err() {
+                    return;
+                }
+            }
+        });
+        info!(directory = %self.directory.display(), "Recording txn stream");
+        Ok(ChannelHandle {
+            join_handle,
+            receiver,
+        })
+    }
+}
+
+/// Replays a recording made by `RecordingStreamSubscriber`, starting from
+/// `starting_version`. Once every batch has been sent the channel is closed.
+pub struct ReplayStreamSubscriber {
+    pub directory: PathBuf,
+    pub starting_version: u64,
+}
+
+#[async_trait::async_trait]
+impl StreamSubscriberTrait for ReplayStreamSubscriber {
+    async fn start(&self) -> Result<ChannelHandle> {
+        let paths = list_batches(&self.directory)?;
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        let starting_version = self.starting_version;
+        let join_handle = tokio::spawn(async move {
+            for path in paths {
+                let mut transactions = match read_batch(&path) {
+                    Ok(transactions) => transactions,
+                    Err(e) => {
+                        error!("Failed to replay txns: {:#}", e);
+                        return;
+                    },
+                };
+                transactions.retain(|transaction| transaction.version >= starting_version);
+                if transactions.is_empty() {
+                    continue;
+                }
+                if sender.send(transactions).await.is_err() {
+                    return;
+                }
+            }
+            info!("Finished replaying txn stream");
+        });
+        info!(
+            directory = %self.directory.display(),
+            starting_version = starting_version,
+            "Replaying txn stream"
+        );
+        Ok(ChannelHandle {
+            join_handle,
+            receiver,
+        })
+    }
+}
+
+/// Keep the chain ID of the stream with the recording, so a replay can be checked
+/// against the DB and canvas directory just like the live stream.
+pub fn write_recording_chain_id(directory: &Path, chain_id: u8) -> Result<()> {
+    fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+    write_chain_id_marker(directory, chain_id)
+}
+
+pub fn read_recording_chain_id(directory: &Path) -> Result<u8> {
+    read_chain_id_marker(directory)?
+        .with_context(|| format!("No chain ID in recording {}", directory.display()))
+}
+
+fn write_batch(directory: &Path, transactions: &[Transaction]) -> Result<()> {
+    let (first, last) = match (transactions.first(), transactions.last()) {
+        (Some(first), Some(last)) => (first.version, last.version),
+        _ => return Ok(()),
+    };
+    let path = directory.join(format!("{:020}-{:020}{}", first, last, RECORDING_EXTENSION));
+    let file =
+        File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    serde_json::to_writer(&mut encoder, transactions)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+fn read_batch(path: &Path) -> Result<Vec<Transaction>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Get the paths of all the batches in the recording, in version order.
+fn list_batches(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)
+        .with_context(|| format!("Failed to read {}", directory.display()))?
+    {
+        let path = entry?.path();
+        let is_batch = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(RECORDING_EXTENSION));
+        if is_batch {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends the given batches and then closes the channel.
+    struct FakeStreamSubscriber {
+        batches: Vec<Vec<Transaction>>,
+    }
+
+    #[async_trait::async_trait]
+    impl StreamSubscriberTrait for FakeStreamSubscriber {
+        async fn start(&self) -> Result<ChannelHandle> {
+            let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+            let batches = self.batches.clone();
+            let join_handle = tokio::spawn(async move {
+                for batch in batches {
+                    sender.send(batch).await.unwrap();
+                }
+            });
+            Ok(ChannelHandle {
+                join_handle,
+                receiver,
+            })
+        }
+    }
+
+    fn build_batch(versions: std::ops::RangeInclusive<u64>) -> Vec<Transaction> {
+        versions
+            .map(|version| Transaction {
+                version,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    async fn receive_all(subscriber: &dyn StreamSubscriberTrait) -> Vec<Vec<Transaction>> {
+        let mut receiver = subscriber.start().await.unwrap().receiver;
+        let mut batches = Vec::new();
+        while let Some(batch) = receiver.recv().await {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let directory =
+            std::env::temp_dir().join(format!("stream-recording-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        // Everything received while recording is passed on unchanged.
+        let batches = vec![
+            build_batch(0..=9),
+            build_batch(10..=11),
+            build_batch(12..=20),
+        ];
+        let recording = RecordingStreamSubscriber {
+            inner: FakeStreamSubscriber {
+                batches: batches.clone(),
+            },
+            directory: directory.clone(),
+        };
+        assert_eq!(receive_all(&recording).await, batches);
+        write_recording_chain_id(&directory, 4).unwrap();
+
+        // Replaying from the start gives back exactly what was recorded.
+        let replay = ReplayStreamSubscriber {
+            directory: directory.clone(),
+            starting_version: 0,
+        };
+        assert_eq!(receive_all(&replay).await, batches);
+
+        // Replaying from part way through skips everything before that version.
+        let replay = ReplayStreamSubscriber {
+            directory: directory.clone(),
+            starting_version: 11,
+        };
+        assert_eq!(
+            receive_all(&replay).await,
+            vec![build_batch(11..=11), build_batch(12..=20)]
+        );
+
+        assert_eq!(read_recording_chain_id(&directory).unwrap(), 4);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
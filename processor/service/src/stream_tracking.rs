@@ -0,0 +1,43 @@
+//! Keeping track of how far along the txn stream is, so we can tell how far behind
+//! it the processor is. See `api::health`.
+
+use anyhow::Result;
+use aptos_processor_framework::{ChannelHandle, StreamSubscriberTrait};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::mpsc;
+
+/// How many batches can be waiting in the channel before we stop reading more.
+const CHANNEL_SIZE: usize = 10;
+
+/// Wraps another stream subscriber, recording the last version of every batch it
+/// receives in `latest_version` before passing the batch on.
+pub struct VersionTrackingStreamSubscriber {
+    pub inner: Box<dyn StreamSubscriberTrait>,
+    pub latest_version: Arc<AtomicU64>,
+}
+
+#[async_trait::async_trait]
+impl StreamSubscriberTrait for VersionTrackingStreamSubscriber {
+    async fn start(&self) -> Result<ChannelHandle> {
+        let mut inner_receiver = self.inner.start().await?.receiver;
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        let latest_version = self.latest_version.clone();
+        let join_handle = tokio::spawn(async move {
+            while let Some(transactions) = inner_receiver.recv().await {
+                if let Some(transaction) = transactions.last() {
+                    latest_version.fetch_max(transaction.version, Ordering::Relaxed);
+                }
+                if sender.send(transactions).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(ChannelHandle {
+            join_handle,
+            receiver,
+        })
+    }
+}
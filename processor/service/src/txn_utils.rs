@@ -0,0 +1,206 @@
+//! Helpers for classifying txns from the txn stream. Plenty of the fields in the
+//! protos are optional and many txns aren't user txns at all, so these never panic,
+//! they just return `None` / `false` when something isn't there.
+
+use aptos_processor_framework::indexer_protos::transaction::v1::{
+    transaction::TxnData, transaction_payload::Payload, EntryFunctionId, EntryFunctionPayload,
+    Transaction, UserTransaction, UserTransactionRequest,
+};
+
+/// What kind of txn this is, based on its `txn_data`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransactionKind {
+    Genesis,
+    BlockMetadata,
+    StateCheckpoint,
+    User,
+    /// The txn has no `txn_data`, so we can't tell.
+    Unknown,
+}
+
+pub fn get_transaction_kind(transaction: &Transaction) -> TransactionKind {
+    match transaction.txn_data.as_ref() {
+        Some(TxnData::Genesis(_)) => TransactionKind::Genesis,
+        Some(TxnData::BlockMetadata(_)) => TransactionKind::BlockMetadata,
+        Some(TxnData::StateCheckpoint(_)) => TransactionKind::StateCheckpoint,
+        Some(TxnData::User(_)) => TransactionKind::User,
+        None => TransactionKind::Unknown,
+    }
+}
+
+/// Whether the txn was executed successfully. A txn without `info` is treated as
+/// unsuccessful, since we can't tell what it changed.
+pub fn is_successful(transaction: &Transaction) -> bool {
+    transaction.info.as_ref().is_some_and(|info| info.success)
+}
+
+pub fn get_user_transaction(transaction: &Transaction) -> Option<&UserTransaction> {
+    match transaction.txn_data.as_ref()? {
+        TxnData::User(user_transaction) => Some(user_transaction),
+        _ => None,
+    }
+}
+
+pub fn get_user_transaction_request(transaction: &Transaction) -> Option<&UserTransactionRequest> {
+    get_user_transaction(transaction)?.request.as_ref()
+}
+
+/// Get the entry function payload if this is a user txn that called an entry
+/// function, as opposed to e.g. running a script.
+pub fn get_entry_function_payload(transaction: &Transaction) -> Option<&EntryFunctionPayload> {
+    let payload = get_user_transaction_request(transaction)?
+        .payload
+        .as_ref()?
+        .payload
+        .as_ref()?;
+    match payload {
+        Payload::EntryFunctionPayload(entry_function_payload) => Some(entry_function_payload),
+        _ => None,
+    }
+}
+
+pub fn get_entry_function_id(transaction: &Transaction) -> Option<&EntryFunctionId> {
+    get_entry_function_payload(transaction)?.function.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aptos_processor_framework::indexer_protos::transaction::v1::{
+        BlockMetadataTransaction, GenesisTransaction, MoveModuleId, ScriptPayload,
+        StateCheckpointTransaction, TransactionInfo, TransactionPayload,
+    };
+
+    fn build_transaction(txn_data: Option<TxnData>, success: bool) -> Transaction {
+        Transaction {
+            version: 1,
+            info: Some(TransactionInfo {
+                success,
+                ..Default::default()
+            }),
+            txn_data,
+            ..Default::default()
+        }
+    }
+
+    fn build_user_transaction(payload: Option<Payload>, success: bool) -> Transaction {
+        build_transaction(
+            Some(TxnData::User(UserTransaction {
+                request: Some(UserTransactionRequest {
+                    payload: Some(TransactionPayload {
+                        payload,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+            success,
+        )
+    }
+
+    fn build_draw_payload() -> Payload {
+        Payload::EntryFunctionPayload(EntryFunctionPayload {
+            function: Some(EntryFunctionId {
+                module: Some(MoveModuleId {
+                    address: "0x1".to_string(),
+                    name: "canvas_token".to_string(),
+                }),
+                name: "draw".to_string(),
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_genesis() {
+        let transaction =
+            build_transaction(Some(TxnData::Genesis(GenesisTransaction::default())), true);
+        assert_eq!(get_transaction_kind(&transaction), TransactionKind::Genesis);
+        assert!(get_user_transaction(&transaction).is_none());
+        assert!(get_entry_function_id(&transaction).is_none());
+    }
+
+    #[test]
+    fn test_block_metadata() {
+        let transaction = build_transaction(
+            Some(TxnData::BlockMetadata(BlockMetadataTransaction::default())),
+            true,
+        );
+        assert_eq!(
+            get_transaction_kind(&transaction),
+            TransactionKind::BlockMetadata
+        );
+        assert!(get_user_transaction(&transaction).is_none());
+        assert!(get_entry_function_id(&transaction).is_none());
+    }
+
+    #[test]
+    fn test_state_checkpoint() {
+        let transaction = build_transaction(
+            Some(TxnData::StateCheckpoint(
+                StateCheckpointTransaction::default(),
+            )),
+            true,
+        );
+        assert_eq!(
+            get_transaction_kind(&transaction),
+            TransactionKind::StateCheckpoint
+        );
+        assert!(get_user_transaction(&transaction).is_none());
+        assert!(get_entry_function_id(&transaction).is_none());
+    }
+
+    #[test]
+    fn test_no_txn_data() {
+        let transaction = build_transaction(None, true);
+        assert_eq!(get_transaction_kind(&transaction), TransactionKind::Unknown);
+        assert!(get_user_transaction(&transaction).is_none());
+        assert!(get_entry_function_id(&transaction).is_none());
+    }
+
+    #[test]
+    fn test_user_entry_function() {
+        let transaction = build_user_transaction(Some(build_draw_payload()), true);
+        assert_eq!(get_transaction_kind(&transaction), TransactionKind::User);
+        assert!(is_successful(&transaction));
+        assert_eq!(get_entry_function_id(&transaction).unwrap().name, "draw");
+    }
+
+    #[test]
+    fn test_user_script() {
+        let transaction =
+            build_user_transaction(Some(Payload::ScriptPayload(ScriptPayload::default())), true);
+        assert_eq!(get_transaction_kind(&transaction), TransactionKind::User);
+        assert!(get_user_transaction_request(&transaction).is_some());
+        assert!(get_entry_function_payload(&transaction).is_none());
+    }
+
+    #[test]
+    fn test_user_without_request_or_payload() {
+        let no_request = build_transaction(Some(TxnData::User(UserTransaction::default())), true);
+        assert!(get_user_transaction_request(&no_request).is_none());
+        assert!(get_entry_function_id(&no_request).is_none());
+
+        let no_payload = build_user_transaction(None, true);
+        assert!(get_user_transaction_request(&no_payload).is_some());
+        assert!(get_entry_function_id(&no_payload).is_none());
+    }
+
+    #[test]
+    fn test_failed() {
+        let transaction = build_user_transaction(Some(build_draw_payload()), false);
+        assert!(!is_successful(&transaction));
+        // We can still classify failed txns, it's up to the caller to skip them.
+        assert!(get_entry_function_id(&transaction).is_some());
+    }
+
+    #[test]
+    fn test_no_info() {
+        let transaction = Transaction {
+            info: None,
+            ..build_user_transaction(Some(build_draw_payload()), true)
+        };
+        assert!(!is_successful(&transaction));
+    }
+}